@@ -0,0 +1,549 @@
+//! Helpers for the APT update and repository handling shared by all products.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Error, bail, format_err};
+use serde::Serialize;
+
+use proxmox_apt_api_types::{
+    APTRepository, APTRepositoryFile, APTRepositoryHandle, APTStandardRepository, APTUpdateInfo,
+};
+
+/// Marker file created by package maintainer scripts if a reboot is needed to apply an update.
+const REBOOT_REQUIRED_FILE: &str = "/run/reboot-required";
+
+/// Name prefixes of kernel packages.
+const KERNEL_PACKAGE_PREFIXES: &[&str] = &["proxmox-kernel-", "pve-kernel-", "linux-image-"];
+
+/// Names of packages, which besides the kernel, only become active after a reboot.
+const REBOOT_REQUIRED_PACKAGES: &[&str] = &["amd64-microcode", "intel-microcode"];
+
+/// Returns whether `update` is a kernel update.
+pub fn is_kernel_update(update: &APTUpdateInfo) -> bool {
+    KERNEL_PACKAGE_PREFIXES
+        .iter()
+        .any(|prefix| update.package.starts_with(prefix))
+}
+
+/// Returns whether `update` comes from a security repository.
+pub fn is_security_update(update: &APTUpdateInfo) -> bool {
+    update.origin.to_lowercase().contains("security")
+}
+
+/// Returns whether installing `update` requires a reboot to become active.
+pub fn update_requires_reboot(update: &APTUpdateInfo) -> bool {
+    is_kernel_update(update) || REBOOT_REQUIRED_PACKAGES.contains(&update.package.as_str())
+}
+
+/// Returns whether a previous upgrade left the reboot-required marker file behind.
+pub fn reboot_pending() -> bool {
+    Path::new(REBOOT_REQUIRED_FILE).exists()
+}
+
+/// Decide whether a notification about the available `updates` should be sent.
+///
+/// By default every update is worth a notification. If `reboot_required_only` is set, only notify
+/// if one of the `updates` requires a reboot or a reboot is already pending.
+pub fn should_notify_updates(
+    updates: &[&APTUpdateInfo],
+    reboot_required_only: bool,
+    reboot_pending: bool,
+) -> bool {
+    !reboot_required_only
+        || reboot_pending
+        || updates.iter().any(|update| update_requires_reboot(update))
+}
+
+/// Result of notifying about available updates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct UpdateNotificationResult {
+    /// The number of updates included in the notification.
+    pub updates: usize,
+    /// Whether a notification was sent and routed to at least one target.
+    pub notified: bool,
+}
+
+/// Notify about the available `updates` using `send`, unless the notification is suppressed.
+///
+/// See [`should_notify_updates`] for when a notification is suppressed.
+pub fn notify_updates(
+    updates: &[&APTUpdateInfo],
+    reboot_required_only: bool,
+    reboot_pending: bool,
+    send: impl FnOnce(&[&APTUpdateInfo]) -> Result<UpdateNotificationResult, Error>,
+) -> Result<UpdateNotificationResult, Error> {
+    if !should_notify_updates(updates, reboot_required_only, reboot_pending) {
+        return Ok(UpdateNotificationResult {
+            updates: updates.len(),
+            notified: false,
+        });
+    }
+
+    send(updates)
+}
+
+/// Available updates, bucketed by their importance.
+#[derive(Default, Serialize)]
+pub struct ClassifiedUpdates {
+    /// Updates from a security repository.
+    pub security: Vec<APTUpdateInfo>,
+    /// Kernel updates, which are not security updates.
+    pub kernel: Vec<APTUpdateInfo>,
+    /// All other updates.
+    pub regular: Vec<APTUpdateInfo>,
+}
+
+/// Classify `updates` into security, kernel and regular updates.
+///
+/// The classification is based on heuristics on the origin and the package name. A security
+/// update of a kernel package is classified as security update.
+pub fn classify_updates(updates: impl IntoIterator<Item = APTUpdateInfo>) -> ClassifiedUpdates {
+    let mut classified = ClassifiedUpdates::default();
+
+    for update in updates {
+        if is_security_update(&update) {
+            classified.security.push(update);
+        } else if is_kernel_update(&update) {
+            classified.kernel.push(update);
+        } else {
+            classified.regular.push(update);
+        }
+    }
+
+    classified
+}
+
+/// A single available update, flagged whether it is a security update.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ClassifiedUpdate {
+    /// The name of the package.
+    pub package: String,
+    /// The currently installed version.
+    pub old_version: String,
+    /// The version the package would be updated to.
+    pub version: String,
+    /// The origin of the repository the update comes from.
+    pub origin: String,
+    /// Whether the update comes from a security repository, see [`is_security_update`].
+    pub security: bool,
+}
+
+impl From<APTUpdateInfo> for ClassifiedUpdate {
+    fn from(update: APTUpdateInfo) -> Self {
+        Self {
+            security: is_security_update(&update),
+            package: update.package,
+            old_version: update.old_version,
+            version: update.version,
+            origin: update.origin,
+        }
+    }
+}
+
+/// Returns the number of bytes APT still needs to download, from the output of
+/// `apt-get --print-uris`.
+///
+/// APT only prints the packages which are not completely downloaded yet, one per line with the
+/// quoted URI, the file name, the size and the checksum.
+fn parse_download_size(output: &str) -> Result<u64, Error> {
+    let mut size = 0;
+
+    for line in output.lines().filter(|line| line.starts_with('\'')) {
+        let package_size = line
+            .split_whitespace()
+            .nth(2)
+            .and_then(|size| size.parse::<u64>().ok())
+            .ok_or_else(|| format_err!("unexpected output of apt-get: '{line}'"))?;
+        size += package_size;
+    }
+
+    Ok(size)
+}
+
+/// Returns the number of bytes which still need to be downloaded to install the `updates`.
+///
+/// APT is asked which packages it needs to download for installing the updates, so packages
+/// which are already downloaded are not counted, while new dependencies are.
+///
+/// Fails if APT does not know one of the updates, since that means that the package cache does
+/// not match them.
+pub fn download_size(updates: &[APTUpdateInfo]) -> Result<u64, Error> {
+    if updates.is_empty() {
+        return Ok(0);
+    }
+
+    let output = Command::new("apt-get")
+        .args(["install", "--print-uris", "--yes", "-qq"])
+        .args(
+            updates
+                .iter()
+                .map(|update| format!("{}={}", update.package, update.version)),
+        )
+        .output()
+        .map_err(|err| format_err!("failed to run apt-get: {err}"))?;
+
+    if !output.status.success() {
+        bail!(
+            "failed to get the download size, the package cache might not be up to date - run \
+            'apt update' first: {}",
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+
+    parse_download_size(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Host of the Proxmox enterprise repositories, which are the production repositories.
+const ENTERPRISE_REPOSITORY_HOST: &str = "enterprise.proxmox.com";
+
+/// The kind of a [`RepositoryFinding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RepositoryFindingKind {
+    /// An enabled repository is accessed via plain `http://` without a `signed-by` key.
+    InsecureTransport,
+    /// An enabled repository is marked as `trusted=yes`, which disables signature checks.
+    Trusted,
+    /// A production repository is configured, but disabled.
+    DisabledProduction,
+}
+
+/// A potential security issue of a configured repository.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RepositoryFinding {
+    /// The path of the file the repository is configured in.
+    pub path: String,
+    /// The index of the repository within its file.
+    pub index: usize,
+    /// What was found.
+    pub kind: RepositoryFindingKind,
+    /// A human readable description of the finding.
+    pub message: String,
+}
+
+/// Returns the values of the option `key` of `repo`, ignoring the case of the key.
+fn repository_option<'a>(repo: &'a APTRepository, key: &str) -> Option<&'a [String]> {
+    repo.options
+        .iter()
+        .find(|option| option.key.eq_ignore_ascii_case(key))
+        .map(|option| option.values.as_slice())
+}
+
+/// Check a single repository for potential security issues.
+fn audit_repository(repo: &APTRepository) -> Vec<(RepositoryFindingKind, String)> {
+    let mut findings = Vec::new();
+
+    if !repo.enabled {
+        if repo
+            .uris
+            .iter()
+            .any(|uri| uri.contains(ENTERPRISE_REPOSITORY_HOST))
+        {
+            findings.push((
+                RepositoryFindingKind::DisabledProduction,
+                "production repository is configured, but disabled".to_string(),
+            ));
+        }
+        return findings;
+    }
+
+    if repository_option(repo, "signed-by").is_none() {
+        for uri in repo.uris.iter().filter(|uri| uri.starts_with("http://")) {
+            findings.push((
+                RepositoryFindingKind::InsecureTransport,
+                format!("repository '{uri}' uses plain HTTP without a signed-by key"),
+            ));
+        }
+    }
+
+    if repository_option(repo, "trusted").is_some_and(|values| values.iter().any(|v| v == "yes")) {
+        findings.push((
+            RepositoryFindingKind::Trusted,
+            "repository is marked as trusted, its signatures are not checked".to_string(),
+        ));
+    }
+
+    findings
+}
+
+/// Audit the repositories configured in `files` for potential security issues.
+///
+/// This reports enabled repositories accessed via plain `http://` without a `signed-by` key,
+/// enabled repositories marked as `trusted=yes` and disabled production repositories. Keys in
+/// the global APT keyring are not considered, as they apply to every repository.
+pub fn audit_repositories(files: &[APTRepositoryFile]) -> Vec<RepositoryFinding> {
+    let mut findings = Vec::new();
+
+    for file in files {
+        for (index, repo) in file.repositories.iter().enumerate() {
+            for (kind, message) in audit_repository(repo) {
+                findings.push(RepositoryFinding {
+                    path: file.path.clone().unwrap_or_default(),
+                    index,
+                    kind,
+                    message,
+                });
+            }
+        }
+    }
+
+    findings
+}
+
+/// Whether and where a standard repository is configured.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StandardRepoStatus {
+    /// The handle of the standard repository.
+    pub handle: APTRepositoryHandle,
+    /// The human readable name of the standard repository.
+    pub name: String,
+    /// Whether the repository is configured at all.
+    pub configured: bool,
+    /// Whether the repository is configured and enabled.
+    pub enabled: bool,
+    /// The files the repository is configured in.
+    pub paths: Vec<String>,
+}
+
+/// Determine the status of the `standard_repos` of a product, as returned by
+/// [`proxmox_apt::list_repositories`], and look up the `files` they are configured in.
+///
+/// `is_referenced` decides whether a configured repository is the one referenced by a handle.
+pub fn standard_repository_status(
+    standard_repos: &[APTStandardRepository],
+    files: &[APTRepositoryFile],
+    is_referenced: impl Fn(&APTRepository, APTRepositoryHandle) -> bool,
+) -> Vec<StandardRepoStatus> {
+    standard_repos
+        .iter()
+        .map(|standard_repo| {
+            let paths = files
+                .iter()
+                .filter(|file| {
+                    file.repositories
+                        .iter()
+                        .any(|repo| is_referenced(repo, standard_repo.handle))
+                })
+                .filter_map(|file| file.path.clone())
+                .collect();
+
+            StandardRepoStatus {
+                handle: standard_repo.handle,
+                name: standard_repo.name.clone(),
+                configured: standard_repo.status.is_some(),
+                enabled: standard_repo.status.unwrap_or(false),
+                paths,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(package: &str) -> APTUpdateInfo {
+        update_from(package, "Proxmox")
+    }
+
+    fn update_from(package: &str, origin: &str) -> APTUpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "Package": package,
+            "Title": package,
+            "Arch": "amd64",
+            "Description": "",
+            "Version": "2.0",
+            "OldVersion": "1.0",
+            "Origin": origin,
+            "Priority": "optional",
+            "Section": "admin",
+        }))
+        .expect("valid update info")
+    }
+
+    #[test]
+    fn reboot_required_only_gating() {
+        let kernel = update("proxmox-kernel-6.8.12-4-pve-signed");
+        let regular = update("vim");
+
+        assert!(update_requires_reboot(&kernel));
+        assert!(!update_requires_reboot(&regular));
+
+        assert!(should_notify_updates(&[&regular], false, false));
+        assert!(!should_notify_updates(&[&regular], true, false));
+        assert!(should_notify_updates(&[&regular], true, true));
+        assert!(should_notify_updates(&[&regular, &kernel], true, false));
+    }
+
+    #[test]
+    fn notification_result() {
+        let kernel = update("proxmox-kernel-6.8.12-4-pve-signed");
+        let regular = update("vim");
+
+        let send = |updates: &[&APTUpdateInfo]| {
+            Ok(UpdateNotificationResult {
+                updates: updates.len(),
+                notified: true,
+            })
+        };
+
+        assert_eq!(
+            notify_updates(&[&regular, &kernel], false, false, send).unwrap(),
+            UpdateNotificationResult {
+                updates: 2,
+                notified: true,
+            }
+        );
+        assert_eq!(
+            notify_updates(&[&regular], true, false, send).unwrap(),
+            UpdateNotificationResult {
+                updates: 1,
+                notified: false,
+            }
+        );
+        assert!(
+            notify_updates(&[&regular], false, false, |_| anyhow::bail!(
+                "failed to send"
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn classify() {
+        let classified = classify_updates([
+            update("vim"),
+            update("proxmox-kernel-6.8.12-4-pve-signed"),
+            update_from("libssl3", "Debian-Security"),
+        ]);
+
+        assert_eq!(classified.security.len(), 1);
+        assert_eq!(classified.security[0].package, "libssl3");
+        assert_eq!(classified.kernel.len(), 1);
+        assert_eq!(
+            classified.kernel[0].package,
+            "proxmox-kernel-6.8.12-4-pve-signed"
+        );
+        assert_eq!(classified.regular.len(), 1);
+        assert_eq!(classified.regular[0].package, "vim");
+    }
+
+    #[test]
+    fn update_download_size() {
+        assert_eq!(parse_download_size("").unwrap(), 0);
+        assert_eq!(
+            parse_download_size(
+                "'http://deb.debian.org/debian/pool/main/v/vim/vim_2%3a9.1_amd64.deb' \
+                 vim_2%3a9.1_amd64.deb 1500 SHA256:0123\n\
+                 'http://deb.debian.org/debian/pool/main/o/openssl/libssl3_3.0_amd64.deb' \
+                 libssl3_3.0_amd64.deb 2000000 SHA256:4567\n"
+            )
+            .unwrap(),
+            2_001_500
+        );
+
+        let err = parse_download_size("'http://example.com/vim.deb' vim.deb").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unexpected output of apt-get: ''http://example.com/vim.deb' vim.deb'"
+        );
+
+        assert_eq!(download_size(&[]).unwrap(), 0);
+    }
+
+    #[test]
+    fn classified_update() {
+        assert_eq!(
+            ClassifiedUpdate::from(update_from("libssl3", "Debian-Security")),
+            ClassifiedUpdate {
+                package: "libssl3".to_string(),
+                old_version: "1.0".to_string(),
+                version: "2.0".to_string(),
+                origin: "Debian-Security".to_string(),
+                security: true,
+            }
+        );
+        assert!(!ClassifiedUpdate::from(update("vim")).security);
+    }
+
+    #[test]
+    fn repository_audit() {
+        use proxmox_apt::repositories::APTRepositoryFileImpl;
+        use proxmox_apt_api_types::APTRepositoryFileType;
+
+        let mut file = APTRepositoryFile::with_content(
+            "deb http://download.proxmox.com/debian/pve bookworm pve-no-subscription\n\
+             deb [signed-by=/etc/apt/keyrings/ex.gpg] http://example.com/debian bookworm main\n\
+             deb [trusted=yes] https://example.com/debian bookworm main\n\
+             # deb https://enterprise.proxmox.com/debian/pve bookworm pve-enterprise\n\
+             deb https://enterprise.proxmox.com/debian/ceph-squid bookworm enterprise\n"
+                .to_string(),
+            APTRepositoryFileType::List,
+        );
+        file.parse().unwrap();
+
+        let findings: Vec<(usize, RepositoryFindingKind)> = audit_repositories(&[file])
+            .into_iter()
+            .map(|finding| (finding.index, finding.kind))
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                (0, RepositoryFindingKind::InsecureTransport),
+                (2, RepositoryFindingKind::Trusted),
+                (3, RepositoryFindingKind::DisabledProduction),
+            ]
+        );
+    }
+
+    #[test]
+    fn standard_repositories() {
+        use proxmox_apt::repositories::APTRepositoryFileImpl;
+        use proxmox_apt_api_types::APTRepositoryFileType;
+
+        let mut file = APTRepositoryFile::with_content(
+            "# deb https://enterprise.proxmox.com/debian/pve bookworm pve-enterprise\n\
+             deb http://download.proxmox.com/debian/pve bookworm pve-no-subscription\n"
+                .to_string(),
+            APTRepositoryFileType::List,
+        );
+        file.parse().unwrap();
+        file.path = Some("/etc/apt/sources.list".to_string());
+
+        let standard_repo = |handle, status| APTStandardRepository {
+            handle,
+            status,
+            name: format!("{handle:?}"),
+            description: String::new(),
+        };
+
+        let status = standard_repository_status(
+            &[
+                standard_repo(APTRepositoryHandle::Enterprise, Some(false)),
+                standard_repo(APTRepositoryHandle::NoSubscription, Some(true)),
+                standard_repo(APTRepositoryHandle::Test, None),
+            ],
+            &[file],
+            |repo, handle| {
+                let component = match handle {
+                    APTRepositoryHandle::Enterprise => "pve-enterprise",
+                    APTRepositoryHandle::NoSubscription => "pve-no-subscription",
+                    _ => "pvetest",
+                };
+                repo.components.iter().any(|c| c == component)
+            },
+        );
+
+        let summary: Vec<(bool, bool, usize)> = status
+            .iter()
+            .map(|status| (status.configured, status.enabled, status.paths.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            [(true, false, 1), (true, true, 1), (false, false, 0)]
+        );
+        assert_eq!(status[0].paths, ["/etc/apt/sources.list"]);
+    }
+}
@@ -4,14 +4,24 @@ pub mod proxmox_rs_apt_repositories {
     //!
     //! APT repository information access.
 
+    use std::cell::Cell;
+
     use anyhow::Error;
 
+    use proxmox_apt::repositories::APTRepositoryImpl;
     use proxmox_apt_api_types::{
         APTChangeRepositoryOptions, APTGetChangelogOptions, APTRepositoriesResult,
         APTRepositoryHandle, APTUpdateInfo, APTUpdateOptions,
     };
     use proxmox_config_digest::ConfigDigest;
 
+    use crate::common::apt;
+
+    pub use crate::common::apt::{
+        ClassifiedUpdate, ClassifiedUpdates, RepositoryFinding, RepositoryFindingKind,
+        StandardRepoStatus, UpdateNotificationResult,
+    };
+
     /// Get information about configured repositories and standard repositories for `product`.
     ///
     /// See [`proxmox_apt::list_repositories`].
@@ -20,6 +30,34 @@ pub mod proxmox_rs_apt_repositories {
         proxmox_apt::list_repositories(product)
     }
 
+    /// Audit the configured repositories for potential security issues.
+    ///
+    /// Returns a list of findings, each with the `path` of the file and the `index` of the
+    /// repository within it, and a machine readable `kind`. No files are modified.
+    ///
+    /// See [`apt::audit_repositories`].
+    #[export]
+    pub fn audit_repositories(product: &str) -> Result<Vec<RepositoryFinding>, Error> {
+        let repositories = proxmox_apt::list_repositories(product)?;
+        Ok(apt::audit_repositories(&repositories.files))
+    }
+
+    /// Check for each standard repository of `product` whether it is configured, enabled and in
+    /// which files.
+    ///
+    /// See [`apt::standard_repository_status`].
+    #[export]
+    pub fn check_standard_repositories(product: &str) -> Result<Vec<StandardRepoStatus>, Error> {
+        let repositories = proxmox_apt::list_repositories(product)?;
+        let suite = proxmox_apt::repositories::get_current_release_codename()?.to_string();
+
+        Ok(apt::standard_repository_status(
+            &repositories.standard_repos,
+            &repositories.files,
+            |repo, handle| repo.is_referenced_repository(handle, product, &suite),
+        ))
+    }
+
     /// Add the repository identified by the `handle` and `product`.
     /// If the repository is already configured, it will be set to enabled.
     ///
@@ -50,6 +88,42 @@ pub mod proxmox_rs_apt_repositories {
         proxmox_apt::change_repository(path, index, &options, digest)
     }
 
+    /// Enable the repository at `index` in the file `path`.
+    ///
+    /// Other repositories in the file are left untouched. The `digest` parameter asserts that the
+    /// configuration has not been modified.
+    ///
+    /// See [`proxmox_apt::change_repository`].
+    #[export]
+    pub fn enable_repository(
+        path: &str,
+        index: usize,
+        digest: Option<ConfigDigest>,
+    ) -> Result<(), Error> {
+        let options = APTChangeRepositoryOptions {
+            enabled: Some(true),
+        };
+        proxmox_apt::change_repository(path, index, &options, digest)
+    }
+
+    /// Disable the repository at `index` in the file `path`.
+    ///
+    /// Other repositories in the file are left untouched. The `digest` parameter asserts that the
+    /// configuration has not been modified.
+    ///
+    /// See [`proxmox_apt::change_repository`].
+    #[export]
+    pub fn disable_repository(
+        path: &str,
+        index: usize,
+        digest: Option<ConfigDigest>,
+    ) -> Result<(), Error> {
+        let options = APTChangeRepositoryOptions {
+            enabled: Some(false),
+        };
+        proxmox_apt::change_repository(path, index, &options, digest)
+    }
+
     /// Retrieve the changelog of the specified package.
     ///
     /// See [`proxmox_apt::get_changelog`].
@@ -68,22 +142,72 @@ pub mod proxmox_rs_apt_repositories {
         proxmox_apt::list_available_apt_update(apt_state_file)
     }
 
+    /// List available APT updates, each flagged whether it is a security update.
+    ///
+    /// Automatically updates an expired package cache, like [`list_available_apt_update`].
+    ///
+    /// See [`apt::ClassifiedUpdate`].
+    #[export]
+    pub fn list_updates(apt_state_file: &str) -> Result<Vec<ClassifiedUpdate>, Error> {
+        Ok(proxmox_apt::list_available_apt_update(apt_state_file)?
+            .into_iter()
+            .map(ClassifiedUpdate::from)
+            .collect())
+    }
+
+    /// Get the number of bytes which still need to be downloaded to install all available APT
+    /// updates, as reported by APT. Already downloaded packages are not counted.
+    ///
+    /// Fails if the package cache is not up to date, in which case `apt update` has to be run
+    /// first.
+    ///
+    /// See [`apt::download_size`].
+    #[export]
+    pub fn pending_update_size(apt_state_file: &str) -> Result<u64, Error> {
+        apt::download_size(&proxmox_apt::list_available_apt_update(apt_state_file)?)
+    }
+
+    /// Classify the given updates into security, kernel and regular updates.
+    ///
+    /// See [`apt::classify_updates`].
+    #[export]
+    pub fn classify_updates(updates: Vec<APTUpdateInfo>) -> ClassifiedUpdates {
+        apt::classify_updates(updates)
+    }
+
     /// Update the APT database
     ///
     /// You should update the APT proxy configuration before running this.
     ///
+    /// If `notify_reboot_required_only` is set, a notification about new updates is only sent if
+    /// one of them requires a reboot, or if a reboot is already pending.
+    ///
+    /// Returns the number of new updates and whether a notification about them was sent.
+    ///
     /// See [`proxmox_apt::update_database`].
     #[export]
-    pub fn update_database(apt_state_file: &str, options: APTUpdateOptions) -> Result<(), Error> {
+    pub fn update_database(
+        apt_state_file: &str,
+        options: APTUpdateOptions,
+        notify_reboot_required_only: Option<bool>,
+    ) -> Result<UpdateNotificationResult, Error> {
+        let result = Cell::new(UpdateNotificationResult::default());
+
         proxmox_apt::update_database(
             apt_state_file,
             &options,
             |updates: &[&APTUpdateInfo]| -> Result<(), Error> {
-                // fixme: howto send notifgications?
-                crate::send_updates_available(updates)?;
+                result.set(apt::notify_updates(
+                    updates,
+                    notify_reboot_required_only.unwrap_or(false),
+                    apt::reboot_pending(),
+                    crate::send_updates_available,
+                )?);
                 Ok(())
             },
-        )
+        )?;
+
+        Ok(result.get())
     }
 
     /// Get package information for a list of important product packages.
@@ -9,6 +9,8 @@ pub mod proxmox_rs_calendar_event {
     use anyhow::Error;
     use perlmod::Value;
 
+    use crate::common::calendar_event;
+
     perlmod::declare_magic!(Box<CalendarEvent> : &CalendarEvent as "Proxmox::RS::CalendarEvent");
 
     /// Wrapper for [`proxmox_time::CalendarEvent`].
@@ -33,4 +35,36 @@ pub mod proxmox_rs_calendar_event {
     ) -> Result<Option<i64>, Error> {
         this.0.compute_next_event(last)
     }
+
+    /// Computes up to `count` timestamps at which `schedule` triggers after `after`, e.g. to
+    /// preview the next runs of a job.
+    ///
+    /// See [`calendar_event::compute_next_events`].
+    #[export]
+    pub fn compute_next_events(
+        schedule: &str,
+        after: i64,
+        count: usize,
+    ) -> Result<Vec<i64>, Error> {
+        calendar_event::compute_next_events(schedule, after, count)
+    }
+
+    /// Parse the calendar event `schedule` and return it in a normalized form, so users can
+    /// confirm what was understood, e.g. `mon..fri 8:00` becomes `mon,tue,wed,thu,fri 08:00:00`.
+    ///
+    /// See [`calendar_event::describe`].
+    #[export]
+    pub fn describe_calendar_event(schedule: &str) -> Result<String, Error> {
+        calendar_event::describe(schedule)
+    }
+
+    /// Computes the next timestamp after `last` at which `schedule` triggers, interpreting the
+    /// schedule in the IANA time zone `tz`, e.g. `Europe/Vienna`. Returns a UTC epoch, fails if
+    /// the schedule does not trigger anymore.
+    ///
+    /// See [`calendar_event::compute_next_in_tz`].
+    #[export]
+    pub fn compute_next_in_tz(schedule: &str, last: i64, tz: &str) -> Result<i64, Error> {
+        calendar_event::compute_next_in_tz(schedule, last, tz)
+    }
 }
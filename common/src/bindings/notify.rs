@@ -9,8 +9,8 @@ pub mod proxmox_rs_notify {
     //! This package provides `STORABLE_freeze` and `STORABLE_attach` subs for `dclone` support,
     //! since this object will be put into `PVE::Cluster`'s `ccache`!
 
-    use std::collections::HashMap;
-    use std::sync::Mutex;
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::{Arc, Mutex};
 
     use anyhow::{Error, bail};
     use serde_json::Value as JSONValue;
@@ -38,11 +38,37 @@ pub mod proxmox_rs_notify {
     };
     use proxmox_notify::{Config, Notification, Severity, api};
 
+    use crate::common::notify::{self, RateLimiter};
+
+    pub use crate::common::notify::{
+        Fanout, JobStatus, LintFinding, LintKind, LintSeverity, NotificationRecord,
+        PrivateConfigEntry, PrivateConfigIntegrity, ProbeResult, ProbeStatus, RenderedTemplate,
+        SendOutcome,
+    };
+
     /// A notification catalog instance.
     ///
     /// See [`Config`].
     pub struct NotificationConfig {
-        config: Mutex<Config>,
+        pub(crate) config: Mutex<Config>,
+        /// The minimal interval of the matchers which have one, see
+        /// [`notify::MIN_INTERVAL_PROPERTY`].
+        pub(crate) min_intervals: Mutex<BTreeMap<String, u64>>,
+        /// Shared with the clones of this instance, so the matchers are rate limited for as long
+        /// as the parsed configuration is cached.
+        pub(crate) rate_limiter: Arc<Mutex<RateLimiter>>,
+    }
+
+    /// A matcher, with its minimal interval.
+    #[derive(serde::Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub struct Matcher {
+        /// The configuration of the matcher.
+        #[serde(flatten)]
+        pub config: MatcherConfig,
+        /// The minimal interval in seconds between two notifications routed by the matcher.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub min_interval: Option<u64>,
     }
 
     perlmod::declare_magic!(Box<NotificationConfig> : &NotificationConfig as "Proxmox::RS::Notify");
@@ -59,6 +85,8 @@ pub mod proxmox_rs_notify {
 
         let mut cloned = Box::new(NotificationConfig {
             config: Mutex::new(this.config.lock().unwrap().clone()),
+            min_intervals: Mutex::new(this.min_intervals.lock().unwrap().clone()),
+            rate_limiter: Arc::clone(&this.rate_limiter),
         });
         let value = Value::new_pointer::<NotificationConfig>(&mut *cloned);
         let _perl = Box::leak(cloned);
@@ -91,10 +119,13 @@ pub mod proxmox_rs_notify {
     ) -> Result<NotificationConfigInstance, Error> {
         let raw_config = std::str::from_utf8(raw_config)?;
         let raw_private_config = std::str::from_utf8(raw_private_config)?;
+        let (config, min_intervals) = notify::parse_config(raw_config, raw_private_config)?;
 
         Ok(perlmod::instantiate_magic!(&class, MAGIC => Box::new(
             NotificationConfig {
-                config: Mutex::new(Config::new(raw_config, raw_private_config)?)
+                config: Mutex::new(config),
+                min_intervals: Mutex::new(min_intervals),
+                rate_limiter: Arc::default(),
             }
         )))
     }
@@ -104,7 +135,12 @@ pub mod proxmox_rs_notify {
     pub fn write_config(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<(String, String), Error> {
-        Ok(this.config.lock().unwrap().write()?)
+        let (config, private_config) = this.config.lock().unwrap().write()?;
+        let min_intervals = this.min_intervals.lock().unwrap();
+        Ok((
+            notify::merge_min_intervals(&config, &min_intervals),
+            private_config,
+        ))
     }
 
     /// Method: Returns the SHA256 digest of the configuration.
@@ -116,11 +152,44 @@ pub mod proxmox_rs_notify {
         hex::encode(config.digest())
     }
 
+    /// Method: Check that the configuration and the private configuration match up.
+    ///
+    /// Reports endpoints which are missing their secret in the private configuration, as well
+    /// as private configuration entries without a matching endpoint.
+    ///
+    /// See [`notify::check_private_config_integrity`].
+    #[export]
+    pub fn check_private_config_integrity(
+        #[try_from_ref] this: &NotificationConfig,
+    ) -> Result<PrivateConfigIntegrity, Error> {
+        let config = this.config.lock().unwrap();
+        notify::check_private_config_integrity(&config)
+    }
+
+    /// Method: Check the configuration for problems.
+    ///
+    /// Returns a list of findings, each with a `severity`, a machine readable `kind` and the
+    /// name of the affected matcher or target as `entity`.
+    ///
+    /// See [`notify::lint_config`].
+    #[export(serialize_error)]
+    pub fn lint_config(
+        #[try_from_ref] this: &NotificationConfig,
+    ) -> Result<Vec<LintFinding>, HttpError> {
+        let config = this.config.lock().unwrap();
+        notify::lint_config(&config)
+    }
+
     /// Method: Send a notification from a template.
     ///
     /// This instantiates a [`Notification`] via [`from_template`](Notification::from_template())
     /// and sends it according to the configuration.
     ///
+    /// To break notification loops, notifications whose `hop-count` field exceeds `max_hops`
+    /// (default 5) are dropped, see [`notify::exceeds_hop_limit`]. Matchers with a
+    /// `min-interval` which already routed a notification within it do not route this one, see
+    /// [`send_rate_limited`].
+    ///
     /// See [`api::common::send`].
     #[export(serialize_error)]
     pub fn send(
@@ -129,30 +198,187 @@ pub mod proxmox_rs_notify {
         template_name: String,
         template_data: Option<JSONValue>,
         fields: Option<HashMap<String, String>>,
+        max_hops: Option<u32>,
     ) -> Result<(), HttpError> {
-        let config = this.config.lock().unwrap();
-        let notification = Notification::from_template(
+        send_rate_limited(
+            this,
             severity,
             template_name,
+            template_data,
+            fields,
+            max_hops,
+        )
+        .map(|_| ())
+    }
+
+    /// Method: Send a notification from a template, returning which matchers were throttled.
+    ///
+    /// This works like [`send`]. A matcher with a `min-interval` does not route the notification
+    /// if it already routed one within the last `min-interval` seconds, see [`RateLimiter`]. The
+    /// interval only starts once a notification was sent successfully, and it is kept for as
+    /// long as the parsed configuration is cached.
+    #[export(serialize_error)]
+    pub fn send_rate_limited(
+        #[try_from_ref] this: &NotificationConfig,
+        severity: Severity,
+        template_name: String,
+        template_data: Option<JSONValue>,
+        fields: Option<HashMap<String, String>>,
+        max_hops: Option<u32>,
+    ) -> Result<SendOutcome, HttpError> {
+        let fields = fields.unwrap_or_default();
+
+        if notify::exceeds_hop_limit(&fields, max_hops.unwrap_or(notify::DEFAULT_MAX_HOPS)) {
+            tracing::warn!("dropping notification '{template_name}', maximum hop count exceeded");
+            return Ok(SendOutcome::default());
+        }
+
+        let mut config = this.config.lock().unwrap();
+        let min_intervals = this.min_intervals.lock().unwrap();
+        let mut rate_limiter = this.rate_limiter.lock().unwrap();
+
+        let notification = Notification::from_template(
+            severity,
+            template_name.clone(),
             template_data.unwrap_or_default(),
-            fields.unwrap_or_default(),
+            fields,
+        );
+
+        let now = proxmox_time::epoch_i64();
+        let mut matchers = api::matcher::get_matchers(&config)?;
+        let throttled = rate_limiter.throttled(&matchers, &notification, &min_intervals, now);
+        matchers.retain(|matcher| !throttled.contains(&matcher.name));
+
+        let result = notify::with_matchers_disabled(&mut config, &throttled, |config| {
+            api::common::send(config, &notification)
+        })?;
+
+        if result.is_ok() {
+            rate_limiter.record(&matchers, &notification, &min_intervals, now);
+        }
+
+        notify::record_notification(NotificationRecord {
+            timestamp: now,
+            severity,
+            template: template_name,
+            targets: notify::matched_targets(&matchers, &notification),
+            throttled: throttled.clone(),
+            error: result.as_ref().err().map(|err| err.to_string()),
+        });
+
+        result.map(|_| SendOutcome { throttled })
+    }
+
+    /// Method: Send a notification about a finished job, e.g. a backup, replication or migration
+    /// job.
+    ///
+    /// The `status` (`ok`, `warning` or `error`) determines the severity of the notification,
+    /// the `details` are rendered as a table. Fails early if the `job-result` template is not
+    /// installed, instead of only logging the error when sending.
+    ///
+    /// See [`send`] and [`notify::job_result_template_data`].
+    #[export(serialize_error)]
+    pub fn send_job_result(
+        #[try_from_ref] this: &NotificationConfig,
+        job_type: &str,
+        status: JobStatus,
+        details: Option<BTreeMap<String, String>>,
+    ) -> Result<(), HttpError> {
+        let hostname = proxmox_sys::nodename();
+        let template_data = notify::job_result_template_data(
+            hostname,
+            job_type,
+            status,
+            &details.unwrap_or_default(),
         );
 
-        api::common::send(&config, &notification)
+        notify::check_template(notify::JOB_RESULT_TEMPLATE, &template_data)
+            .map_err(|err| api::http_err!(INTERNAL_SERVER_ERROR, "{err}"))?;
+
+        send(
+            this,
+            status.severity(),
+            notify::JOB_RESULT_TEMPLATE.to_string(),
+            Some(template_data),
+            Some(notify::job_result_fields(hostname, job_type, status)),
+            None,
+        )
+    }
+
+    /// Method: Render a notification template without sending it.
+    ///
+    /// Returns the rendered `subject`, `body_text` and, for mail targets or if `target_kind` is
+    /// not set, `body_html`.
+    ///
+    /// See [`notify::render_template`].
+    #[export]
+    pub fn render_template(
+        #[try_from_ref] _this: &NotificationConfig,
+        template_name: String,
+        template_data: Option<JSONValue>,
+        target_kind: Option<String>,
+    ) -> Result<RenderedTemplate, Error> {
+        notify::render_template(
+            &template_name,
+            &template_data.unwrap_or_default(),
+            target_kind.as_deref(),
+        )
+    }
+
+    /// Get up to `limit` of the notifications most recently sent by this process, the most
+    /// recent first.
+    ///
+    /// See [`notify::recent_notifications`].
+    #[export]
+    pub fn recent_notifications(limit: usize) -> Vec<NotificationRecord> {
+        notify::recent_notifications(limit)
+    }
+
+    /// Method: Estimate how many targets and recipients a notification would reach, without
+    /// sending it.
+    ///
+    /// Calendar matchers are evaluated against the current time.
+    ///
+    /// See [`notify::estimate_fanout`].
+    #[export(serialize_error)]
+    pub fn estimate_fanout(
+        #[try_from_ref] this: &NotificationConfig,
+        severity: Severity,
+        fields: Option<HashMap<String, String>>,
+    ) -> Result<Fanout, HttpError> {
+        let config = this.config.lock().unwrap();
+        let notification =
+            Notification::from_template(severity, "", JSONValue::Null, fields.unwrap_or_default());
+
+        let matchers = api::matcher::get_matchers(&config)?;
+        let recipients = notify::target_recipients(&config, crate::lookup_email_for_user)?;
+
+        Ok(notify::estimate_fanout(
+            &matchers,
+            &recipients,
+            &notification,
+        ))
     }
 
     /// Method: Get a list of all notification targets.
     ///
+    /// The targets are sorted by name.
+    ///
     /// See [`api::get_targets`].
     #[export(serialize_error)]
     pub fn get_targets(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<Vec<Target>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::get_targets(&config)
+        let mut targets = api::get_targets(&config)?;
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(targets)
     }
 
     /// Method: Test a target, see [`api::common::test_target`].
+    ///
+    /// If testing an SMTP target fails, the error is amended with its cause, like a mismatch of
+    /// the TLS mode or rejected credentials, see [`notify::explain_test_failure`].
     #[export(serialize_error)]
     pub fn test_target(
         #[try_from_ref] this: &NotificationConfig,
@@ -160,17 +386,38 @@ pub mod proxmox_rs_notify {
     ) -> Result<(), HttpError> {
         let config = this.config.lock().unwrap();
         api::common::test_target(&config, target)
+            .map_err(|err| notify::explain_test_failure(&config, target, err))
+    }
+
+    /// Method: Check whether a target is reachable, without sending a notification.
+    ///
+    /// Returns the status (`reachable`, `unreachable` or `not-supported`), the latency in
+    /// milliseconds and a message describing the outcome. HTTP based targets are probed via the
+    /// configured HTTP proxy.
+    ///
+    /// See [`notify::probe_target`].
+    #[export(serialize_error)]
+    pub fn probe_target(
+        #[try_from_ref] this: &NotificationConfig,
+        target: &str,
+    ) -> Result<ProbeResult, HttpError> {
+        let config = this.config.lock().unwrap();
+        notify::probe_target(&config, target, crate::http_proxy_config().as_ref())
     }
 
     /// Method: Get sendmail endpoints.
     ///
+    /// The endpoints are sorted by name.
+    ///
     /// See [`api::sendmail::get_endpoints`].
     #[export(serialize_error)]
     pub fn get_sendmail_endpoints(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<Vec<SendmailConfig>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::sendmail::get_endpoints(&config)
+        let mut endpoints = api::sendmail::get_endpoints(&config)?;
+        endpoints.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(endpoints)
     }
 
     /// Method: Get a single sendmail endpoint by id.
@@ -254,27 +501,91 @@ pub mod proxmox_rs_notify {
         )
     }
 
+    /// Method: Add a sendmail endpoint, or update it if it already exists.
+    ///
+    /// Properties which are not set are left unchanged when updating. The digest is not checked.
+    ///
+    /// See [`add_sendmail_endpoint`] and [`update_sendmail_endpoint`].
+    #[export(serialize_error)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsert_sendmail_endpoint(
+        #[try_from_ref] this: &NotificationConfig,
+        name: String,
+        mailto: Option<Vec<String>>,
+        mailto_user: Option<Vec<String>>,
+        from_address: Option<String>,
+        author: Option<String>,
+        comment: Option<String>,
+        disable: Option<bool>,
+    ) -> Result<(), HttpError> {
+        let mut config = this.config.lock().unwrap();
+        let exists = api::sendmail::get_endpoints(&config)?
+            .iter()
+            .any(|endpoint| endpoint.name == name);
+
+        if exists {
+            api::sendmail::update_endpoint(
+                &mut config,
+                &name,
+                SendmailConfigUpdater {
+                    mailto,
+                    mailto_user,
+                    from_address,
+                    author,
+                    comment,
+                    disable,
+                },
+                None,
+                None,
+            )
+        } else {
+            api::sendmail::add_endpoint(
+                &mut config,
+                SendmailConfig {
+                    name,
+                    mailto: mailto.unwrap_or_default(),
+                    mailto_user: mailto_user.unwrap_or_default(),
+                    from_address,
+                    author,
+                    comment,
+                    disable,
+                    filter: None,
+                    origin: None,
+                },
+            )
+        }
+    }
+
     /// Method: Delete a sendmail endpoint.
     ///
+    /// If `refuse_if_referenced` is set, this fails if the endpoint is still used by a matcher,
+    /// otherwise only a warning is logged, see [`notify::referencing_matchers`].
+    ///
     /// See [`api::sendmail::delete_endpoint`].
     #[export(serialize_error)]
     pub fn delete_sendmail_endpoint(
         #[try_from_ref] this: &NotificationConfig,
         name: &str,
+        refuse_if_referenced: Option<bool>,
     ) -> Result<(), HttpError> {
         let mut config = this.config.lock().unwrap();
+        notify::ensure_unreferenced(&config, name, refuse_if_referenced.unwrap_or(false))?;
         api::sendmail::delete_endpoint(&mut config, name)
     }
 
     /// Method: Get 'gotify' endpoints.
     ///
+    /// The endpoints are sorted by name.
+    ///
     /// See [`api::gotify::get_endpoints`].
     #[export(serialize_error)]
     pub fn get_gotify_endpoints(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<Vec<GotifyConfig>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::gotify::get_endpoints(&config)
+        let mut endpoints = api::gotify::get_endpoints(&config)?;
+        endpoints.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(endpoints)
     }
 
     /// Method: Get a single 'gotify' endpoint by id.
@@ -348,27 +659,84 @@ pub mod proxmox_rs_notify {
         )
     }
 
+    /// Method: Add a 'gotify' endpoint, or update it if it already exists.
+    ///
+    /// Properties which are not set are left unchanged when updating. The digest is not checked.
+    ///
+    /// See [`add_gotify_endpoint`] and [`update_gotify_endpoint`].
+    #[export(serialize_error)]
+    pub fn upsert_gotify_endpoint(
+        #[try_from_ref] this: &NotificationConfig,
+        name: String,
+        server: String,
+        token: String,
+        comment: Option<String>,
+        disable: Option<bool>,
+    ) -> Result<(), HttpError> {
+        let mut config = this.config.lock().unwrap();
+        let exists = api::gotify::get_endpoints(&config)?
+            .iter()
+            .any(|endpoint| endpoint.name == name);
+
+        if exists {
+            api::gotify::update_endpoint(
+                &mut config,
+                &name,
+                GotifyConfigUpdater {
+                    server: Some(server),
+                    comment,
+                    disable,
+                },
+                GotifyPrivateConfigUpdater { token: Some(token) },
+                None,
+                None,
+            )
+        } else {
+            api::gotify::add_endpoint(
+                &mut config,
+                GotifyConfig {
+                    name: name.clone(),
+                    server,
+                    comment,
+                    disable,
+                    filter: None,
+                    origin: None,
+                },
+                GotifyPrivateConfig { name, token },
+            )
+        }
+    }
+
     /// Method: Delete a 'gotify' endpoint.
     ///
+    /// If `refuse_if_referenced` is set, this fails if the endpoint is still used by a matcher,
+    /// otherwise only a warning is logged, see [`notify::referencing_matchers`].
+    ///
     /// See [`api::gotify::delete_gotify_endpoint`].
     #[export(serialize_error)]
     pub fn delete_gotify_endpoint(
         #[try_from_ref] this: &NotificationConfig,
         name: &str,
+        refuse_if_referenced: Option<bool>,
     ) -> Result<(), HttpError> {
         let mut config = this.config.lock().unwrap();
+        notify::ensure_unreferenced(&config, name, refuse_if_referenced.unwrap_or(false))?;
         api::gotify::delete_gotify_endpoint(&mut config, name)
     }
 
     /// Method: Get SMTP endpoints.
     ///
+    /// The endpoints are sorted by name.
+    ///
     /// See [`api::smtp::get_endpoints`].
     #[export(serialize_error)]
     pub fn get_smtp_endpoints(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<Vec<SmtpConfig>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::smtp::get_endpoints(&config)
+        let mut endpoints = api::smtp::get_endpoints(&config)?;
+        endpoints.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(endpoints)
     }
 
     /// Method: Get a single SMTP endpoint by id.
@@ -470,27 +838,105 @@ pub mod proxmox_rs_notify {
         )
     }
 
+    /// Method: Add an SMTP endpoint, or update it if it already exists.
+    ///
+    /// Properties which are not set are left unchanged when updating. The digest is not checked.
+    ///
+    /// See [`add_smtp_endpoint`] and [`update_smtp_endpoint`].
+    #[export(serialize_error)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn upsert_smtp_endpoint(
+        #[try_from_ref] this: &NotificationConfig,
+        name: String,
+        server: String,
+        port: Option<u16>,
+        mode: Option<SmtpMode>,
+        username: Option<String>,
+        password: Option<String>,
+        mailto: Option<Vec<String>>,
+        mailto_user: Option<Vec<String>>,
+        from_address: String,
+        author: Option<String>,
+        comment: Option<String>,
+        disable: Option<bool>,
+    ) -> Result<(), HttpError> {
+        let mut config = this.config.lock().unwrap();
+        let exists = api::smtp::get_endpoints(&config)?
+            .iter()
+            .any(|endpoint| endpoint.name == name);
+
+        if exists {
+            api::smtp::update_endpoint(
+                &mut config,
+                &name,
+                SmtpConfigUpdater {
+                    server: Some(server),
+                    port,
+                    mode,
+                    username,
+                    mailto,
+                    mailto_user,
+                    from_address: Some(from_address),
+                    author,
+                    comment,
+                    disable,
+                },
+                SmtpPrivateConfigUpdater { password },
+                None,
+                None,
+            )
+        } else {
+            api::smtp::add_endpoint(
+                &mut config,
+                SmtpConfig {
+                    name: name.clone(),
+                    server,
+                    port,
+                    mode,
+                    username,
+                    mailto: mailto.unwrap_or_default(),
+                    mailto_user: mailto_user.unwrap_or_default(),
+                    from_address,
+                    author,
+                    comment,
+                    disable,
+                    origin: None,
+                },
+                SmtpPrivateConfig { name, password },
+            )
+        }
+    }
+
     /// Method: Delete an SMTP endpoint.
     ///
+    /// If `refuse_if_referenced` is set, this fails if the endpoint is still used by a matcher,
+    /// otherwise only a warning is logged, see [`notify::referencing_matchers`].
+    ///
     /// See [`api::smtp::delete_endpoint`].
     #[export(serialize_error)]
     pub fn delete_smtp_endpoint(
         #[try_from_ref] this: &NotificationConfig,
         name: &str,
+        refuse_if_referenced: Option<bool>,
     ) -> Result<(), HttpError> {
         let mut config = this.config.lock().unwrap();
+        notify::ensure_unreferenced(&config, name, refuse_if_referenced.unwrap_or(false))?;
         api::smtp::delete_endpoint(&mut config, name)
     }
 
     /// Method: Get webhook endpoints.
     ///
+    /// The endpoints are sorted by name.
+    ///
     /// See [`api::webhook::get_endpoints`].
     #[export(serialize_error)]
     pub fn get_webhook_endpoints(
         #[try_from_ref] this: &NotificationConfig,
     ) -> Result<Vec<WebhookConfig>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::webhook::get_endpoints(&config)
+        let mut endpoints = api::webhook::get_endpoints(&config)?;
+        endpoints.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(endpoints)
     }
 
     /// Method: Get a single webhook endpoint by id.
@@ -514,6 +960,9 @@ pub mod proxmox_rs_notify {
         #[try_from_ref] this: &NotificationConfig,
         endpoint_config: WebhookConfig,
     ) -> Result<(), HttpError> {
+        notify::validate_webhook_config(&endpoint_config)
+            .map_err(|err| api::http_err!(BAD_REQUEST, "{err}"))?;
+
         let mut config = this.config.lock().unwrap();
         api::webhook::add_endpoint(&mut config, endpoint_config)
     }
@@ -533,7 +982,7 @@ pub mod proxmox_rs_notify {
         let mut config = this.config.lock().unwrap();
         let digest = decode_digest(digest)?;
 
-        api::webhook::update_endpoint(
+        update_webhook(
             &mut config,
             name,
             config_updater,
@@ -542,27 +991,111 @@ pub mod proxmox_rs_notify {
         )
     }
 
+    /// Update the webhook endpoint `name` in `config`, validating the resulting endpoint before
+    /// touching the actual config.
+    fn update_webhook(
+        config: &mut Config,
+        name: &str,
+        config_updater: WebhookConfigUpdater,
+        delete: Option<&[DeleteableWebhookProperty]>,
+        digest: Option<&[u8]>,
+    ) -> Result<(), HttpError> {
+        let mut updated = config.clone();
+        api::webhook::update_endpoint(&mut updated, name, config_updater, delete, digest)?;
+        notify::validate_webhook_config(&api::webhook::get_endpoint(&updated, name)?)
+            .map_err(|err| api::http_err!(BAD_REQUEST, "{err}"))?;
+
+        *config = updated;
+        Ok(())
+    }
+
+    /// Method: Validate a webhook endpoint configuration without saving it.
+    ///
+    /// This is also done when adding or updating a webhook endpoint.
+    ///
+    /// See [`notify::validate_webhook_config`].
+    #[export]
+    pub fn validate_webhook_config(
+        #[try_from_ref] _this: &NotificationConfig,
+        endpoint_config: WebhookConfig,
+    ) -> Result<(), Error> {
+        notify::validate_webhook_config(&endpoint_config)
+    }
+
+    /// Method: Add a webhook endpoint, or update it if it already exists.
+    ///
+    /// Properties which are not set are left unchanged when updating. The digest is not checked.
+    ///
+    /// See [`add_webhook_endpoint`] and [`update_webhook_endpoint`].
+    #[export(serialize_error)]
+    pub fn upsert_webhook_endpoint(
+        #[try_from_ref] this: &NotificationConfig,
+        endpoint_config: WebhookConfig,
+    ) -> Result<(), HttpError> {
+        let mut config = this.config.lock().unwrap();
+        let exists = api::webhook::get_endpoints(&config)?
+            .iter()
+            .any(|endpoint| endpoint.name == endpoint_config.name);
+
+        if !exists {
+            notify::validate_webhook_config(&endpoint_config)
+                .map_err(|err| api::http_err!(BAD_REQUEST, "{err}"))?;
+            return api::webhook::add_endpoint(&mut config, endpoint_config);
+        }
+
+        // the updater has the same properties as the config, except for the name
+        let config_updater = serde_json::to_value(&endpoint_config)
+            .and_then(serde_json::from_value::<WebhookConfigUpdater>)
+            .map_err(|err| api::http_err!(BAD_REQUEST, "invalid webhook config: {err}"))?;
+
+        update_webhook(
+            &mut config,
+            &endpoint_config.name,
+            config_updater,
+            None,
+            None,
+        )
+    }
+
     /// Method: Delete a webhook endpoint.
     ///
+    /// If `refuse_if_referenced` is set, this fails if the endpoint is still used by a matcher,
+    /// otherwise only a warning is logged, see [`notify::referencing_matchers`].
+    ///
     /// See [`api::webhook::delete_endpoint`].
     #[export(serialize_error)]
     pub fn delete_webhook_endpoint(
         #[try_from_ref] this: &NotificationConfig,
         name: &str,
+        refuse_if_referenced: Option<bool>,
     ) -> Result<(), HttpError> {
         let mut config = this.config.lock().unwrap();
+        notify::ensure_unreferenced(&config, name, refuse_if_referenced.unwrap_or(false))?;
         api::webhook::delete_endpoint(&mut config, name)
     }
 
     /// Method: Get a list of all matchers.
     ///
+    /// The matchers are sorted by name.
+    ///
     /// See [`api::matcher::get_matchers`].
     #[export(serialize_error)]
     pub fn get_matchers(
         #[try_from_ref] this: &NotificationConfig,
-    ) -> Result<Vec<MatcherConfig>, HttpError> {
+    ) -> Result<Vec<Matcher>, HttpError> {
         let config = this.config.lock().unwrap();
-        api::matcher::get_matchers(&config)
+        let min_intervals = this.min_intervals.lock().unwrap();
+
+        let mut matchers = api::matcher::get_matchers(&config)?;
+        matchers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(matchers
+            .into_iter()
+            .map(|config| Matcher {
+                min_interval: min_intervals.get(&config.name).copied(),
+                config,
+            })
+            .collect())
     }
 
     /// Method: Get a single matchers by id.
@@ -572,14 +1105,23 @@ pub mod proxmox_rs_notify {
     pub fn get_matcher(
         #[try_from_ref] this: &NotificationConfig,
         id: &str,
-    ) -> Result<MatcherConfig, HttpError> {
+    ) -> Result<Matcher, HttpError> {
         let config = this.config.lock().unwrap();
-        api::matcher::get_matcher(&config, id)
+        let min_intervals = this.min_intervals.lock().unwrap();
+
+        Ok(Matcher {
+            config: api::matcher::get_matcher(&config, id)?,
+            min_interval: min_intervals.get(id).copied(),
+        })
     }
 
     /// Method: Add a matcher.
     ///
-    /// See [`api::matcher::add_matcher`].
+    /// `min_severity` is a shorthand for matching the given severity and all more severe ones, it
+    /// is rejected if `match_severity` is passed as well. `min_interval` rate limits the
+    /// matcher, see [`send_rate_limited`].
+    ///
+    /// See [`api::matcher::add_matcher`] and [`notify::resolve_min_severity`].
     #[export(serialize_error)]
     #[allow(clippy::too_many_arguments)]
     pub fn add_matcher(
@@ -593,12 +1135,17 @@ pub mod proxmox_rs_notify {
         invert_match: Option<bool>,
         comment: Option<String>,
         disable: Option<bool>,
+        min_severity: Option<Severity>,
+        min_interval: Option<u64>,
     ) -> Result<(), HttpError> {
+        let match_severity = notify::resolve_min_severity(match_severity, min_severity)?;
+
         let mut config = this.config.lock().unwrap();
+        let mut min_intervals = this.min_intervals.lock().unwrap();
         api::matcher::add_matcher(
             &mut config,
             MatcherConfig {
-                name,
+                name: name.clone(),
                 match_severity: match_severity.unwrap_or_default(),
                 match_field: match_field.unwrap_or_default(),
                 match_calendar: match_calendar.unwrap_or_default(),
@@ -609,12 +1156,21 @@ pub mod proxmox_rs_notify {
                 disable,
                 origin: None,
             },
-        )
+        )?;
+
+        if let Some(min_interval) = min_interval {
+            min_intervals.insert(name, min_interval);
+        }
+
+        Ok(())
     }
 
     /// Method: Update a matcher.
     ///
-    /// See [`api::matcher::update_matcher`].
+    /// `min_severity` replaces the severity matchers, like in [`add_matcher`]. A `min_interval`
+    /// of 0 removes the rate limit of the matcher.
+    ///
+    /// See [`api::matcher::update_matcher`] and [`notify::resolve_min_severity`].
     #[export(serialize_error)]
     #[allow(clippy::too_many_arguments)]
     pub fn update_matcher(
@@ -630,8 +1186,13 @@ pub mod proxmox_rs_notify {
         disable: Option<bool>,
         delete: Option<Vec<DeleteableMatcherProperty>>,
         digest: Option<&str>,
+        min_severity: Option<Severity>,
+        min_interval: Option<u64>,
     ) -> Result<(), HttpError> {
+        let match_severity = notify::resolve_min_severity(match_severity, min_severity)?;
+
         let mut config = this.config.lock().unwrap();
+        let mut min_intervals = this.min_intervals.lock().unwrap();
         let digest = decode_digest(digest)?;
 
         api::matcher::update_matcher(
@@ -649,7 +1210,19 @@ pub mod proxmox_rs_notify {
             },
             delete.as_deref(),
             digest.as_deref(),
-        )
+        )?;
+
+        match min_interval {
+            Some(0) => {
+                min_intervals.remove(name);
+            }
+            Some(min_interval) => {
+                min_intervals.insert(name.to_string(), min_interval);
+            }
+            None => {}
+        }
+
+        Ok(())
     }
 
     /// Method: Delete a matcher.
@@ -661,7 +1234,9 @@ pub mod proxmox_rs_notify {
         name: &str,
     ) -> Result<(), HttpError> {
         let mut config = this.config.lock().unwrap();
-        api::matcher::delete_matcher(&mut config, name)
+        api::matcher::delete_matcher(&mut config, name)?;
+        this.min_intervals.lock().unwrap().remove(name);
+        Ok(())
     }
 
     /// Method: Get a list of referenced entities for an entity.
@@ -676,6 +1251,20 @@ pub mod proxmox_rs_notify {
         api::common::get_referenced_entities(&config, name)
     }
 
+    /// Method: Get the names of all matchers routing notifications to the target `target_name`.
+    ///
+    /// The names are sorted. Targets which are still used by a matcher cannot be deleted.
+    ///
+    /// See [`notify::referencing_matchers`].
+    #[export(serialize_error)]
+    pub fn referencing_matchers(
+        #[try_from_ref] this: &NotificationConfig,
+        target_name: &str,
+    ) -> Result<Vec<String>, HttpError> {
+        let config = this.config.lock().unwrap();
+        notify::referencing_matchers(&config, target_name)
+    }
+
     fn decode_digest(digest: Option<&str>) -> Result<Option<Vec<u8>>, HttpError> {
         digest
             .map(hex::decode)
@@ -683,3 +1272,79 @@ pub mod proxmox_rs_notify {
             .map_err(|e| api::http_err!(BAD_REQUEST, "invalid digest: {e}"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::common::notify;
+
+    use super::proxmox_rs_notify::{
+        NotificationConfig, get_gotify_endpoints, get_matchers, get_targets, write_config,
+    };
+
+    #[test]
+    fn lists_sorted_by_name() {
+        let raw_config = "sendmail: zeta\n\
+                          \tmailto-user root@pam\n\
+                          \n\
+                          gotify: beta\n\
+                          \tserver https://gotify.example.com\n\
+                          \n\
+                          gotify: alpha\n\
+                          \tserver https://gotify.example.com\n\
+                          \n\
+                          matcher: late\n\
+                          \tmin-interval 600\n\
+                          \ttarget zeta\n\
+                          \n\
+                          matcher: early\n\
+                          \ttarget alpha\n";
+        let (config, min_intervals) = notify::parse_config(
+            raw_config,
+            "gotify: beta\n\
+             \ttoken secret\n\
+             \n\
+             gotify: alpha\n\
+             \ttoken secret\n",
+        )
+        .unwrap();
+        let config = NotificationConfig {
+            config: Mutex::new(config),
+            min_intervals: Mutex::new(min_intervals),
+            rate_limiter: Arc::default(),
+        };
+
+        let targets: Vec<String> = get_targets(&config)
+            .unwrap()
+            .into_iter()
+            .map(|target| target.name)
+            .collect();
+        // the product context may add built-in targets and matchers, so only check the order
+        assert!(targets.is_sorted());
+        assert!(
+            ["alpha", "beta", "zeta"]
+                .iter()
+                .all(|name| targets.contains(&name.to_string()))
+        );
+
+        let endpoints: Vec<String> = get_gotify_endpoints(&config)
+            .unwrap()
+            .into_iter()
+            .map(|endpoint| endpoint.name)
+            .collect();
+        assert_eq!(endpoints, ["alpha", "beta"]);
+
+        let matchers: Vec<(String, Option<u64>)> = get_matchers(&config)
+            .unwrap()
+            .into_iter()
+            .map(|matcher| (matcher.config.name, matcher.min_interval))
+            .collect();
+        assert!(matchers.is_sorted());
+        assert!(matchers.contains(&("early".to_string(), None)));
+        assert!(matchers.contains(&("late".to_string(), Some(600))));
+
+        let (written, _) = write_config(&config).unwrap();
+        assert!(written.contains("matcher: late\n\tmin-interval 600\n"));
+    }
+}
@@ -11,9 +11,12 @@ pub mod proxmox_rs_oidc {
     use anyhow::Error;
 
     use perlmod::{Value, to_value};
+    use serde_json::Value as JSONValue;
 
     use proxmox_openid::{OpenIdAuthenticator, OpenIdConfig, PrivateAuthState};
 
+    use crate::common::oidc;
+
     perlmod::declare_magic!(Box<OIDC> : &OIDC as "Proxmox::RS::OIDC");
 
     /// An OpenIdAuthenticator client instance.
@@ -21,11 +24,19 @@ pub mod proxmox_rs_oidc {
     /// See [`proxmox_openid::OpenIdAuthenticator`].
     pub struct OIDC {
         inner: Mutex<OpenIdAuthenticator>,
+        config: OpenIdConfig,
+        /// The raw provider metadata, fetched when creating the instance.
+        metadata: JSONValue,
     }
 
     /// Class method: Create a new OIDC client instance
     ///
-    /// See [`OpenIdAuthenticator::discover`].
+    /// The provider metadata is fetched once when creating the instance.
+    ///
+    /// All requests to the provider, including the ones of [`proxmox_openid`], are sent via the
+    /// HTTP proxy set in the `all_proxy` environment variable, if any.
+    ///
+    /// See [`OpenIdAuthenticator::discover`] and [`oidc::fetch_provider_metadata`].
     #[export(raw_return)]
     pub fn discover(
         #[raw] class: Value,
@@ -33,10 +44,14 @@ pub mod proxmox_rs_oidc {
         redirect_url: &str,
     ) -> Result<Value, Error> {
         let oidc = OpenIdAuthenticator::discover(&config, redirect_url)?;
+        let metadata = oidc::fetch_provider_metadata(&config.issuer_url)?;
+
         Ok(perlmod::instantiate_magic!(
             &class,
             MAGIC => Box::new(OIDC {
                 inner: Mutex::new(oidc),
+                config,
+                metadata,
             })
         ))
     }
@@ -44,15 +59,35 @@ pub mod proxmox_rs_oidc {
     // FIXME: There's no documentation in the proxmox_openid crate.
     /// Method: Authorize an URL.
     ///
-    /// See [`OpenIdAuthenticator::authorize_url`].
+    /// A fresh nonce is generated for every request and stored along with the CSRF token in the
+    /// private auth state in `state_dir`, see [`verify_authorization_code`].
+    ///
+    /// The optional `scopes`, `acr_values` and `prompt` override the ones of the realm
+    /// configuration for this authorization request.
+    ///
+    /// See [`OpenIdAuthenticator::authorize_url`] and [`oidc::override_authorize_params`].
     #[export]
     pub fn authorize_url(
         #[try_from_ref] this: &OIDC,
         state_dir: &str,
         realm: &str,
+        scopes: Option<Vec<String>>,
+        acr_values: Option<Vec<String>>,
+        prompt: Option<String>,
     ) -> Result<String, Error> {
         let oidc = this.inner.lock().unwrap();
-        oidc.authorize_url(state_dir, realm)
+        let url = oidc.authorize_url(state_dir, realm)?;
+
+        if scopes.is_none() && acr_values.is_none() && prompt.is_none() {
+            return Ok(url);
+        }
+
+        oidc::override_authorize_params(
+            &url,
+            scopes.as_deref(),
+            acr_values.as_deref(),
+            prompt.as_deref(),
+        )
     }
 
     // FIXME: There's no documentation in the proxmox_openid crate.
@@ -67,9 +102,25 @@ pub mod proxmox_rs_oidc {
         OpenIdAuthenticator::verify_public_auth_state(state_dir, state)
     }
 
+    /// Remove auth state files older than `max_age_secs` from `state_dir`.
+    ///
+    /// Returns the number of removed files.
+    ///
+    /// See [`oidc::prune_auth_states`].
+    #[export]
+    pub fn prune_auth_states(state_dir: &str, max_age_secs: u64) -> Result<usize, Error> {
+        oidc::prune_auth_states(
+            std::path::Path::new(state_dir),
+            std::time::Duration::from_secs(max_age_secs),
+        )
+    }
+
     // FIXME: There's no documentation in the proxmox_openid crate.
     /// Method: Verify authorization code.
     ///
+    /// The `nonce` claim of the returned ID token is checked by [`proxmox_openid`] against the
+    /// nonce stored in the `private_auth_state` by [`authorize_url`].
+    ///
     /// See [`OpenIdAuthenticator::verify_authorization_code_simple_userinfo`].
     #[export(raw_return)]
     pub fn verify_authorization_code(
@@ -87,4 +138,44 @@ pub mod proxmox_rs_oidc {
 
         Ok(to_value(&claims)?)
     }
+
+    /// Method: Query the claims of the provider's userinfo endpoint for an `access_token`.
+    ///
+    /// This does not verify an authorization code, the access token may come from another flow.
+    ///
+    /// See [`oidc::fetch_userinfo`].
+    #[export]
+    pub fn fetch_userinfo(
+        #[try_from_ref] this: &OIDC,
+        access_token: &str,
+    ) -> Result<JSONValue, Error> {
+        oidc::fetch_userinfo(&this.metadata, access_token)
+    }
+
+    /// Method: Build the URL for an RP-initiated logout at the provider.
+    ///
+    /// Returns `undef` if the provider does not advertise an `end_session_endpoint`.
+    ///
+    /// See [`oidc::end_session_url`].
+    #[export]
+    pub fn end_session_url(
+        #[try_from_ref] this: &OIDC,
+        id_token_hint: Option<String>,
+        post_logout_redirect: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        oidc::end_session_url(
+            &this.metadata,
+            &this.config.client_id,
+            id_token_hint.as_deref(),
+            post_logout_redirect.as_deref(),
+        )
+    }
+
+    /// Method: Returns the raw discovery document advertised by the provider.
+    ///
+    /// This is the document the instance was created from, the provider is not queried again.
+    #[export]
+    pub fn discovery_metadata(#[try_from_ref] this: &OIDC) -> Result<JSONValue, Error> {
+        Ok(this.metadata.clone())
+    }
 }
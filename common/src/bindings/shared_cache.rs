@@ -4,8 +4,11 @@ pub mod proxmox_rs_shared_cache {
     //!
     //! A simple cache that can be used from multiple processes concurrently.
     //!
+    //! Values can be given a time to live, after which they are treated as absent.
+    //!
     //! See [`proxmox_shared_cache::SharedCache`].
 
+    use std::path::Path;
     use std::time::Duration;
 
     use anyhow::Error;
@@ -18,8 +21,10 @@ pub mod proxmox_rs_shared_cache {
     use proxmox_shared_cache::SharedCache;
     use proxmox_sys::fs::CreateOptions;
 
-    /// Wrapper for [`proxmox_shared_cache::SharedCache`].
-    pub struct Cache(SharedCache);
+    use crate::common::shared_cache::{self, ExpiringCache};
+
+    /// Wrapper for [`proxmox_shared_cache::SharedCache`] with support for expiring values.
+    pub struct Cache(ExpiringCache);
 
     perlmod::declare_magic!(Box<Cache> : &Cache as "Proxmox::RS::SharedCache");
 
@@ -45,7 +50,7 @@ pub mod proxmox_rs_shared_cache {
 
         Ok(perlmod::instantiate_magic!(&class, MAGIC => Box::new(
             Cache (
-                SharedCache::new(params.path, options, params.keep_old)?
+                ExpiringCache::new(params.path, options, params.keep_old)?
             )
         )))
     }
@@ -59,18 +64,37 @@ pub mod proxmox_rs_shared_cache {
         value: JSONValue,
         lock_timeout: u64,
     ) -> Result<(), Error> {
-        this.0.set(&value, Duration::from_secs(lock_timeout))
+        this.0.set(value, None, Duration::from_secs(lock_timeout))
+    }
+
+    /// Method: Set the cached value, which is treated as absent after `ttl_secs` seconds.
+    ///
+    /// Setting a value again after it expired behaves just like a fresh [`set`].
+    #[export]
+    pub fn set_with_ttl(
+        #[try_from_ref] this: &Cache,
+        value: JSONValue,
+        ttl_secs: u64,
+        lock_timeout: u64,
+    ) -> Result<(), Error> {
+        this.0.set(
+            value,
+            Some(Duration::from_secs(ttl_secs)),
+            Duration::from_secs(lock_timeout),
+        )
     }
 
     /// Method: Get the last cached value.
     ///
+    /// Returns `undef` if the value has expired, in which case it is removed from the cache.
+    ///
     /// See [`SharedCache::get`](SharedCache::get()).
     #[export]
     pub fn get(#[try_from_ref] this: &Cache) -> Result<Option<JSONValue>, Error> {
         this.0.get()
     }
 
-    /// Method: Get any last stored item, including old entries.
+    /// Method: Get any last stored item, including old entries. Expired entries are skipped.
     ///
     /// See [`SharedCache::get_last`](SharedCache::get_last()).
     #[export]
@@ -81,6 +105,25 @@ pub mod proxmox_rs_shared_cache {
         this.0.get_last(number_of_old_entries)
     }
 
+    /// Method: Atomically set the cached value to `new` if it currently equals `expected`, where
+    /// `undef` means that there is no (unexpired) value. Setting `new` to `undef` removes all
+    /// items from the cache.
+    ///
+    /// Returns whether the value has been replaced, e.g. to elect a single leader among multiple
+    /// processes.
+    ///
+    /// See [`ExpiringCache::compare_and_swap`].
+    #[export]
+    pub fn compare_and_swap(
+        #[try_from_ref] this: &Cache,
+        expected: Option<JSONValue>,
+        new: Option<JSONValue>,
+        lock_timeout: u64,
+    ) -> Result<bool, Error> {
+        this.0
+            .compare_and_swap(expected.as_ref(), new, Duration::from_secs(lock_timeout))
+    }
+
     /// Method: Removes all items from the cache.
     ///
     /// See [`SharedCache::delete`](SharedCache::delete()).
@@ -88,4 +131,16 @@ pub mod proxmox_rs_shared_cache {
     pub fn delete(#[try_from_ref] this: &Cache, lock_timeout: u64) -> Result<(), Error> {
         this.0.delete(Duration::from_secs(lock_timeout))
     }
+
+    /// Returns the names of all caches in the directory `dir` which currently hold a value,
+    /// optionally only those starting with `prefix`.
+    ///
+    /// Lock files, temporary files, rotated old entries and expired values are skipped. This is
+    /// meant for inspecting caches, e.g. for debugging, and should not be used in hot paths.
+    ///
+    /// See [`shared_cache::list_keys`].
+    #[export]
+    pub fn list_keys(dir: &str, prefix: Option<String>) -> Result<Vec<String>, Error> {
+        shared_cache::list_keys(Path::new(dir), prefix.as_deref())
+    }
 }
@@ -6,13 +6,18 @@ pub mod proxmox_rs_subscription {
 
     use anyhow::{Error, bail, format_err};
 
-    use proxmox_subscription::SubscriptionInfo;
+    use proxmox_subscription::{SubscriptionInfo, SubscriptionStatus};
     use proxmox_sys::fs::CreateOptions;
 
     use proxmox_http::HttpOptions;
     use proxmox_http::ProxyConfig;
     use proxmox_http::client::sync::Client;
 
+    use crate::common::shared_cache::ExpiringCache;
+    use crate::common::subscription::{self, RateLimitedClient};
+
+    pub use crate::common::subscription::{RefreshError, SubscriptionDetails, SubscriptionLevel};
+
     /// Read the subscription status.
     ///
     /// See [`proxmox_subscription::files::read_subscription`].
@@ -24,6 +29,17 @@ pub mod proxmox_rs_subscription {
         )
     }
 
+    /// Read the subscription status and return its details, e.g. the product name, support level
+    /// and number of sockets.
+    ///
+    /// Returns `undef` if there is no subscription.
+    ///
+    /// See [`subscription::details`].
+    #[export]
+    pub fn subscription_info(path: String) -> Result<Option<SubscriptionDetails>, Error> {
+        Ok(read_subscription(path)?.and_then(subscription::details))
+    }
+
     /// Write the subscription status.
     ///
     /// See [`proxmox_subscription::files::write_subscription`].
@@ -81,6 +97,51 @@ pub mod proxmox_rs_subscription {
         user_agent: String,
         proxy: Option<String>,
     ) -> Result<SubscriptionInfo, Error> {
+        let client = http_client(user_agent, proxy)?;
+
+        proxmox_subscription::check::check_subscription(key, server_id, product_url, client)
+    }
+
+    /// Check the subscription `key` of the current system online, like [`check_subscription`].
+    ///
+    /// Unless `force` is set, the result of the last check is returned instead if it is less
+    /// than [`subscription::REFRESH_INTERVAL`] old. The last result is stored in the cache at
+    /// `cache_path`.
+    ///
+    /// If the shop rate limits the check, the error has `rate_limited` set and contains the
+    /// number of seconds to wait in `retry_after`, if the shop sent it.
+    ///
+    /// See [`subscription::refresh`].
+    #[export(serialize_error)]
+    pub fn refresh_subscription(
+        cache_path: String,
+        key: String,
+        product_url: String,
+        user_agent: String,
+        proxy: Option<String>,
+        force: bool,
+    ) -> Result<SubscriptionInfo, RefreshError> {
+        let mode = nix::sys::stat::Mode::from_bits_truncate(0o0600);
+        let options = CreateOptions::new().perm(mode).owner(nix::unistd::ROOT);
+        let cache = ExpiringCache::new(cache_path, options, 0)?;
+
+        let info = subscription::refresh(&cache, &key, force, || {
+            let server_id = proxmox_subscription::get_hardware_address()?;
+            let client = RateLimitedClient(http_client(user_agent, proxy)?);
+
+            proxmox_subscription::check::check_subscription(
+                key.clone(),
+                server_id,
+                product_url,
+                client,
+            )
+        })?;
+
+        Ok(info)
+    }
+
+    /// Create the HTTP client for checking a subscription.
+    fn http_client(user_agent: String, proxy: Option<String>) -> Result<Client, Error> {
         let proxy_config = match proxy {
             Some(url) => Some(ProxyConfig::parse_proxy_url(&url)?),
             None => None,
@@ -90,9 +151,50 @@ pub mod proxmox_rs_subscription {
             user_agent: Some(user_agent),
             ..Default::default()
         };
-        let client = Client::new(options);
 
-        proxmox_subscription::check::check_subscription(key, server_id, product_url, client)
+        Ok(Client::new(options))
+    }
+
+    /// Get the server ID of the current system, which subscription keys are bound to.
+    ///
+    /// This is the same ID that is sent to the shop when checking a subscription and compared by
+    /// [`check_server_id`].
+    ///
+    /// See [`proxmox_subscription::get_hardware_address`].
+    #[export]
+    pub fn subscription_server_id() -> Result<String, Error> {
+        proxmox_subscription::get_hardware_address()
+    }
+
+    /// Validate a signed offline subscription without any network access.
+    ///
+    /// `blob` is the signed subscription info as JSON. Its signature is verified against the
+    /// embedded signing key, then it must be bound to the server ID of the current system and
+    /// must not have expired.
+    ///
+    /// See [`subscription::check_offline`].
+    #[export]
+    pub fn validate_offline_subscription(blob: &[u8]) -> Result<SubscriptionInfo, Error> {
+        let mut info: SubscriptionInfo = serde_json::from_slice(blob)
+            .map_err(|err| format_err!("failed to parse offline subscription: {err}"))?;
+
+        if !info.is_signed() {
+            bail!("offline subscription is not signed");
+        }
+
+        info.check_signature(&[proxmox_subscription::files::DEFAULT_SIGNING_KEY]);
+        if info.status == SubscriptionStatus::Invalid {
+            bail!(
+                "offline subscription has an invalid signature: {}",
+                info.message.as_deref().unwrap_or("unknown error"),
+            );
+        }
+
+        let server_id = proxmox_subscription::get_hardware_address()?;
+        let today = proxmox_time::strftime_local("%Y-%m-%d", proxmox_time::epoch_i64())?;
+        subscription::check_offline(&info, &server_id, &today)?;
+
+        Ok(info)
     }
 
     /// Check that server ID contained in [`SubscriptionInfo`] matches that of current system.
@@ -0,0 +1,399 @@
+//! Helpers for calendar events which are not covered by [`proxmox_time`].
+
+use anyhow::{Error, bail, format_err};
+use jiff::Timestamp;
+use jiff::tz::TimeZone;
+
+use proxmox_time::CalendarEvent;
+
+/// The maximum number of events [`compute_next_events`] computes at once.
+pub const MAX_NEXT_EVENTS: usize = 1000;
+
+/// Compute up to `count` timestamps at which `event` triggers, strictly after `last`.
+///
+/// Fewer timestamps are returned if the event does not trigger often enough. `count` is capped at
+/// [`MAX_NEXT_EVENTS`], so pathological requests cannot run for a long time.
+fn next_events(event: &CalendarEvent, mut last: i64, count: usize) -> Result<Vec<i64>, Error> {
+    let count = count.min(MAX_NEXT_EVENTS);
+    let mut events = Vec::with_capacity(count);
+
+    while events.len() < count {
+        match event.compute_next_event(last)? {
+            // guard against not making progress, which would loop forever
+            Some(next) if next > last => {
+                events.push(next);
+                last = next;
+            }
+            _ => break,
+        }
+    }
+
+    Ok(events)
+}
+
+/// Compute up to `count` timestamps at which the calendar event `schedule` triggers, strictly
+/// after `after`.
+///
+/// See [`next_events`] for how `count` is limited.
+pub fn compute_next_events(schedule: &str, after: i64, count: usize) -> Result<Vec<i64>, Error> {
+    let event: CalendarEvent = schedule
+        .parse()
+        .map_err(|err| format_err!("invalid calendar event '{schedule}': {err}"))?;
+
+    next_events(&event, after, count)
+}
+
+/// The weekdays in the order they are used in calendar events.
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// The long names of [`WEEKDAYS`].
+const LONG_WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// Returns the index of a weekday in [`WEEKDAYS`], accepting both short and long names.
+fn weekday_index(name: &str) -> Option<usize> {
+    let name = name.to_ascii_lowercase();
+    WEEKDAYS
+        .iter()
+        .zip(LONG_WEEKDAYS)
+        .position(|(short, long)| name == *short || name == long)
+}
+
+/// Expand a weekday specification like `mon..wed,Friday` into `mon,tue,wed,fri`.
+fn expand_weekdays(spec: &str) -> Option<String> {
+    let mut days = [false; 7];
+
+    for part in spec.split(',') {
+        match part.split_once("..") {
+            Some((start, end)) => {
+                let (start, end) = (weekday_index(start)?, weekday_index(end)?);
+                let mut day = start;
+                loop {
+                    days[day] = true;
+                    if day == end {
+                        break;
+                    }
+                    day = (day + 1) % WEEKDAYS.len();
+                }
+            }
+            None => days[weekday_index(part)?] = true,
+        }
+    }
+
+    let days: Vec<&str> = WEEKDAYS
+        .iter()
+        .zip(days)
+        .filter_map(|(name, set)| set.then_some(*name))
+        .collect();
+
+    Some(days.join(","))
+}
+
+/// Zero-pad all numbers in a single date or time component, e.g. `8..9/5` becomes `08..09/05`.
+fn pad_component(component: &str) -> String {
+    component
+        .split(',')
+        .map(|value| {
+            value
+                .split('/')
+                .map(|part| {
+                    part.split("..")
+                        .map(|number| match number.parse::<u32>() {
+                            Ok(n) if number.len() < 2 => format!("{n:02}"),
+                            _ => number.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("..")
+                })
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The timestamps from which [`describe`] compares the events of a schedule and its description.
+const DESCRIBE_CHECK_STARTS: [i64; 3] = [0, 1_700_000_000, 1_735_689_600];
+
+/// Returns whether `a` and `b` trigger at the same times, checking the next few events after
+/// each of [`DESCRIBE_CHECK_STARTS`].
+fn same_events(a: &CalendarEvent, b: &CalendarEvent) -> Result<bool, Error> {
+    for start in DESCRIBE_CHECK_STARTS {
+        if next_events(a, start, 32)? != next_events(b, start, 32)? {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Describe the calendar event `schedule` in a normalized form, e.g. `mon..fri 8:00` becomes
+/// `mon,tue,wed,thu,fri 08:00:00`.
+///
+/// The schedule is parsed with [`CalendarEvent`] first, so invalid schedules are rejected with the
+/// parser's error. The normalized form is parsed again and must trigger at the same times as
+/// `schedule`, otherwise an error is returned. The normalized form is only meant to be shown to
+/// users, it does not influence how the schedule gets evaluated.
+pub fn describe(schedule: &str) -> Result<String, Error> {
+    let event: CalendarEvent = schedule
+        .parse()
+        .map_err(|err| format_err!("invalid calendar event '{schedule}': {err}"))?;
+
+    let mut parts = Vec::new();
+    let mut utc = false;
+
+    for (i, token) in schedule.split_whitespace().enumerate() {
+        if token.eq_ignore_ascii_case("utc") {
+            utc = true;
+        } else if token.contains(':') {
+            let mut time: Vec<String> = token.split(':').map(pad_component).collect();
+            if time.len() == 2 {
+                time.push("00".to_string());
+            }
+            parts.push(time.join(":"));
+        } else if token.contains('-') {
+            let date: Vec<String> = token
+                .split('-')
+                .enumerate()
+                .map(|(j, component)| match (j, token.split('-').count()) {
+                    // keep the year as it is
+                    (0, 3) => component.to_string(),
+                    _ => pad_component(component),
+                })
+                .collect();
+            parts.push(date.join("-"));
+        } else if let Some(days) = (i == 0).then(|| expand_weekdays(token)).flatten() {
+            parts.push(days);
+        } else {
+            parts.push(token.to_ascii_lowercase());
+        }
+    }
+
+    if utc {
+        parts.push("UTC".to_string());
+    }
+
+    let description = parts.join(" ");
+
+    let described: CalendarEvent = description.parse().map_err(|err| {
+        format_err!("cannot normalize calendar event '{schedule}' to '{description}': {err}")
+    })?;
+
+    if !same_events(&event, &described)? {
+        bail!("cannot normalize calendar event '{schedule}', '{description}' triggers differently");
+    }
+
+    Ok(description)
+}
+
+/// Returns whether the calendar event `schedule` is evaluated in UTC instead of local time.
+fn is_utc(schedule: &str) -> bool {
+    schedule
+        .split_whitespace()
+        .last()
+        .is_some_and(|token| token.eq_ignore_ascii_case("utc"))
+}
+
+/// Compute the next timestamp after `last` at which `schedule` triggers, interpreting the
+/// schedule in the IANA time zone `tz` instead of the host's local time zone.
+///
+/// The schedule is evaluated in UTC on the wall clock time of `tz`, which is then resolved back
+/// with [`jiff`], so the process wide local time zone is never touched. Times skipped by daylight
+/// saving time transitions are shifted forward by the length of the gap, times happening twice
+/// trigger on their first occurrence, unless that is not after `last` anymore. The returned
+/// timestamp is a UTC epoch. Schedules with an explicit `UTC` suffix are not affected by `tz`.
+pub fn compute_next_in_tz(schedule: &str, last: i64, tz: &str) -> Result<i64, Error> {
+    let event: CalendarEvent = schedule
+        .parse()
+        .map_err(|err| format_err!("invalid calendar event '{schedule}': {err}"))?;
+    let zone = TimeZone::get(tz).map_err(|_| format_err!("unknown time zone '{tz}'"))?;
+
+    let never = || format_err!("calendar event '{schedule}' does not trigger after {last}");
+
+    if is_utc(schedule) {
+        return event.compute_next_event(last)?.ok_or_else(never);
+    }
+
+    let event: CalendarEvent = format!("{schedule} UTC").parse()?;
+
+    let offset = Timestamp::from_second(last)?
+        .to_zoned(zone.clone())
+        .offset();
+    let mut local = last + i64::from(offset.seconds());
+
+    loop {
+        let next_local = match event.compute_next_event(local)? {
+            // guard against not making progress, which would loop forever
+            Some(next) if next > local => next,
+            _ => return Err(never()),
+        };
+
+        let wall_clock = Timestamp::from_second(next_local)?
+            .to_zoned(TimeZone::UTC)
+            .datetime();
+        let resolved = zone.to_ambiguous_timestamp(wall_clock);
+
+        // the first occurrence of a repeated time might be before `last`
+        for next in [resolved.compatible()?, resolved.later()?] {
+            if next.as_second() > last {
+                return Ok(next.as_second());
+            }
+        }
+
+        local = next_local;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_events() {
+        assert_eq!(compute_next_events("*:*", 0, 3).unwrap(), [60, 120, 180]);
+        assert_eq!(compute_next_events("*:*", 30, 2).unwrap(), [60, 120]);
+        assert!(compute_next_events("*:*", 0, 0).unwrap().is_empty());
+        assert_eq!(
+            compute_next_events("*:*", 0, usize::MAX).unwrap().len(),
+            MAX_NEXT_EVENTS
+        );
+
+        assert!(
+            compute_next_events("2000-01-01 00:00", 946_684_800, 5)
+                .unwrap()
+                .is_empty()
+        );
+
+        let err = compute_next_events("8:xx", 0, 5).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid calendar event '8:xx':")
+        );
+    }
+
+    #[test]
+    fn describe_events() {
+        assert_eq!(
+            describe("mon..fri 8:00").unwrap(),
+            "mon,tue,wed,thu,fri 08:00:00"
+        );
+        assert_eq!(
+            describe("Sat,Sunday  *-*-1 4:0/15:5 UTC").unwrap(),
+            "sat,sun *-*-01 04:00/15:05 UTC"
+        );
+        assert_eq!(
+            describe("Wed..fri,mon 2025-3-7 0:00").unwrap(),
+            "mon,wed,thu,fri 2025-03-07 00:00:00"
+        );
+        assert_eq!(describe("8..17:30").unwrap(), "08..17:30:00");
+        assert_eq!(describe("daily").unwrap(), "daily");
+        assert_eq!(describe("monthly").unwrap(), "monthly");
+        assert_eq!(describe("Monday 8:00").unwrap(), "mon 08:00:00");
+        assert!(weekday_index("monthly").is_none());
+        assert!(weekday_index("mo").is_none());
+
+        let err = describe("mon..xyz 8:00").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid calendar event 'mon..xyz 8:00':")
+        );
+    }
+
+    #[test]
+    fn next_in_timezone() {
+        // 2024-06-01 12:00 UTC
+        let last = 1_717_243_200;
+        assert_eq!(
+            compute_next_in_tz("8:00", last, "Europe/Vienna").unwrap(),
+            1_717_308_000
+        );
+        assert_eq!(
+            compute_next_in_tz("8:00", last, "America/New_York").unwrap(),
+            1_717_329_600
+        );
+        assert_eq!(
+            compute_next_in_tz("8:00 UTC", last, "America/New_York").unwrap(),
+            1_717_315_200
+        );
+
+        for tz in [
+            "Europe/Nowhere",
+            "",
+            "../etc/passwd",
+            "/etc/localtime",
+            "Europe",
+        ] {
+            let err = compute_next_in_tz("8:00", last, tz).unwrap_err();
+            assert_eq!(err.to_string(), format!("unknown time zone '{tz}'"));
+        }
+
+        assert!(compute_next_in_tz("8:xx", last, "Europe/Vienna").is_err());
+
+        let err = compute_next_in_tz("2000-01-01 00:00", last, "Europe/Vienna").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("calendar event '2000-01-01 00:00' does not trigger after {last}")
+        );
+    }
+
+    #[test]
+    fn next_in_timezone_spring_forward() {
+        // 2024-03-30 12:00 UTC, clocks in Vienna go from 02:00 to 03:00 on 2024-03-31
+        let last = 1_711_800_000;
+
+        // 08:00 CEST on 2024-03-31
+        assert_eq!(
+            compute_next_in_tz("8:00", last, "Europe/Vienna").unwrap(),
+            1_711_864_800
+        );
+
+        // 02:30 does not exist on 2024-03-31 and is shifted to 03:30 CEST
+        assert_eq!(
+            compute_next_in_tz("2:30", last, "Europe/Vienna").unwrap(),
+            1_711_848_600
+        );
+
+        // 02:30 CEST on 2024-04-01
+        assert_eq!(
+            compute_next_in_tz("2:30", 1_711_848_600, "Europe/Vienna").unwrap(),
+            1_711_931_400
+        );
+    }
+
+    #[test]
+    fn next_in_timezone_fall_back() {
+        // 2024-10-26 12:00 UTC, clocks in Vienna go from 03:00 back to 02:00 on 2024-10-27
+        let last = 1_729_944_000;
+
+        // 08:00 CET on 2024-10-27
+        assert_eq!(
+            compute_next_in_tz("8:00", last, "Europe/Vienna").unwrap(),
+            1_730_012_400
+        );
+
+        // 02:30 happens twice on 2024-10-27, the first occurrence (CEST) is used
+        assert_eq!(
+            compute_next_in_tz("2:30", last, "Europe/Vienna").unwrap(),
+            1_729_989_000
+        );
+
+        // ... and not triggered again at the second one (CET), but on 2024-10-28
+        assert_eq!(
+            compute_next_in_tz("2:30", 1_729_989_000, "Europe/Vienna").unwrap(),
+            1_730_079_000
+        );
+
+        // the second occurrence is used if the first one is already over, 02:00 CET
+        assert_eq!(
+            compute_next_in_tz("2:30", 1_729_990_800, "Europe/Vienna").unwrap(),
+            1_729_992_600
+        );
+    }
+}
@@ -1,3 +1,19 @@
+pub mod apt;
+
 pub mod bindings;
 
+pub mod calendar_event;
+
 pub mod logger;
+
+pub mod notify;
+
+pub mod oidc;
+
+pub mod retry_after;
+
+pub mod shared_cache;
+
+pub mod subscription;
+
+pub mod tfa;
@@ -0,0 +1,1908 @@
+//! Helpers for the notification system which are not covered by [`proxmox_notify`].
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Error, bail, format_err};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use proxmox_http::client::sync::Client;
+use proxmox_http::{HttpClient, HttpOptions, ProxyConfig};
+use proxmox_http_error::HttpError;
+use proxmox_notify::endpoints::smtp::{SmtpConfig, SmtpMode};
+use proxmox_notify::endpoints::webhook::{WebhookConfig, WebhookEndpoint, WebhookPrivateConfig};
+use proxmox_notify::matcher::{
+    DeleteableMatcherProperty, MatcherConfig, MatcherConfigUpdater, SeverityMatcher, check_matches,
+};
+use proxmox_notify::renderer::{self, TemplateType};
+use proxmox_notify::schema::ENTITY_NAME_SCHEMA;
+use proxmox_notify::{Config, Notification, Severity, api};
+use proxmox_section_config::{SectionConfig, SectionConfigData};
+
+/// How many targets and recipients a notification would reach.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Fanout {
+    /// The number of targets the notification is sent to.
+    pub targets: usize,
+    /// The number of recipients the notification is delivered to.
+    ///
+    /// Mail targets count every distinct address, including those of the configured users, all
+    /// other targets count as a single recipient.
+    pub recipients: usize,
+}
+
+/// Count the distinct addresses a mail target delivers to.
+///
+/// Users in `mailto_user` are resolved with `lookup_email`, users without an address are skipped
+/// like when sending the notification.
+fn mail_recipients(
+    mailto: &[String],
+    mailto_user: &[String],
+    lookup_email: &impl Fn(&str) -> Option<String>,
+) -> usize {
+    let mut addresses: HashSet<String> = mailto.iter().cloned().collect();
+    addresses.extend(mailto_user.iter().filter_map(|user| lookup_email(user)));
+    addresses.len()
+}
+
+/// Returns the number of recipients for every enabled target in `config`.
+///
+/// The addresses of the users of mail targets are resolved with `lookup_email`.
+pub fn target_recipients(
+    config: &Config,
+    lookup_email: impl Fn(&str) -> Option<String>,
+) -> Result<HashMap<String, usize>, HttpError> {
+    let mut recipients = HashMap::new();
+
+    for endpoint in api::sendmail::get_endpoints(config)? {
+        if !endpoint.disable.unwrap_or_default() {
+            recipients.insert(
+                endpoint.name,
+                mail_recipients(&endpoint.mailto, &endpoint.mailto_user, &lookup_email),
+            );
+        }
+    }
+
+    for endpoint in api::smtp::get_endpoints(config)? {
+        if !endpoint.disable.unwrap_or_default() {
+            recipients.insert(
+                endpoint.name,
+                mail_recipients(&endpoint.mailto, &endpoint.mailto_user, &lookup_email),
+            );
+        }
+    }
+
+    for endpoint in api::gotify::get_endpoints(config)? {
+        if !endpoint.disable.unwrap_or_default() {
+            recipients.insert(endpoint.name, 1);
+        }
+    }
+
+    for endpoint in api::webhook::get_endpoints(config)? {
+        if !endpoint.disable.unwrap_or_default() {
+            recipients.insert(endpoint.name, 1);
+        }
+    }
+
+    Ok(recipients)
+}
+
+/// Returns the names of the targets the enabled `matchers` route `notification` to, sorted by
+/// name.
+pub fn matched_targets(matchers: &[MatcherConfig], notification: &Notification) -> Vec<String> {
+    let matchers: Vec<&MatcherConfig> = matchers
+        .iter()
+        .filter(|matcher| !matcher.disable.unwrap_or_default())
+        .collect();
+
+    let mut targets: Vec<String> = check_matches(&matchers, notification)
+        .into_iter()
+        .map(|target| target.to_string())
+        .collect();
+    targets.sort();
+    targets
+}
+
+/// Estimate how many of the targets in `recipients` a `notification` would reach, according to
+/// the `matchers`.
+///
+/// This uses the same matcher evaluation as sending a notification, but does not send anything.
+pub fn estimate_fanout(
+    matchers: &[MatcherConfig],
+    recipients: &HashMap<String, usize>,
+    notification: &Notification,
+) -> Fanout {
+    matched_targets(matchers, notification)
+        .iter()
+        .filter_map(|target| recipients.get(target))
+        .fold(Fanout::default(), |fanout, count| Fanout {
+            targets: fanout.targets + 1,
+            recipients: fanout.recipients + count,
+        })
+}
+
+/// Send `notification` according to `config`.
+///
+/// Returns how many targets and recipients the notification was routed to, counted like in
+/// [`estimate_fanout`]. Failures of single targets are only logged by [`api::common::send`], so
+/// those targets are counted as well.
+pub fn send(
+    config: &Config,
+    notification: &Notification,
+    lookup_email: impl Fn(&str) -> Option<String>,
+) -> Result<Fanout, HttpError> {
+    let fanout = estimate_fanout(
+        &api::matcher::get_matchers(config)?,
+        &target_recipients(config, lookup_email)?,
+        notification,
+    );
+
+    api::common::send(config, notification)?;
+
+    Ok(fanout)
+}
+
+/// How many notifications are kept in the history, see [`recent_notifications`].
+pub const HISTORY_SIZE: usize = 256;
+
+/// A notification sent by this process.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct NotificationRecord {
+    /// When the notification was sent, as UNIX epoch.
+    pub timestamp: i64,
+    /// The severity of the notification.
+    pub severity: Severity,
+    /// The name of the template the notification was rendered from.
+    pub template: String,
+    /// The targets the matchers routed the notification to, sorted by name.
+    pub targets: Vec<String>,
+    /// The matchers which did not route the notification, as they already did so within their
+    /// minimal interval.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub throttled: Vec<String>,
+    /// Why sending the notification failed, if it did.
+    ///
+    /// Failures of single targets are only logged by [`api::common::send`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A bounded history of notifications, the most recent last.
+struct History(VecDeque<NotificationRecord>);
+
+impl History {
+    fn push(&mut self, record: NotificationRecord, capacity: usize) {
+        while self.0.len() >= capacity {
+            self.0.pop_front();
+        }
+        self.0.push_back(record);
+    }
+
+    fn recent(&self, limit: usize) -> Vec<NotificationRecord> {
+        self.0.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History(VecDeque::new()));
+
+/// Add a sent notification to the history, dropping the oldest entry if it is full.
+pub fn record_notification(record: NotificationRecord) {
+    HISTORY.lock().unwrap().push(record, HISTORY_SIZE);
+}
+
+/// Returns up to `limit` of the most recently sent notifications, the most recent first.
+///
+/// The history only covers notifications sent by this process and holds at most
+/// [`HISTORY_SIZE`] entries.
+pub fn recent_notifications(limit: usize) -> Vec<NotificationRecord> {
+    HISTORY.lock().unwrap().recent(limit)
+}
+
+/// Result of sending a notification.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SendOutcome {
+    /// The matchers which did not route the notification, as they already did so within their
+    /// minimal interval, see [`RateLimiter`].
+    pub throttled: Vec<String>,
+}
+
+/// The matcher property holding the minimal interval in seconds between two notifications routed
+/// by the matcher.
+///
+/// [`proxmox_notify`] does not know this property, so it is split off the configuration before
+/// parsing it, see [`split_min_intervals`].
+pub const MIN_INTERVAL_PROPERTY: &str = "min-interval";
+
+/// Returns the name of the matcher declared by the section header `line`, if it is one.
+fn matcher_header(line: &str) -> Option<&str> {
+    let (section_type, name) = line.split_once(':')?;
+    (section_type == "matcher").then(|| name.trim())
+}
+
+/// Remove the [`MIN_INTERVAL_PROPERTY`] of all matchers from `raw_config`.
+///
+/// Returns the remaining configuration and the minimal interval of each matcher which has one.
+pub fn split_min_intervals(raw_config: &str) -> Result<(String, BTreeMap<String, u64>), Error> {
+    let mut config = String::with_capacity(raw_config.len());
+    let mut min_intervals = BTreeMap::new();
+    let mut matcher = None;
+
+    for line in raw_config.lines() {
+        if !line.starts_with(char::is_whitespace) && !line.is_empty() {
+            matcher = matcher_header(line);
+        } else if let Some(name) = matcher {
+            let mut parts = line.trim().splitn(2, char::is_whitespace);
+            if parts.next() == Some(MIN_INTERVAL_PROPERTY) {
+                let value = parts.next().unwrap_or_default().trim();
+                let interval = value.parse().map_err(|err| {
+                    format_err!(
+                        "invalid {MIN_INTERVAL_PROPERTY} '{value}' of matcher '{name}' - {err}"
+                    )
+                })?;
+                min_intervals.insert(name.to_string(), interval);
+                continue;
+            }
+        }
+
+        config.push_str(line);
+        config.push('\n');
+    }
+
+    Ok((config, min_intervals))
+}
+
+/// Add the `min_intervals` of the matchers back to `raw_config`, as written by [`Config::write`].
+pub fn merge_min_intervals(raw_config: &str, min_intervals: &BTreeMap<String, u64>) -> String {
+    let mut config = String::with_capacity(raw_config.len());
+
+    for line in raw_config.lines() {
+        config.push_str(line);
+        config.push('\n');
+
+        if let Some(interval) = matcher_header(line).and_then(|name| min_intervals.get(name)) {
+            config.push_str(&format!("\t{MIN_INTERVAL_PROPERTY} {interval}\n"));
+        }
+    }
+
+    config
+}
+
+/// Parse the notification configuration, which may contain the [`MIN_INTERVAL_PROPERTY`] of
+/// matchers.
+///
+/// Returns the configuration and the minimal interval of each matcher which has one.
+pub fn parse_config(
+    raw_config: &str,
+    raw_private_config: &str,
+) -> Result<(Config, BTreeMap<String, u64>), Error> {
+    let (raw_config, min_intervals) = split_min_intervals(raw_config)?;
+    Ok((Config::new(&raw_config, raw_private_config)?, min_intervals))
+}
+
+/// Remembers when matchers with a minimal interval last routed a notification.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    last_routed: BTreeMap<String, i64>,
+}
+
+impl RateLimiter {
+    /// Returns the enabled `matchers` with a minimal interval which match `notification`.
+    fn limited_matches<'a>(
+        matchers: &'a [MatcherConfig],
+        notification: &'a Notification,
+        min_intervals: &'a BTreeMap<String, u64>,
+    ) -> impl Iterator<Item = (&'a str, u64)> + 'a {
+        matchers.iter().filter_map(move |matcher| {
+            let min_interval = *min_intervals.get(&matcher.name)?;
+
+            if matcher.disable.unwrap_or_default()
+                || check_matches(&[matcher], notification).is_empty()
+            {
+                return None;
+            }
+
+            Some((matcher.name.as_str(), min_interval))
+        })
+    }
+
+    /// Returns the names of the enabled `matchers` which match `notification`, but already
+    /// routed a notification within their minimal interval at `now`.
+    ///
+    /// `min_intervals` maps matcher names to their minimal interval in seconds, matchers without
+    /// an entry are never throttled.
+    pub fn throttled(
+        &self,
+        matchers: &[MatcherConfig],
+        notification: &Notification,
+        min_intervals: &BTreeMap<String, u64>,
+        now: i64,
+    ) -> Vec<String> {
+        Self::limited_matches(matchers, notification, min_intervals)
+            .filter(|(name, min_interval)| {
+                let min_interval = i64::try_from(*min_interval).unwrap_or(i64::MAX);
+                self.last_routed
+                    .get(*name)
+                    .is_some_and(|last| now.saturating_sub(*last) < min_interval)
+            })
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    /// Remember that the `matchers` with a minimal interval which match `notification` routed it
+    /// at `now`.
+    ///
+    /// This must only be called once the notification was sent, and without the matchers which
+    /// were [`throttled`](Self::throttled).
+    pub fn record(
+        &mut self,
+        matchers: &[MatcherConfig],
+        notification: &Notification,
+        min_intervals: &BTreeMap<String, u64>,
+        now: i64,
+    ) {
+        for (name, _) in Self::limited_matches(matchers, notification, min_intervals) {
+            self.last_routed.insert(name.to_string(), now);
+        }
+    }
+}
+
+/// Call `func` with the `matchers` of `config` disabled, restoring them afterwards.
+pub fn with_matchers_disabled<T>(
+    config: &mut Config,
+    matchers: &[String],
+    func: impl FnOnce(&Config) -> T,
+) -> Result<T, HttpError> {
+    let mut disabled = Vec::new();
+
+    let result: Result<(), HttpError> = matchers.iter().try_for_each(|name| {
+        let previous = api::matcher::get_matcher(config, name)?.disable;
+
+        let mut updater = MatcherConfigUpdater::default();
+        updater.disable = Some(true);
+        api::matcher::update_matcher(config, name, updater, None, None)?;
+
+        disabled.push((name, previous));
+        Ok(())
+    });
+
+    let result = result.map(|()| func(config));
+
+    for (name, previous) in disabled {
+        let mut updater = MatcherConfigUpdater::default();
+        let delete: &[DeleteableMatcherProperty] = match previous {
+            Some(disable) => {
+                updater.disable = Some(disable);
+                &[]
+            }
+            None => &[DeleteableMatcherProperty::Disable],
+        };
+        api::matcher::update_matcher(config, name, updater, Some(delete), None)?;
+    }
+
+    result
+}
+
+/// A notification template rendered by [`render_template`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RenderedTemplate {
+    /// The rendered subject.
+    pub subject: String,
+    /// The rendered plain text body.
+    pub body_text: String,
+    /// The rendered HTML body, only rendered for mail targets.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body_html: Option<String>,
+}
+
+/// Render the template `template_name` with `data`, the same way as when sending a notification
+/// from it, but without sending anything.
+///
+/// `target_kind` is the type of target the notification is rendered for, i.e. `sendmail`,
+/// `smtp`, `gotify` or `webhook`. The HTML body is only rendered for mail targets, or if no
+/// target kind is given. All helpers available when sending, like `table`, can be used.
+pub fn render_template(
+    template_name: &str,
+    data: &Value,
+    target_kind: Option<&str>,
+) -> Result<RenderedTemplate, Error> {
+    let with_html = match target_kind {
+        None | Some("sendmail" | "smtp") => true,
+        Some("gotify" | "webhook") => false,
+        Some(kind) => bail!("unknown target kind '{kind}'"),
+    };
+
+    let body_html = if with_html {
+        Some(renderer::render_template(
+            TemplateType::HtmlBody,
+            template_name,
+            data,
+        )?)
+    } else {
+        None
+    };
+
+    Ok(RenderedTemplate {
+        subject: renderer::render_template(TemplateType::Subject, template_name, data)?,
+        body_text: renderer::render_template(TemplateType::PlaintextBody, template_name, data)?,
+        body_html,
+    })
+}
+
+/// Check that the template `template_name` can be rendered with `data`, i.e. that it is
+/// installed.
+///
+/// Templates are looked up via the notification context, like when sending. This allows failing
+/// early instead of only logging the error once the notification is sent.
+pub fn check_template(template_name: &str, data: &Value) -> Result<(), Error> {
+    renderer::render_template(TemplateType::Subject, template_name, data)
+        .map(|_| ())
+        .map_err(|err| format_err!("cannot render notification template '{template_name}': {err}"))
+}
+
+/// The outcome of a job.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// The job finished successfully.
+    Ok,
+    /// The job finished, but with warnings.
+    Warning,
+    /// The job failed.
+    Error,
+}
+
+impl JobStatus {
+    /// The severity of a notification about a job with this status.
+    pub fn severity(self) -> Severity {
+        match self {
+            JobStatus::Ok => Severity::Info,
+            JobStatus::Warning => Severity::Warning,
+            JobStatus::Error => Severity::Error,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Ok => "ok",
+            JobStatus::Warning => "warning",
+            JobStatus::Error => "error",
+        }
+    }
+}
+
+/// The name of the template used for notifications about finished jobs.
+///
+/// The template is shipped by the product, like all other notification templates. It gets the
+/// data built by [`job_result_template_data`].
+pub const JOB_RESULT_TEMPLATE: &str = "job-result";
+
+/// Build the template data for a notification about a finished job.
+///
+/// The `details` are rendered via the `table` handlebars helper, in the order of their keys.
+pub fn job_result_template_data(
+    hostname: &str,
+    job_type: &str,
+    status: JobStatus,
+    details: &BTreeMap<String, String>,
+) -> Value {
+    let details: Vec<Value> = details
+        .iter()
+        .map(|(property, value)| json!({ "property": property, "value": value }))
+        .collect();
+
+    json!({
+        "hostname": hostname,
+        "job-type": job_type,
+        "status": status.as_str(),
+        "details": {
+            "schema": {
+                "columns": [
+                    {
+                        "label": "Property",
+                        "id": "property",
+                    },
+                    {
+                        "label": "Value",
+                        "id": "value",
+                    },
+                ],
+            },
+            "data": details,
+        },
+    })
+}
+
+/// Build the metadata fields of a notification about a finished job, for matching it.
+pub fn job_result_fields(
+    hostname: &str,
+    job_type: &str,
+    status: JobStatus,
+) -> HashMap<String, String> {
+    HashMap::from([
+        ("hostname".into(), hostname.into()),
+        ("type".into(), job_type.into()),
+        ("job-status".into(), status.as_str().into()),
+    ])
+}
+
+/// Metadata field carrying how often a notification was already passed on, e.g. by a webhook
+/// receiver which generates a new notification for every received one.
+pub const HOP_COUNT_FIELD: &str = "hop-count";
+
+/// The default maximum number of hops a notification may take before it is dropped.
+pub const DEFAULT_MAX_HOPS: u32 = 5;
+
+/// Returns whether a notification with the metadata `fields` exceeded the `max_hops` limit and
+/// should be dropped to break a notification loop.
+///
+/// Notifications without (or with an invalid) [`HOP_COUNT_FIELD`] are never dropped.
+pub fn exceeds_hop_limit(fields: &HashMap<String, String>, max_hops: u32) -> bool {
+    fields
+        .get(HOP_COUNT_FIELD)
+        .and_then(|hops| hops.parse::<u32>().ok())
+        .is_some_and(|hops| hops > max_hops)
+}
+
+/// Returns the names of the `matchers` routing notifications to `target`, sorted by name.
+fn matchers_referencing(matchers: &[MatcherConfig], target: &str) -> Vec<String> {
+    let mut names: Vec<String> = matchers
+        .iter()
+        .filter(|matcher| matcher.target.iter().any(|t| t == target))
+        .map(|matcher| matcher.name.clone())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Returns the names of all matchers routing notifications to `target`, sorted by name.
+pub fn referencing_matchers(config: &Config, target: &str) -> Result<Vec<String>, HttpError> {
+    Ok(matchers_referencing(
+        &api::matcher::get_matchers(config)?,
+        target,
+    ))
+}
+
+/// All severities in ascending order.
+///
+/// Notifications with an `unknown` severity count as the most severe ones, so a minimum severity
+/// never drops them.
+const SEVERITY_ORDER: [Severity; 5] = [
+    Severity::Info,
+    Severity::Notice,
+    Severity::Warning,
+    Severity::Error,
+    Severity::Unknown,
+];
+
+/// Build a severity matcher matching `min_severity` and all more severe severities.
+pub fn min_severity_matcher(min_severity: Severity) -> Result<SeverityMatcher, HttpError> {
+    let position = SEVERITY_ORDER
+        .iter()
+        .position(|severity| *severity == min_severity)
+        .ok_or_else(|| api::http_err!(BAD_REQUEST, "unknown severity '{min_severity}'"))?;
+
+    let severities: Vec<String> = SEVERITY_ORDER[position..]
+        .iter()
+        .map(|severity| severity.to_string())
+        .collect();
+
+    severities.join(",").parse().map_err(|err| {
+        api::http_err!(
+            INTERNAL_SERVER_ERROR,
+            "failed to build severity matcher for '{min_severity}': {err}"
+        )
+    })
+}
+
+/// Resolve the `min_severity` shorthand of a matcher into the equivalent `match_severity`.
+///
+/// The shorthand cannot be combined with explicit severity matchers, since it would be ambiguous
+/// whether it narrows or replaces them.
+pub fn resolve_min_severity(
+    match_severity: Option<Vec<SeverityMatcher>>,
+    min_severity: Option<Severity>,
+) -> Result<Option<Vec<SeverityMatcher>>, HttpError> {
+    match (match_severity, min_severity) {
+        (Some(_), Some(_)) => Err(api::http_err!(
+            BAD_REQUEST,
+            "'min-severity' cannot be combined with 'match-severity'"
+        )),
+        (None, Some(min_severity)) => Ok(Some(vec![min_severity_matcher(min_severity)?])),
+        (match_severity, None) => Ok(match_severity),
+    }
+}
+
+/// Check whether any matcher still routes notifications to `target`, which is about to be
+/// deleted.
+///
+/// If `refuse` is set, this fails if the target is still referenced, otherwise only a warning
+/// is logged.
+pub fn ensure_unreferenced(config: &Config, target: &str, refuse: bool) -> Result<(), HttpError> {
+    let matchers = referencing_matchers(config, target)?;
+    if matchers.is_empty() {
+        return Ok(());
+    }
+
+    let matchers = matchers.join(", ");
+    if refuse {
+        return Err(api::http_err!(
+            BAD_REQUEST,
+            "cannot delete target '{target}', it is still used by matchers: {matchers}"
+        ));
+    }
+
+    tracing::warn!("deleting target '{target}', which is still used by matchers: {matchers}");
+    Ok(())
+}
+
+/// A private config entry, as reported by [`check_private_config_integrity`].
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct PrivateConfigEntry {
+    /// The section type of the entry, e.g. `gotify`.
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The name of the endpoint the entry belongs to.
+    pub name: String,
+}
+
+/// Result of [`check_private_config_integrity`].
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PrivateConfigIntegrity {
+    /// Endpoints which require a secret, but have no matching private config entry.
+    pub missing_secret: Vec<String>,
+    /// Private config entries without a matching endpoint in the public config.
+    pub orphaned: Vec<PrivateConfigEntry>,
+}
+
+impl PrivateConfigIntegrity {
+    /// Returns whether the public and private config match up.
+    pub fn is_ok(&self) -> bool {
+        self.missing_secret.is_empty() && self.orphaned.is_empty()
+    }
+}
+
+/// Parse the raw private notification config.
+///
+/// Every section is accepted regardless of its type, so entries of unknown or removed endpoint
+/// types are reported as well.
+fn parse_private_config(raw_private_config: &str) -> Result<SectionConfigData, Error> {
+    SectionConfig::new(&ENTITY_NAME_SCHEMA)
+        .allow_unknown_sections(true)
+        .parse("priv/notifications.cfg", raw_private_config)
+}
+
+/// Check that every endpoint which requires a secret has a private config entry with it, and
+/// that every private config entry belongs to an endpoint.
+///
+/// Gotify endpoints always require a token, SMTP endpoints require a password if a username is
+/// set.
+pub fn check_private_config_integrity(config: &Config) -> Result<PrivateConfigIntegrity, Error> {
+    let (_, raw_private_config) = config.write()?;
+    let private_config = parse_private_config(&raw_private_config)?;
+
+    let has_secret = |ty: &str, name: &str, key: &str| {
+        private_config
+            .sections
+            .get(name)
+            .is_some_and(|(section_ty, data)| section_ty == ty && data.get(key).is_some())
+    };
+
+    let mut integrity = PrivateConfigIntegrity::default();
+    let mut endpoints = Vec::new();
+
+    for endpoint in api::gotify::get_endpoints(config)? {
+        if !has_secret("gotify", &endpoint.name, "token") {
+            integrity.missing_secret.push(endpoint.name.clone());
+        }
+        endpoints.push(("gotify", endpoint.name));
+    }
+
+    for endpoint in api::smtp::get_endpoints(config)? {
+        if endpoint.username.is_some() && !has_secret("smtp", &endpoint.name, "password") {
+            integrity.missing_secret.push(endpoint.name.clone());
+        }
+        endpoints.push(("smtp", endpoint.name));
+    }
+
+    for endpoint in api::webhook::get_endpoints(config)? {
+        endpoints.push(("webhook", endpoint.name));
+    }
+
+    integrity.orphaned = private_config
+        .order
+        .iter()
+        .filter_map(|name| {
+            let (ty, _) = private_config.sections.get(name)?;
+            let known = endpoints
+                .iter()
+                .any(|(endpoint_ty, endpoint_name)| endpoint_ty == ty && endpoint_name == name);
+
+            (!known).then(|| PrivateConfigEntry {
+                ty: ty.clone(),
+                name: name.clone(),
+            })
+        })
+        .collect();
+
+    Ok(integrity)
+}
+
+/// How severe a [`LintFinding`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// The configuration works, but might not be as intended.
+    Info,
+    /// Notifications might not be delivered as expected.
+    Warning,
+    /// The configuration is broken.
+    Error,
+}
+
+/// The kind of a [`LintFinding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintKind {
+    /// A matcher references a target which does not exist.
+    UnknownTarget,
+    /// A matcher references a target which is disabled.
+    DisabledTarget,
+    /// A matcher does not route notifications to any target.
+    MatcherWithoutTargets,
+    /// No enabled matcher routes notifications to a target.
+    UnusedTarget,
+}
+
+/// A potential problem of the notification configuration found by [`lint_config`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LintFinding {
+    /// How severe the problem is.
+    pub severity: LintSeverity,
+    /// What was found.
+    pub kind: LintKind,
+    /// The name of the matcher or target the finding is about.
+    pub entity: String,
+    /// A human readable description of the finding.
+    pub message: String,
+}
+
+/// Check the `matchers` against the `targets`, a map of target names to whether the target is
+/// disabled.
+fn lint(matchers: &[MatcherConfig], targets: &HashMap<String, bool>) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+
+    for matcher in matchers {
+        if matcher.target.is_empty() {
+            findings.push(LintFinding {
+                severity: LintSeverity::Warning,
+                kind: LintKind::MatcherWithoutTargets,
+                entity: matcher.name.clone(),
+                message: format!("matcher '{}' has no targets", matcher.name),
+            });
+        }
+
+        for target in &matcher.target {
+            match targets.get(target) {
+                None => findings.push(LintFinding {
+                    severity: LintSeverity::Error,
+                    kind: LintKind::UnknownTarget,
+                    entity: matcher.name.clone(),
+                    message: format!(
+                        "matcher '{}' references unknown target '{target}'",
+                        matcher.name
+                    ),
+                }),
+                Some(true) => findings.push(LintFinding {
+                    severity: LintSeverity::Warning,
+                    kind: LintKind::DisabledTarget,
+                    entity: matcher.name.clone(),
+                    message: format!(
+                        "matcher '{}' references disabled target '{target}'",
+                        matcher.name
+                    ),
+                }),
+                Some(false) => (),
+            }
+        }
+    }
+
+    let mut unused: Vec<&String> = targets
+        .keys()
+        .filter(|target| {
+            !matchers.iter().any(|matcher| {
+                !matcher.disable.unwrap_or_default() && matcher.target.contains(*target)
+            })
+        })
+        .collect();
+    unused.sort();
+
+    for target in unused {
+        findings.push(LintFinding {
+            severity: LintSeverity::Info,
+            kind: LintKind::UnusedTarget,
+            entity: target.clone(),
+            message: format!("no enabled matcher routes notifications to target '{target}'"),
+        });
+    }
+
+    findings
+}
+
+/// Check the notification configuration for problems, like matchers referencing unknown or
+/// disabled targets, matchers without targets and targets no matcher routes notifications to.
+pub fn lint_config(config: &Config) -> Result<Vec<LintFinding>, HttpError> {
+    let mut targets = HashMap::new();
+
+    for endpoint in api::sendmail::get_endpoints(config)? {
+        targets.insert(endpoint.name, endpoint.disable.unwrap_or_default());
+    }
+    for endpoint in api::smtp::get_endpoints(config)? {
+        targets.insert(endpoint.name, endpoint.disable.unwrap_or_default());
+    }
+    for endpoint in api::gotify::get_endpoints(config)? {
+        targets.insert(endpoint.name, endpoint.disable.unwrap_or_default());
+    }
+    for endpoint in api::webhook::get_endpoints(config)? {
+        targets.insert(endpoint.name, endpoint.disable.unwrap_or_default());
+    }
+
+    Ok(lint(&api::matcher::get_matchers(config)?, &targets))
+}
+
+/// The timeout for connecting to an SMTP endpoint in [`probe_target`].
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The outcome of [`probe_target`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProbeStatus {
+    /// The endpoint was reached.
+    Reachable,
+    /// The endpoint could not be reached, or the connection failed.
+    Unreachable,
+    /// The endpoint type cannot be probed without sending a notification.
+    NotSupported,
+}
+
+/// Result of [`probe_target`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ProbeResult {
+    /// Whether the endpoint was reached.
+    pub status: ProbeStatus,
+    /// How long the probe took, in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// What the endpoint responded with, or why it could not be reached.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl ProbeResult {
+    /// Run `probe`, measuring how long it takes.
+    fn measure(probe: impl FnOnce() -> Result<String, Error>) -> Self {
+        let start = Instant::now();
+        let (status, message) = match probe() {
+            Ok(message) => (ProbeStatus::Reachable, message),
+            Err(err) => (ProbeStatus::Unreachable, err.to_string()),
+        };
+
+        Self {
+            status,
+            latency_ms: Some(start.elapsed().as_millis() as u64),
+            message: Some(message),
+        }
+    }
+
+    fn not_supported(message: &str) -> Self {
+        Self {
+            status: ProbeStatus::NotSupported,
+            latency_ms: None,
+            message: Some(message.to_string()),
+        }
+    }
+}
+
+/// Open a TCP connection to `host` on `port`, trying all addresses `host` resolves to.
+fn connect(host: &str, port: u16) -> Result<TcpStream, Error> {
+    let addresses = (host, port)
+        .to_socket_addrs()
+        .map_err(|err| format_err!("failed to resolve '{host}': {err}"))?;
+
+    let mut last_err = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(&address, PROBE_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    match last_err {
+        Some(err) => bail!("failed to connect to {host}:{port}: {err}"),
+        None => bail!("'{host}' did not resolve to any address"),
+    }
+}
+
+/// Returns the port of the SMTP `endpoint`, defaulting to the standard port of its mode.
+fn smtp_port(endpoint: &SmtpConfig) -> u16 {
+    endpoint
+        .port
+        .unwrap_or(match endpoint.mode.unwrap_or_default() {
+            SmtpMode::Insecure => 25,
+            SmtpMode::Starttls => 587,
+            SmtpMode::Tls => 465,
+        })
+}
+
+/// Open a TCP connection to an SMTP endpoint.
+///
+/// Nothing is sent, talking SMTP and TLS is left to proxmox-notify. Mode mismatches, TLS and
+/// certificate problems and rejected credentials are reported when testing the target instead,
+/// see [`explain_test_failure`].
+fn probe_smtp(endpoint: &SmtpConfig) -> Result<String, Error> {
+    let port = smtp_port(endpoint);
+    connect(&endpoint.server, port)?;
+
+    Ok(format!("connected to {}:{port}", endpoint.server))
+}
+
+/// Returns whether the SMTP reply `code` rejects the credentials, see RFC 4954.
+fn is_auth_failure(code: u16) -> bool {
+    matches!(code, 534 | 535)
+}
+
+/// Returns the code of the SMTP reply which rejected a command, as contained in the message of a
+/// failed send, e.g. `535` in `permanent error (535): 5.7.8 bad credentials`.
+fn smtp_reply_code(message: &str) -> Option<u16> {
+    let (_, reply) = message.split_once("permanent error (")?;
+    reply.get(..3)?.parse().ok()
+}
+
+/// Returns whether the message of a failed send says that the server did not answer in time.
+fn is_timeout_message(message: &str) -> bool {
+    message.contains("timed out") || message.contains("Resource temporarily unavailable")
+}
+
+/// Classify why sending via an SMTP endpoint with `mode` failed, from the error `message`
+/// proxmox-notify returned.
+///
+/// Returns `None` if the message does not point to a mode mismatch, a certificate validation
+/// failure, another TLS handshake failure or rejected credentials.
+fn classify_smtp_failure(message: &str, mode: SmtpMode) -> Option<String> {
+    let reason = if message.contains("STARTTLS is not supported") {
+        "mode mismatch: the server does not offer STARTTLS, use mode 'tls' or 'insecure'"
+    } else if message.contains("wrong version number") {
+        // the server sent plain text, most likely its SMTP greeting
+        "mode mismatch: TLS handshake failed, the server might expect STARTTLS (mode \
+         'starttls') or no TLS at all (mode 'insecure')"
+    } else if !matches!(mode, SmtpMode::Tls) && is_timeout_message(message) {
+        "mode mismatch: the server did not send an SMTP greeting, it might expect implicit TLS \
+         (mode 'tls')"
+    } else if message.contains("certificate verify failed") {
+        "certificate validation failed"
+    } else if message.contains("tls error") {
+        "TLS handshake failed"
+    } else {
+        let code = smtp_reply_code(message).filter(|code| is_auth_failure(*code))?;
+        return Some(format!("authentication failed ({code})"));
+    };
+
+    Some(reason.to_string())
+}
+
+/// Explain why testing the target `name` failed with `err`, if it is an SMTP target.
+///
+/// The error returned when sending contains the message of the SMTP transport, which is
+/// classified into mode mismatches, certificate validation failures, other TLS handshake failures
+/// and rejected credentials, see [`classify_smtp_failure`]. Other errors are returned as they
+/// are.
+pub fn explain_test_failure(config: &Config, name: &str, err: HttpError) -> HttpError {
+    let Some(endpoint) = api::smtp::get_endpoints(config)
+        .unwrap_or_default()
+        .into_iter()
+        .find(|endpoint| endpoint.name == name)
+    else {
+        return err;
+    };
+
+    match classify_smtp_failure(&err.message, endpoint.mode.unwrap_or_default()) {
+        Some(reason) => HttpError::new(err.code, format!("{} ({reason})", err.message)),
+        None => err,
+    }
+}
+
+/// Send a request with `method` to `url`, without a body, via the HTTP proxy in `proxy_config`
+/// if one is set.
+///
+/// Any response counts as reachable, the status is part of the returned message.
+fn probe_http(
+    method: &str,
+    url: &str,
+    proxy_config: Option<&ProxyConfig>,
+) -> Result<String, Error> {
+    let client = Client::new(HttpOptions {
+        proxy_config: proxy_config.cloned(),
+        ..Default::default()
+    });
+    let request = http::Request::builder()
+        .method(method)
+        .uri(url)
+        .body(String::new())?;
+
+    let response = client
+        .request(request)
+        .map_err(|err| format_err!("request to {url} failed: {err}"))?;
+
+    Ok(format!("{method} {url}: {}", response.status()))
+}
+
+/// Returns the origin, i.e. scheme, host and port, of a webhook `url`.
+///
+/// Webhook URLs can contain templates, e.g. for secrets, which are only rendered when sending,
+/// so only the origin is probed. Returns `None` if the origin itself contains a template.
+fn webhook_origin(url: &str) -> Option<&str> {
+    let authority_start = url.find("://")? + 3;
+    let origin = match url[authority_start..].find('/') {
+        Some(end) => &url[..authority_start + end + 1],
+        None => url,
+    };
+
+    (!origin.contains("{{")).then_some(origin)
+}
+
+/// Check whether the notification target `name` is reachable, without sending a notification.
+///
+/// For SMTP targets, this only opens a TCP connection to the server. For Gotify
+/// targets, the unauthenticated version endpoint of the server is queried, and for webhook
+/// targets, a `HEAD` request is sent to the origin of the webhook URL. Sendmail targets cannot
+/// be probed, as mails are handed to the local mail system.
+///
+/// Gotify and webhook targets are probed via the HTTP proxy in `proxy_config`, like when sending
+/// a notification.
+pub fn probe_target(
+    config: &Config,
+    name: &str,
+    proxy_config: Option<&ProxyConfig>,
+) -> Result<ProbeResult, HttpError> {
+    if let Some(endpoint) = api::smtp::get_endpoints(config)?
+        .into_iter()
+        .find(|endpoint| endpoint.name == name)
+    {
+        return Ok(ProbeResult::measure(|| probe_smtp(&endpoint)));
+    }
+
+    if let Some(endpoint) = api::gotify::get_endpoints(config)?
+        .into_iter()
+        .find(|endpoint| endpoint.name == name)
+    {
+        let url = format!("{}/version", endpoint.server.trim_end_matches('/'));
+        return Ok(ProbeResult::measure(|| {
+            probe_http("GET", &url, proxy_config)
+        }));
+    }
+
+    if let Some(endpoint) = api::webhook::get_endpoints(config)?
+        .into_iter()
+        .find(|endpoint| endpoint.name == name)
+    {
+        return Ok(match webhook_origin(&endpoint.url) {
+            Some(origin) => ProbeResult::measure(|| probe_http("HEAD", origin, proxy_config)),
+            None => ProbeResult::not_supported("the webhook URL's host is a template"),
+        });
+    }
+
+    if api::sendmail::get_endpoints(config)?
+        .iter()
+        .any(|endpoint| endpoint.name == name)
+    {
+        return Ok(ProbeResult::not_supported(
+            "sendmail targets are delivered by the local mail system",
+        ));
+    }
+
+    Err(api::http_err!(NOT_FOUND, "target '{name}' does not exist"))
+}
+
+/// The URL used when rendering the headers and the body of a webhook endpoint on their own.
+const SAMPLE_WEBHOOK_URL: &str = "http://localhost/";
+
+/// Render the request of a webhook endpoint for a sample notification, the same way
+/// proxmox-notify does when sending to it.
+fn render_webhook_request(endpoint: WebhookConfig) -> Result<(), Error> {
+    let notification = Notification::from_template(
+        Severity::Info,
+        "test",
+        json!({ "target": endpoint.name }),
+        HashMap::new(),
+    );
+
+    let private_config = WebhookPrivateConfig {
+        name: endpoint.name.clone(),
+        secret: endpoint.secret.clone(),
+    };
+
+    WebhookEndpoint {
+        config: endpoint,
+        private_config,
+    }
+    .build_request(&notification)
+    .map(|_| ())
+    .map_err(|err| format_err!("invalid template: {err}"))
+}
+
+/// Check the configuration of a webhook endpoint before saving it.
+///
+/// This checks that the URL uses `http` or `https` and that the header names are valid. The URL,
+/// each header value and the body are then rendered on their own for a sample notification with
+/// proxmox-notify's webhook templating, so errors name the offending property.
+pub fn validate_webhook_config(endpoint: &WebhookConfig) -> Result<(), Error> {
+    let url = &endpoint.url;
+    let scheme = url.split_once("://").map(|(scheme, _)| scheme);
+    if !matches!(scheme, Some("http" | "https")) {
+        bail!("url: unsupported scheme, expected 'http' or 'https'");
+    }
+    if !url.contains("{{") {
+        url::Url::parse(url).map_err(|err| format_err!("url: invalid URL: {err}"))?;
+    }
+
+    let sample = |url: &str, header, body| WebhookConfig {
+        url: url.to_string(),
+        header,
+        body,
+        ..endpoint.clone()
+    };
+
+    render_webhook_request(sample(url, Vec::new(), None))
+        .map_err(|err| format_err!("url: {err}"))?;
+
+    for header in &endpoint.header {
+        let name = &header.name;
+        if http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+            bail!("header: invalid header name '{name}'");
+        }
+
+        render_webhook_request(sample(SAMPLE_WEBHOOK_URL, vec![header.clone()], None))
+            .map_err(|err| format_err!("header '{name}': {err}"))?;
+    }
+
+    if let Some(body) = &endpoint.body {
+        render_webhook_request(sample(SAMPLE_WEBHOOK_URL, Vec::new(), Some(body.clone())))
+            .map_err(|err| format_err!("body: {err}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+
+    use super::*;
+
+    fn matcher(name: &str, severity: &str, target: &[&str]) -> MatcherConfig {
+        MatcherConfig {
+            name: name.into(),
+            match_severity: vec![severity.parse::<SeverityMatcher>().unwrap()],
+            match_field: Vec::new(),
+            match_calendar: Vec::new(),
+            target: target.iter().map(|target| target.to_string()).collect(),
+            mode: None,
+            invert_match: None,
+            comment: None,
+            disable: None,
+            origin: None,
+        }
+    }
+
+    #[test]
+    fn min_severity() {
+        let mut pager = matcher("pager", "info", &["pagerduty"]);
+        pager.match_severity = resolve_min_severity(None, Some(Severity::Warning))
+            .unwrap()
+            .expect("severity matcher");
+
+        let routed = |severity| {
+            let notification =
+                Notification::from_template(severity, "test", Default::default(), HashMap::new());
+            !check_matches(&[&pager], &notification).is_empty()
+        };
+
+        assert!(!routed(Severity::Info));
+        assert!(!routed(Severity::Notice));
+        assert!(routed(Severity::Warning));
+        assert!(routed(Severity::Error));
+        assert!(routed(Severity::Unknown));
+
+        assert!(resolve_min_severity(None, None).unwrap().is_none());
+
+        let explicit = vec!["error".parse::<SeverityMatcher>().unwrap()];
+        assert_eq!(
+            resolve_min_severity(Some(explicit.clone()), None)
+                .unwrap()
+                .map(|matchers| matchers.len()),
+            Some(1)
+        );
+
+        let err = resolve_min_severity(Some(explicit), Some(Severity::Warning)).unwrap_err();
+        assert_eq!(err.code, http::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            err.message,
+            "'min-severity' cannot be combined with 'match-severity'"
+        );
+    }
+
+    #[test]
+    fn mail_recipients_resolve_users() {
+        let lookup_email = |user: &str| match user {
+            "root@pam" => Some("root@example.com".to_string()),
+            "alice@pve" => Some("alice@example.com".to_string()),
+            _ => None,
+        };
+
+        let mailto = ["root@example.com".to_string()];
+        let mailto_user = [
+            "root@pam".to_string(),
+            "alice@pve".to_string(),
+            "nomail@pve".to_string(),
+        ];
+
+        assert_eq!(mail_recipients(&mailto, &mailto_user, &lookup_email), 2);
+        assert_eq!(mail_recipients(&[], &mailto_user, &lookup_email), 2);
+        assert_eq!(mail_recipients(&mailto, &[], &lookup_email), 1);
+    }
+
+    #[test]
+    fn fanout_to_mail_target() {
+        let matchers = [
+            matcher("errors", "error", &["mail-admins", "gotify"]),
+            matcher("everything", "info,notice,warning,error", &["mail-admins"]),
+        ];
+        let recipients = HashMap::from([("mail-admins".to_string(), 3), ("gotify".to_string(), 1)]);
+
+        let notification = |severity| {
+            Notification::from_template(severity, "test", Default::default(), HashMap::new())
+        };
+
+        assert_eq!(
+            estimate_fanout(&matchers, &recipients, &notification(Severity::Info)),
+            Fanout {
+                targets: 1,
+                recipients: 3,
+            }
+        );
+        assert_eq!(
+            estimate_fanout(&matchers, &recipients, &notification(Severity::Error)),
+            Fanout {
+                targets: 2,
+                recipients: 4,
+            }
+        );
+        assert_eq!(
+            estimate_fanout(&[], &recipients, &notification(Severity::Error)),
+            Fanout::default()
+        );
+    }
+
+    #[test]
+    fn hop_limit() {
+        let fields = |hops: &str| HashMap::from([(HOP_COUNT_FIELD.to_string(), hops.to_string())]);
+
+        assert!(!exceeds_hop_limit(&HashMap::new(), DEFAULT_MAX_HOPS));
+        assert!(!exceeds_hop_limit(&fields("5"), DEFAULT_MAX_HOPS));
+        assert!(exceeds_hop_limit(&fields("6"), DEFAULT_MAX_HOPS));
+        assert!(exceeds_hop_limit(&fields("2"), 1));
+        assert!(!exceeds_hop_limit(&fields("invalid"), 1));
+    }
+
+    #[test]
+    fn bounded_history() {
+        let record = |template: &str| NotificationRecord {
+            timestamp: 0,
+            severity: Severity::Info,
+            template: template.to_string(),
+            targets: vec!["mail".to_string()],
+            throttled: Vec::new(),
+            error: None,
+        };
+
+        let mut history = History(VecDeque::new());
+        for template in ["first", "second", "third", "fourth"] {
+            history.push(record(template), 3);
+        }
+
+        let templates = |records: Vec<NotificationRecord>| -> Vec<String> {
+            records.into_iter().map(|record| record.template).collect()
+        };
+
+        assert_eq!(templates(history.recent(10)), ["fourth", "third", "second"]);
+        assert_eq!(templates(history.recent(1)), ["fourth"]);
+        assert!(history.recent(0).is_empty());
+    }
+
+    #[test]
+    fn throttled_matchers() {
+        let matchers = [
+            matcher("flapping", "error", &["mail"]),
+            matcher("unlimited", "error", &["mail"]),
+            matcher("info", "info", &["mail"]),
+        ];
+        let min_intervals =
+            BTreeMap::from([("flapping".to_string(), 300), ("info".to_string(), 300)]);
+
+        let notification = |severity| {
+            Notification::from_template(severity, "test", Default::default(), HashMap::new())
+        };
+        let error = notification(Severity::Error);
+
+        let mut limiter = RateLimiter::default();
+        let mut send = |notification: &Notification, now| {
+            let throttled = limiter.throttled(&matchers, notification, &min_intervals, now);
+            let routing: Vec<MatcherConfig> = matchers
+                .iter()
+                .filter(|matcher| !throttled.contains(&matcher.name))
+                .cloned()
+                .collect();
+            limiter.record(&routing, notification, &min_intervals, now);
+            throttled
+        };
+
+        assert!(send(&error, 1000).is_empty());
+        // a second send right after the first one is suppressed
+        assert_eq!(send(&error, 1010), ["flapping"]);
+        assert_eq!(send(&error, 1299), ["flapping"]);
+        assert!(send(&error, 1300).is_empty());
+        assert_eq!(send(&error, 1301), ["flapping"]);
+
+        // matchers are only throttled by notifications they match
+        assert!(send(&notification(Severity::Info), 1302).is_empty());
+        assert_eq!(send(&notification(Severity::Info), 1303), ["info"]);
+
+        // the window only starts once a notification was actually sent
+        let mut limiter = RateLimiter::default();
+        assert!(
+            limiter
+                .throttled(&matchers, &error, &min_intervals, 2000)
+                .is_empty()
+        );
+        assert!(
+            limiter
+                .throttled(&matchers, &error, &min_intervals, 2001)
+                .is_empty()
+        );
+        limiter.record(&matchers, &error, &min_intervals, 2002);
+        assert_eq!(
+            limiter.throttled(&matchers, &error, &min_intervals, 2003),
+            ["flapping"]
+        );
+    }
+
+    #[test]
+    fn min_interval_property() {
+        let raw = "matcher: flapping\n\
+                   \tmin-interval 300\n\
+                   \tmode all\n\
+                   \n\
+                   sendmail: mail\n\
+                   \tmailto-user root@pam\n\
+                   \tcomment min-interval 5\n\
+                   \n\
+                   matcher: other\n\
+                   \ttarget mail\n";
+
+        let (config, min_intervals) = split_min_intervals(raw).unwrap();
+        assert_eq!(min_intervals, BTreeMap::from([("flapping".into(), 300)]));
+        assert!(!config.contains("min-interval 300"));
+        assert!(config.contains("\tcomment min-interval 5\n"));
+
+        assert_eq!(merge_min_intervals(&config, &min_intervals), raw);
+
+        let err = split_min_intervals("matcher: flapping\n\tmin-interval soon\n").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid min-interval 'soon' of matcher 'flapping'")
+        );
+    }
+
+    #[test]
+    fn disable_matchers_temporarily() {
+        let (mut config, _) = parse_config(
+            "sendmail: mail\n\
+             \tmailto-user root@pam\n\
+             \n\
+             matcher: first\n\
+             \ttarget mail\n\
+             \n\
+             matcher: second\n\
+             \ttarget mail\n\
+             \tdisable false\n",
+            "",
+        )
+        .unwrap();
+        let (raw, _) = config.write().unwrap();
+
+        let names = ["first".to_string(), "second".to_string()];
+        let disabled = with_matchers_disabled(&mut config, &names, |config| {
+            names
+                .iter()
+                .map(|name| api::matcher::get_matcher(config, name).unwrap().disable)
+                .collect::<Vec<_>>()
+        })
+        .unwrap();
+        assert_eq!(disabled, [Some(true), Some(true)]);
+
+        assert_eq!(config.write().unwrap().0, raw);
+    }
+
+    #[test]
+    fn job_result_template() {
+        let details = BTreeMap::from([
+            ("target".to_string(), "node2".to_string()),
+            ("duration".to_string(), "42s".to_string()),
+        ]);
+
+        assert_eq!(
+            job_result_template_data("node1", "replication", JobStatus::Error, &details),
+            json!({
+                "hostname": "node1",
+                "job-type": "replication",
+                "status": "error",
+                "details": {
+                    "schema": {
+                        "columns": [
+                            { "label": "Property", "id": "property" },
+                            { "label": "Value", "id": "value" },
+                        ],
+                    },
+                    "data": [
+                        { "property": "duration", "value": "42s" },
+                        { "property": "target", "value": "node2" },
+                    ],
+                },
+            })
+        );
+
+        assert_eq!(JobStatus::Ok.severity(), Severity::Info);
+        assert_eq!(JobStatus::Warning.severity(), Severity::Warning);
+        assert_eq!(JobStatus::Error.severity(), Severity::Error);
+    }
+
+    #[test]
+    fn missing_template() {
+        proxmox_notify::context::set_context(&TEMPLATE_CONTEXT);
+
+        let data = json!({ "target": "mail" });
+        check_template("test", &data).unwrap();
+
+        let err = check_template(JOB_RESULT_TEMPLATE, &data).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("cannot render notification template 'job-result'")
+        );
+    }
+
+    #[test]
+    fn render_unknown_target_kind() {
+        let err = render_template("test", &Value::Null, Some("pager")).unwrap_err();
+        assert_eq!(err.to_string(), "unknown target kind 'pager'");
+    }
+
+    /// Notification context providing the templates of the `test` and `updates` notifications.
+    #[derive(Debug)]
+    struct TemplateContext;
+
+    impl proxmox_notify::context::Context for TemplateContext {
+        fn lookup_email_for_user(&self, _user: &str) -> Option<String> {
+            None
+        }
+
+        fn default_sendmail_author(&self) -> String {
+            "Proxmox VE".to_string()
+        }
+
+        fn default_sendmail_from(&self) -> String {
+            "root".to_string()
+        }
+
+        fn http_proxy_config(&self) -> Option<String> {
+            None
+        }
+
+        fn default_config(&self) -> &'static str {
+            ""
+        }
+
+        fn lookup_template(
+            &self,
+            filename: &str,
+            _namespace: Option<&str>,
+            _source: renderer::TemplateSource,
+        ) -> Result<Option<String>, proxmox_notify::Error> {
+            let template = match filename {
+                "test-subject.txt.hbs" => "Test notification",
+                "test-body.txt.hbs" => "This is a test of the target '{{ target }}'.",
+                "test-body.html.hbs" => "<p>This is a test of the target '{{ target }}'.</p>",
+                "updates-subject.txt.hbs" => "{{ count }} updates for {{ hostname }}",
+                "updates-body.txt.hbs" => "Updates for {{ hostname }}:\n{{ table updates }}",
+                "updates-body.html.hbs" => "<h1>{{ hostname }}</h1>{{ table updates }}",
+                _ => return Ok(None),
+            };
+            Ok(Some(template.to_string()))
+        }
+    }
+
+    static TEMPLATE_CONTEXT: TemplateContext = TemplateContext;
+
+    #[test]
+    fn render() {
+        proxmox_notify::context::set_context(&TEMPLATE_CONTEXT);
+
+        let data = json!({
+            "count": 1,
+            "hostname": "pve<1>",
+            "updates": {
+                "schema": {
+                    "columns": [
+                        { "label": "Package", "id": "package" },
+                        { "label": "New Version", "id": "version" },
+                    ],
+                },
+                "data": [
+                    { "package": "pve-manager", "version": "9.0.1" },
+                ],
+            },
+        });
+
+        let rendered = render_template("updates", &data, Some("smtp")).unwrap();
+        assert_eq!(rendered.subject, "1 updates for pve<1>");
+        assert!(rendered.body_text.starts_with("Updates for pve<1>:\n"));
+        assert!(rendered.body_text.contains("pve-manager"));
+        assert!(rendered.body_text.contains("9.0.1"));
+
+        let body_html = rendered.body_html.expect("mail targets get an HTML body");
+        assert!(body_html.starts_with("<h1>pve&lt;1&gt;</h1>"));
+        assert!(body_html.contains("<table"));
+        assert!(body_html.contains("pve-manager"));
+
+        let rendered = render_template("updates", &data, Some("gotify")).unwrap();
+        assert_eq!(rendered.subject, "1 updates for pve<1>");
+        assert_eq!(rendered.body_html, None);
+
+        assert!(render_template("missing", &data, None).is_err());
+    }
+
+    #[test]
+    fn referencing() {
+        let matchers = [
+            matcher("errors", "error", &["mail", "gotify"]),
+            matcher("all", "info", &["mail"]),
+            matcher("other", "info", &["webhook"]),
+        ];
+
+        assert_eq!(matchers_referencing(&matchers, "mail"), ["all", "errors"]);
+        assert_eq!(matchers_referencing(&matchers, "gotify"), ["errors"]);
+        assert!(matchers_referencing(&matchers, "unused").is_empty());
+    }
+
+    #[test]
+    fn private_config_integrity() {
+        let config = Config::new(
+            "gotify: gotify-ok\n\
+             \tserver https://gotify.example.com\n\
+             \n\
+             gotify: gotify-missing\n\
+             \tserver https://gotify.example.com\n\
+             \n\
+             smtp: smtp-anonymous\n\
+             \tserver mail.example.com\n\
+             \tfrom-address pve@example.com\n\
+             \tmailto admin@example.com\n\
+             \n\
+             smtp: smtp-auth\n\
+             \tserver mail.example.com\n\
+             \tfrom-address pve@example.com\n\
+             \tmailto admin@example.com\n\
+             \tusername pve\n",
+            "gotify: gotify-ok\n\
+             \ttoken secret\n\
+             \n\
+             smtp: smtp-auth\n\
+             \tpassword secret\n\
+             \n\
+             gotify: removed\n\
+             \ttoken secret\n",
+        )
+        .unwrap();
+
+        let integrity = check_private_config_integrity(&config).unwrap();
+        assert!(!integrity.is_ok());
+        assert_eq!(
+            integrity,
+            PrivateConfigIntegrity {
+                missing_secret: vec!["gotify-missing".to_string()],
+                orphaned: vec![PrivateConfigEntry {
+                    ty: "gotify".to_string(),
+                    name: "removed".to_string(),
+                }],
+            }
+        );
+
+        let config = Config::new(
+            "gotify: gotify-ok\n\tserver https://gotify.example.com\n",
+            "gotify: gotify-ok\n\ttoken secret\n",
+        )
+        .unwrap();
+        assert!(check_private_config_integrity(&config).unwrap().is_ok());
+    }
+
+    #[test]
+    fn lint_findings() {
+        let mut disabled = matcher("disabled", "error", &["unused"]);
+        disabled.disable = Some(true);
+
+        let matchers = [
+            matcher("ok", "error", &["mail"]),
+            matcher("broken", "error", &["mail", "removed", "off"]),
+            matcher("empty", "error", &[]),
+            disabled,
+        ];
+        let targets = HashMap::from([
+            ("mail".to_string(), false),
+            ("off".to_string(), true),
+            ("unused".to_string(), false),
+        ]);
+
+        let findings: Vec<(LintKind, String)> = lint(&matchers, &targets)
+            .into_iter()
+            .map(|finding| (finding.kind, finding.entity))
+            .collect();
+
+        assert_eq!(
+            findings,
+            [
+                (LintKind::UnknownTarget, "broken".to_string()),
+                (LintKind::DisabledTarget, "broken".to_string()),
+                (LintKind::MatcherWithoutTargets, "empty".to_string()),
+                (LintKind::UnusedTarget, "unused".to_string()),
+            ]
+        );
+
+        assert!(
+            lint(
+                &matchers[..1],
+                &HashMap::from([("mail".to_string(), false)])
+            )
+            .is_empty()
+        );
+    }
+
+    /// Run a mock SMTP server for a single connection, which sends the `replies` one by one,
+    /// each after receiving a line from the client.
+    ///
+    /// Returns the port and a handle for the lines received from the client.
+    fn mock_smtp_server(
+        greeting: &'static str,
+        replies: &'static [&'static str],
+    ) -> (u16, std::thread::JoinHandle<Vec<String>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            stream.write_all(greeting.as_bytes()).unwrap();
+
+            let mut received = Vec::new();
+            for reply in replies {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                received.push(line);
+                stream.write_all(reply.as_bytes()).unwrap();
+            }
+            received
+        });
+
+        (port, server)
+    }
+
+    fn smtp_config(port: u16, mode: &str) -> Config {
+        Config::new(
+            &format!(
+                "smtp: local\n\
+                 \tserver 127.0.0.1\n\
+                 \tport {port}\n\
+                 \tmode {mode}\n\
+                 \tfrom-address pve@example.com\n\
+                 \tmailto admin@example.com\n\
+                 \n\
+                 sendmail: mail\n\
+                 \tmailto admin@example.com\n"
+            ),
+            "",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn smtp_failures() {
+        let classify = |message: &str, mode| classify_smtp_failure(message, mode);
+
+        assert_eq!(
+            classify(
+                "could not notify via endpoint(s): local: internal client error: STARTTLS is \
+                 not supported by this server",
+                SmtpMode::Starttls
+            )
+            .unwrap(),
+            "mode mismatch: the server does not offer STARTTLS, use mode 'tls' or 'insecure'"
+        );
+        assert!(
+            classify(
+                "could not notify via endpoint(s): local: tls error: error:0A00010B:SSL \
+                 routines:ssl3_get_record:wrong version number",
+                SmtpMode::Tls
+            )
+            .unwrap()
+            .starts_with("mode mismatch: TLS handshake failed")
+        );
+        assert!(
+            classify(
+                "could not notify via endpoint(s): local: network error: Resource temporarily \
+                 unavailable (os error 11)",
+                SmtpMode::Starttls
+            )
+            .unwrap()
+            .starts_with("mode mismatch: the server did not send an SMTP greeting")
+        );
+        assert_eq!(
+            classify(
+                "could not notify via endpoint(s): local: tls error: error:0A000086:SSL \
+                 routines:tls_post_process_server_certificate:certificate verify failed",
+                SmtpMode::Starttls
+            )
+            .unwrap(),
+            "certificate validation failed"
+        );
+        assert_eq!(
+            classify(
+                "could not notify via endpoint(s): local: permanent error (535): 5.7.8 bad \
+                 credentials",
+                SmtpMode::Insecure
+            )
+            .unwrap(),
+            "authentication failed (535)"
+        );
+        assert_eq!(
+            classify(
+                "could not notify via endpoint(s): local: permanent error (550): mailbox \
+                 unavailable",
+                SmtpMode::Insecure
+            ),
+            None
+        );
+        assert_eq!(classify("could not render template", SmtpMode::Tls), None);
+    }
+
+    #[test]
+    fn test_smtp_without_starttls() {
+        proxmox_notify::context::set_context(&TEMPLATE_CONTEXT);
+
+        let (port, server) = mock_smtp_server(
+            "220 localhost ESMTP test\r\n",
+            &["250-localhost\r\n250 PIPELINING\r\n"],
+        );
+        let config = smtp_config(port, "starttls");
+
+        let err = api::common::test_target(&config, "local")
+            .map_err(|err| explain_test_failure(&config, "local", err))
+            .unwrap_err();
+        assert!(
+            err.message.ends_with(
+                "(mode mismatch: the server does not offer STARTTLS, use mode 'tls' or \
+                 'insecure')"
+            ),
+            "{}",
+            err.message
+        );
+        assert!(server.join().unwrap()[0].starts_with("EHLO "));
+    }
+
+    #[test]
+    fn probe_smtp_target() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let config = smtp_config(port, "insecure");
+
+        let result = probe_target(&config, "local", None).unwrap();
+        assert_eq!(result.status, ProbeStatus::Reachable);
+        assert_eq!(
+            result.message.unwrap(),
+            format!("connected to 127.0.0.1:{port}")
+        );
+
+        drop(listener);
+        let result = probe_target(&config, "local", None).unwrap();
+        assert_eq!(result.status, ProbeStatus::Unreachable);
+        assert!(
+            result
+                .message
+                .unwrap()
+                .starts_with(&format!("failed to connect to 127.0.0.1:{port}: "))
+        );
+
+        let result = probe_target(&config, "mail", None).unwrap();
+        assert_eq!(result.status, ProbeStatus::NotSupported);
+        assert_eq!(result.latency_ms, None);
+
+        assert!(probe_target(&config, "missing", None).is_err());
+    }
+
+    #[test]
+    fn webhook_origins() {
+        assert_eq!(
+            webhook_origin("https://chat.example.com/hooks/{{ secrets.token }}"),
+            Some("https://chat.example.com/")
+        );
+        assert_eq!(
+            webhook_origin("http://10.0.0.1:8080"),
+            Some("http://10.0.0.1:8080")
+        );
+        assert_eq!(webhook_origin("https://{{ secrets.host }}/hook"), None);
+        assert_eq!(webhook_origin("not a url"), None);
+    }
+
+    #[test]
+    fn webhook_templates() {
+        proxmox_notify::context::set_context(&TEMPLATE_CONTEXT);
+
+        let encode = |template: &str| openssl::base64::encode_block(template.as_bytes());
+        let endpoint = |body: &str| -> WebhookConfig {
+            serde_json::from_value(json!({
+                "name": "hook",
+                "url": "https://example.com/{{ url-encode fields.hostname }}",
+                "method": "post",
+                "header": [format!("name=X-Token,value={}", encode("{{ secrets.token }}"))],
+                "secret": [format!("name=token,value={}", encode("secret"))],
+                "body": encode(body),
+            }))
+            .unwrap()
+        };
+
+        for template in [
+            "plain text",
+            "{{ title }}: {{ message }}",
+            "{{{ json fields }}} {{~ url-encode secrets.token ~}}",
+            "{{#if fields.hostname}}{{ fields.hostname }}{{else}}unknown{{/if}}",
+            "{{#each fields}}{{@key}}={{ this }} {{ escape name }}{{/each}}",
+            "{{! a comment }}{{ severity }} at {{ timestamp }}",
+            r#"{"text": {"title": "{{ escape title }}"}}"#,
+            r#"{{#if (eq severity "error")}}{{ lookup fields "job id" }}{{/if}}"#,
+            r#"{{#if (and (ne severity "info") (or fields.a fields.b))}}!{{/if}}"#,
+        ] {
+            validate_webhook_config(&endpoint(template)).unwrap();
+        }
+
+        for template in ["{{ title", "{{#if title}}", "{{#if title}}{{/each}}"] {
+            let err = validate_webhook_config(&endpoint(template)).unwrap_err();
+            assert!(
+                err.to_string().starts_with("body: invalid template"),
+                "{template}"
+            );
+        }
+
+        let mut broken = endpoint("");
+        broken.url = "https://example.com/{{ title".to_string();
+        let err = validate_webhook_config(&broken).unwrap_err();
+        assert!(err.to_string().starts_with("url: invalid template"));
+
+        broken.url = "ftp://example.com/".to_string();
+        let err = validate_webhook_config(&broken).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "url: unsupported scheme, expected 'http' or 'https'"
+        );
+    }
+}
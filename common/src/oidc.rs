@@ -0,0 +1,357 @@
+//! Helpers for the OpenID Connect support which are not covered by [`proxmox_openid`].
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Error, bail, format_err};
+use serde_json::Value;
+
+use proxmox_http::client::sync::Client;
+use proxmox_http::{HttpClient, HttpOptions, ProxyConfig};
+use proxmox_sys::fs::CreateOptions;
+
+/// Create an HTTP client, connecting via the HTTP proxy set in the `all_proxy` environment
+/// variable, if any.
+///
+/// This is the proxy [`proxmox_openid`] uses for its requests, so all requests to the provider
+/// go through the same proxy.
+fn http_client() -> Result<Client, Error> {
+    let options = HttpOptions {
+        proxy_config: ProxyConfig::from_proxy_env()?,
+        ..Default::default()
+    };
+
+    Ok(Client::new(options))
+}
+
+/// Fetch the raw provider metadata of the OpenID provider `issuer_url`.
+///
+/// See <https://openid.net/specs/openid-connect-discovery-1_0.html#ProviderConfig>.
+pub fn fetch_provider_metadata(issuer_url: &str) -> Result<Value, Error> {
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer_url.trim_end_matches('/')
+    );
+
+    let client = http_client()?;
+    let response = client
+        .get(&url, None)
+        .map_err(|err| format_err!("failed to query provider metadata from {url}: {err}"))?;
+
+    if !response.status().is_success() {
+        bail!(
+            "failed to query provider metadata from {url}: {}",
+            response.status()
+        );
+    }
+
+    serde_json::from_str(response.body())
+        .map_err(|err| format_err!("failed to parse provider metadata from {url}: {err}"))
+}
+
+/// Build the URL for an RP-initiated logout, if the provider advertises an `end_session_endpoint`
+/// in its `metadata`.
+///
+/// See <https://openid.net/specs/openid-connect-rpinitiated-1_0.html#RPLogout>.
+pub fn end_session_url(
+    metadata: &Value,
+    client_id: &str,
+    id_token_hint: Option<&str>,
+    post_logout_redirect: Option<&str>,
+) -> Result<Option<String>, Error> {
+    let Some(endpoint) = metadata.get("end_session_endpoint") else {
+        return Ok(None);
+    };
+
+    let endpoint = endpoint
+        .as_str()
+        .ok_or_else(|| format_err!("invalid end_session_endpoint in provider metadata"))?;
+
+    let mut url = url::Url::parse(endpoint)
+        .map_err(|err| format_err!("invalid end_session_endpoint '{endpoint}': {err}"))?;
+
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("client_id", client_id);
+        if let Some(id_token_hint) = id_token_hint {
+            query.append_pair("id_token_hint", id_token_hint);
+        }
+        if let Some(post_logout_redirect) = post_logout_redirect {
+            query.append_pair("post_logout_redirect_uri", post_logout_redirect);
+        }
+    }
+
+    Ok(Some(url.into()))
+}
+
+/// Override the `scope`, `acr_values` and `prompt` parameters of an authorization request `url`.
+///
+/// Parameters which are `None` are kept as they are. The `openid` scope is always requested, as
+/// the request is not an OpenID Connect request otherwise.
+pub fn override_authorize_params(
+    url: &str,
+    scopes: Option<&[String]>,
+    acr_values: Option<&[String]>,
+    prompt: Option<&str>,
+) -> Result<String, Error> {
+    let mut url =
+        url::Url::parse(url).map_err(|err| format_err!("invalid authorize url '{url}': {err}"))?;
+
+    let mut overrides = Vec::new();
+
+    if let Some(scopes) = scopes {
+        let mut scope: Vec<&str> = vec!["openid"];
+        scope.extend(
+            scopes
+                .iter()
+                .map(String::as_str)
+                .filter(|scope| *scope != "openid"),
+        );
+        overrides.push(("scope", scope.join(" ")));
+    }
+    if let Some(acr_values) = acr_values {
+        overrides.push(("acr_values", acr_values.join(" ")));
+    }
+    if let Some(prompt) = prompt {
+        overrides.push(("prompt", prompt.to_string()));
+    }
+
+    let pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| !overrides.iter().any(|(name, _)| *name == &**key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(pairs)
+        .extend_pairs(overrides);
+
+    Ok(url.into())
+}
+
+/// Look up a required endpoint URL in the provider `metadata`.
+fn metadata_endpoint<'a>(metadata: &'a Value, name: &str) -> Result<&'a str, Error> {
+    metadata
+        .get(name)
+        .ok_or_else(|| format_err!("provider does not advertise a {name}"))?
+        .as_str()
+        .ok_or_else(|| format_err!("invalid {name} in provider metadata"))
+}
+
+/// Query the claims of the `userinfo_endpoint` with the bearer `access_token`.
+fn query_userinfo(
+    client: &Client,
+    userinfo_endpoint: &str,
+    access_token: &str,
+) -> Result<Value, Error> {
+    let headers = HashMap::from([(
+        "Authorization".to_string(),
+        format!("Bearer {access_token}"),
+    )]);
+
+    let response = client
+        .get(userinfo_endpoint, Some(&headers))
+        .map_err(|err| format_err!("failed to query userinfo: {err}"))?;
+
+    if !response.status().is_success() {
+        bail!("failed to query userinfo: {}", response.status());
+    }
+
+    serde_json::from_str(response.body())
+        .map_err(|err| format_err!("failed to parse userinfo: {err}"))
+}
+
+/// Query the claims of the provider's userinfo endpoint for an `access_token` obtained by some
+/// other flow.
+///
+/// See <https://openid.net/specs/openid-connect-core-1_0.html#UserInfo>.
+pub fn fetch_userinfo(metadata: &Value, access_token: &str) -> Result<Value, Error> {
+    let userinfo_endpoint = metadata_endpoint(metadata, "userinfo_endpoint")?;
+
+    query_userinfo(&http_client()?, userinfo_endpoint, access_token)
+}
+
+/// File name prefix of the auth state files [`proxmox_openid`] keeps per realm in the state
+/// directory.
+const AUTH_STATE_PREFIX: &str = "proxmox-openid-auth-state-";
+
+/// Returns whether `file_name` is an auth state file.
+///
+/// Lock files and temporary files of concurrent writers are not considered auth state files.
+fn is_auth_state_file(file_name: &str) -> bool {
+    file_name.starts_with(AUTH_STATE_PREFIX)
+        && !file_name.ends_with(".lck")
+        && !file_name.contains(".tmp_")
+}
+
+/// Returns whether the file at `path` was not modified for at least `max_age`.
+fn is_stale(path: &Path, max_age: Duration) -> Result<bool, Error> {
+    let modified = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.modified()?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err.into()),
+    };
+
+    // files modified in the future are not stale
+    Ok(SystemTime::now()
+        .duration_since(modified)
+        .is_ok_and(|age| age >= max_age))
+}
+
+/// Remove the auth state files in `state_dir` which were not modified for at least `max_age`
+/// and return how many were removed.
+///
+/// The auth state files of a realm are only written while logins are in progress, so stale
+/// files are mostly left over from removed realms. Each file is removed while holding the lock
+/// [`proxmox_openid`] uses, so concurrent logins are not affected. The lock files themselves
+/// are kept, as removing them could break the locking of concurrent writers.
+pub fn prune_auth_states(state_dir: &Path, max_age: Duration) -> Result<usize, Error> {
+    let mut removed = 0;
+
+    for entry in std::fs::read_dir(state_dir)
+        .map_err(|err| format_err!("failed to read state dir {state_dir:?}: {err}"))?
+    {
+        let entry = entry?;
+
+        let file_name = entry.file_name();
+        let Some(file_name) = file_name.to_str() else {
+            continue;
+        };
+
+        if !is_auth_state_file(file_name) || !entry.file_type()?.is_file() {
+            continue;
+        }
+
+        let path = entry.path();
+        if !is_stale(&path, max_age)? {
+            continue;
+        }
+
+        let _lock = proxmox_sys::fs::open_file_locked(
+            state_dir.join(format!("{file_name}.lck")),
+            Duration::from_secs(10),
+            true,
+            CreateOptions::new(),
+        )?;
+
+        // check again, the file might have been updated while waiting for the lock
+        if !is_stale(&path, max_age)? {
+            continue;
+        }
+
+        match std::fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => bail!("failed to remove auth state file {path:?}: {err}"),
+        }
+    }
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn logout_url() {
+        let metadata = json!({
+            "issuer": "https://idp.example.com/realms/pve",
+            "end_session_endpoint": "https://idp.example.com/realms/pve/logout?ui=1",
+        });
+
+        assert_eq!(
+            end_session_url(
+                &metadata,
+                "pve",
+                Some("token"),
+                Some("https://pve.example.com:8006/")
+            )
+            .unwrap()
+            .as_deref(),
+            Some(
+                "https://idp.example.com/realms/pve/logout?ui=1&client_id=pve&id_token_hint=token\
+                &post_logout_redirect_uri=https%3A%2F%2Fpve.example.com%3A8006%2F"
+            )
+        );
+
+        assert_eq!(
+            end_session_url(&metadata, "pve", None, None)
+                .unwrap()
+                .as_deref(),
+            Some("https://idp.example.com/realms/pve/logout?ui=1&client_id=pve")
+        );
+
+        let metadata = json!({ "issuer": "https://idp.example.com/realms/pve" });
+        assert_eq!(end_session_url(&metadata, "pve", None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn authorize_params() {
+        let url = "https://idp.example.com/auth?response_type=code&client_id=pve\
+            &state=abc&scope=openid+email&prompt=login";
+
+        assert_eq!(
+            override_authorize_params(url, None, None, None).unwrap(),
+            url
+        );
+
+        assert_eq!(
+            override_authorize_params(
+                url,
+                Some(&["profile".to_string(), "openid".to_string()]),
+                Some(&["mfa".to_string(), "phr".to_string()]),
+                Some("consent"),
+            )
+            .unwrap(),
+            "https://idp.example.com/auth?response_type=code&client_id=pve&state=abc\
+            &scope=openid+profile&acr_values=mfa+phr&prompt=consent"
+        );
+    }
+
+    #[test]
+    fn metadata_endpoints() {
+        let metadata = json!({ "userinfo_endpoint": "https://idp.example.com/userinfo" });
+        assert_eq!(
+            metadata_endpoint(&metadata, "userinfo_endpoint").unwrap(),
+            "https://idp.example.com/userinfo"
+        );
+        assert!(metadata_endpoint(&metadata, "token_endpoint").is_err());
+    }
+
+    #[test]
+    fn prune_stale_auth_states() {
+        assert!(is_auth_state_file("proxmox-openid-auth-state-realm"));
+        assert!(!is_auth_state_file("proxmox-openid-auth-state-realm.lck"));
+        assert!(!is_auth_state_file(
+            "proxmox-openid-auth-state-realm.tmp_AbC123"
+        ));
+        assert!(!is_auth_state_file("other-file"));
+
+        let state_dir =
+            std::env::temp_dir().join(format!("oidc-prune-auth-states-{}", std::process::id()));
+        std::fs::create_dir_all(&state_dir).unwrap();
+
+        for file_name in [
+            "proxmox-openid-auth-state-old",
+            "proxmox-openid-auth-state-older",
+            "unrelated",
+        ] {
+            std::fs::write(state_dir.join(file_name), "[]").unwrap();
+        }
+
+        assert_eq!(
+            prune_auth_states(&state_dir, Duration::from_secs(3600)).unwrap(),
+            0
+        );
+        assert_eq!(prune_auth_states(&state_dir, Duration::ZERO).unwrap(), 2);
+        assert!(state_dir.join("unrelated").exists());
+        assert!(!state_dir.join("proxmox-openid-auth-state-old").exists());
+
+        std::fs::remove_dir_all(&state_dir).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+//! Parsing of the `Retry-After` HTTP header.
+
+use std::time::SystemTime;
+
+/// Parse the value of a `Retry-After` header into the number of seconds to wait after `now`.
+///
+/// The value is either a number of seconds or an HTTP date, see RFC 9110, section 10.2.3. A date
+/// in the past means no waiting is needed, invalid values are ignored.
+pub fn parse_retry_after(value: &str, now: SystemTime) -> Option<u64> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(seconds);
+    }
+
+    let time = httpdate::parse_http_date(value).ok()?;
+    Some(time.duration_since(now).map_or(0, |wait| wait.as_secs()))
+}
+
+/// Returns the number of seconds to wait according to the `Retry-After` header of `response`,
+/// if it has a valid one.
+pub fn retry_after<B>(response: &http::Response<B>) -> Option<u64> {
+    response
+        .headers()
+        .get(http::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_retry_after(value, SystemTime::now()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn seconds_and_dates() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+
+        assert_eq!(parse_retry_after("120", now), Some(120));
+        assert_eq!(parse_retry_after(" 0 ", now), Some(0));
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:51:07 GMT", now),
+            Some(90)
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:00:00 GMT", now),
+            Some(0)
+        );
+        // the obsolete RFC 850 and asctime formats must be accepted as well
+        assert_eq!(
+            parse_retry_after("Sunday, 06-Nov-94 08:50:37 GMT", now),
+            Some(60)
+        );
+        assert_eq!(parse_retry_after("Sun Nov  6 08:50:37 1994", now), Some(60));
+        assert_eq!(parse_retry_after("-5", now), None);
+        assert_eq!(parse_retry_after("Sun, 06 Nov 1994 08:49 GMT", now), None);
+    }
+
+    #[test]
+    fn from_response() {
+        let response = http::Response::builder()
+            .status(http::StatusCode::TOO_MANY_REQUESTS)
+            .header(http::header::RETRY_AFTER, "30")
+            .body(())
+            .unwrap();
+        assert_eq!(retry_after(&response), Some(30));
+
+        let response = http::Response::builder().body(()).unwrap();
+        assert_eq!(retry_after(&response), None);
+    }
+}
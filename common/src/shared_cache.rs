@@ -0,0 +1,393 @@
+//! Expiring entries, atomic updates and inspection for [`SharedCache`].
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JSONValue;
+
+use proxmox_shared_cache::SharedCache;
+use proxmox_sys::fs::CreateOptions;
+
+/// The key under which expiring values are wrapped in the cache file.
+const EXPIRING_KEY: &str = "proxmox-shared-cache-expiring";
+
+/// Suffix of the lock file serializing the updates of an [`ExpiringCache`].
+const UPDATE_LOCK_SUFFIX: &str = ".update-lock";
+
+/// Suffixes of the files next to a cache which do not hold a value: the lock file of the
+/// [`SharedCache`] and the update lock of the [`ExpiringCache`].
+const LOCK_FILE_SUFFIXES: [&str; 2] = [".lck", UPDATE_LOCK_SUFFIX];
+
+/// Marker of the temporary files written by [`proxmox_sys::fs::replace_file`] before they are
+/// renamed over the cache file.
+const TMP_FILE_MARKER: &str = ".tmp_";
+
+/// How long to wait for the update lock when lazily removing an expired value.
+const EXPIRED_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A cached value with an expiry time.
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Expiring {
+    /// Epoch after which the value counts as absent.
+    expires_at: i64,
+    value: JSONValue,
+}
+
+/// Unwraps a value read from the cache, returning `None` if it has expired at `now`.
+fn unwrap_value(value: JSONValue, now: i64) -> Option<JSONValue> {
+    let expiring = match &value {
+        JSONValue::Object(map) if map.len() == 1 => map.get(EXPIRING_KEY),
+        _ => None,
+    };
+
+    match expiring.map(Expiring::deserialize) {
+        Some(Ok(expiring)) if expiring.expires_at <= now => None,
+        Some(Ok(expiring)) => Some(expiring.value),
+        _ => Some(value),
+    }
+}
+
+/// A [`SharedCache`] whose values can expire.
+///
+/// All updates are serialized via an additional lock file, so an expired value can be removed
+/// without racing against another process refreshing it. Readers never need the lock, since the
+/// [`SharedCache`] replaces its file atomically.
+pub struct ExpiringCache {
+    cache: SharedCache,
+    path: PathBuf,
+    lock_path: PathBuf,
+    options: CreateOptions,
+}
+
+impl ExpiringCache {
+    /// Create a new cache at `path`, see [`SharedCache::new`].
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        options: CreateOptions,
+        keep_old: u32,
+    ) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(UPDATE_LOCK_SUFFIX);
+
+        Ok(Self {
+            cache: SharedCache::new(path, options.clone(), keep_old)?,
+            path: path.to_owned(),
+            lock_path: lock_path.into(),
+            options,
+        })
+    }
+
+    fn lock(&self, lock_timeout: Duration) -> Result<File, Error> {
+        proxmox_sys::fs::open_file_locked(&self.lock_path, lock_timeout, true, self.options.clone())
+    }
+
+    /// Set the cached value, which is treated as absent after `ttl` if one is passed.
+    pub fn set(
+        &self,
+        value: JSONValue,
+        ttl: Option<Duration>,
+        lock_timeout: Duration,
+    ) -> Result<(), Error> {
+        let value = match ttl {
+            Some(ttl) => {
+                let ttl = i64::try_from(ttl.as_secs()).unwrap_or(i64::MAX);
+                let expiring = Expiring {
+                    expires_at: proxmox_time::epoch_i64().saturating_add(ttl),
+                    value,
+                };
+                serde_json::json!({ EXPIRING_KEY: expiring })
+            }
+            None => value,
+        };
+
+        let _lock = self.lock(lock_timeout)?;
+        self.cache.set(&value, lock_timeout)
+    }
+
+    /// Get the cached value, if it has not expired yet.
+    ///
+    /// An expired value is removed from the cache, unless another process refreshed it in the
+    /// meantime. Old entries are kept, see [`get_last`](Self::get_last).
+    pub fn get(&self) -> Result<Option<JSONValue>, Error> {
+        let Some(raw) = self.cache.get()? else {
+            return Ok(None);
+        };
+
+        let now = proxmox_time::epoch_i64();
+        if let Some(value) = unwrap_value(raw.clone(), now) {
+            return Ok(Some(value));
+        }
+
+        // removing the expired value is only an optimization, so do not fail the read over it
+        let Ok(_lock) = self.lock(EXPIRED_LOCK_TIMEOUT) else {
+            return Ok(None);
+        };
+
+        // only remove the current value, SharedCache::delete would drop the old entries too
+        if self.cache.get().ok().flatten().as_ref() == Some(&raw) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+
+        Ok(None)
+    }
+
+    /// Get the last `number_of_old_entries` values, skipping expired ones.
+    ///
+    /// See [`SharedCache::get_last`].
+    pub fn get_last(&self, number_of_old_entries: u32) -> Result<Vec<JSONValue>, Error> {
+        let now = proxmox_time::epoch_i64();
+
+        Ok(self
+            .cache
+            .get_last(number_of_old_entries)?
+            .into_iter()
+            .filter_map(|value| unwrap_value(value, now))
+            .collect())
+    }
+
+    /// Atomically replace the cached value with `new` if it currently equals `expected`.
+    ///
+    /// `None` stands for an absent (or expired) value, so passing `None` as `expected` only sets
+    /// the value if there is none yet, and passing `None` as `new` removes all values. Returns
+    /// whether the value has been replaced.
+    pub fn compare_and_swap(
+        &self,
+        expected: Option<&JSONValue>,
+        new: Option<JSONValue>,
+        lock_timeout: Duration,
+    ) -> Result<bool, Error> {
+        let _lock = self.lock(lock_timeout)?;
+
+        let now = proxmox_time::epoch_i64();
+        let current = self.cache.get()?.and_then(|value| unwrap_value(value, now));
+
+        if current.as_ref() != expected {
+            return Ok(false);
+        }
+
+        match new {
+            Some(value) => self.cache.set(&value, lock_timeout)?,
+            None => self.cache.delete(lock_timeout)?,
+        }
+
+        Ok(true)
+    }
+
+    /// Removes all values from the cache.
+    pub fn delete(&self, lock_timeout: Duration) -> Result<(), Error> {
+        let _lock = self.lock(lock_timeout)?;
+        self.cache.delete(lock_timeout)
+    }
+}
+
+/// Returns whether `file_name` is the file of a cache, rather than a lock file, a temporary file
+/// of a concurrent writer or a rotated old entry.
+fn is_cache_file(file_name: &str) -> bool {
+    let is_rotated = file_name.ends_with(".zst")
+        || file_name.rsplit_once('.').is_some_and(|(_, suffix)| {
+            !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit())
+        });
+
+    !file_name.starts_with('.')
+        && !LOCK_FILE_SUFFIXES
+            .iter()
+            .any(|suffix| file_name.ends_with(suffix))
+        && !file_name.contains(TMP_FILE_MARKER)
+        && !is_rotated
+}
+
+/// Returns the names of all caches in `dir` which currently hold an unexpired value, optionally
+/// only those starting with `prefix`, sorted by name.
+///
+/// Files which cannot be read or do not contain a cached value are skipped, so this never fails
+/// because of concurrent writers.
+pub fn list_keys(dir: &Path, prefix: Option<&str>) -> Result<Vec<String>, Error> {
+    let now = proxmox_time::epoch_i64();
+    let mut keys = Vec::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let Ok(entry) = entry else {
+            continue;
+        };
+
+        let Ok(file_name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        let matches_prefix = prefix.is_none_or(|prefix| file_name.starts_with(prefix));
+        if !is_cache_file(&file_name) || !matches_prefix {
+            continue;
+        }
+
+        let value = std::fs::read(entry.path())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .and_then(|value| unwrap_value(value, now));
+
+        if value.is_some() {
+            keys.push(file_name);
+        }
+    }
+
+    keys.sort();
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn unwrap_values() {
+        let expiring = json!({ EXPIRING_KEY: { "expires-at": 100, "value": [1, 2] } });
+        assert_eq!(unwrap_value(expiring.clone(), 99), Some(json!([1, 2])));
+        assert_eq!(unwrap_value(expiring, 100), None);
+
+        // values without an expiry, or which only look like one, are returned as they are
+        for value in [
+            json!("plain"),
+            json!({ "expires-at": 100, "value": 1 }),
+            json!({ EXPIRING_KEY: { "expires-at": 100, "value": 1, "other": 2 } }),
+            json!({ EXPIRING_KEY: { "expires-at": 100, "value": 1 }, "other": 2 }),
+        ] {
+            assert_eq!(unwrap_value(value.clone(), 1000), Some(value));
+        }
+    }
+
+    #[test]
+    fn expiring_values() {
+        let dir = std::env::temp_dir().join(format!("shared-cache-ttl-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let cache = ExpiringCache::new(dir.join("cache"), CreateOptions::new(), 2).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        cache
+            .set(json!("fresh"), Some(Duration::from_secs(3600)), timeout)
+            .unwrap();
+        assert_eq!(cache.get().unwrap(), Some(json!("fresh")));
+
+        cache
+            .set(json!("stale"), Some(Duration::ZERO), timeout)
+            .unwrap();
+        assert_eq!(cache.get_last(2).unwrap(), [json!("fresh")]);
+        assert_eq!(cache.get().unwrap(), None);
+        assert!(!dir.join("cache").exists());
+
+        // removing the expired value keeps the old entries
+        assert_eq!(cache.get_last(2).unwrap(), [json!("fresh")]);
+
+        cache.set(json!("again"), None, timeout).unwrap();
+        assert_eq!(cache.get().unwrap(), Some(json!("again")));
+        assert_eq!(cache.get_last(2).unwrap()[0], json!("again"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compare_and_swap_contended() {
+        let dir = std::env::temp_dir().join(format!("shared-cache-cas-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("leader");
+        let timeout = Duration::from_secs(30);
+
+        let barrier = std::sync::Barrier::new(8);
+        let winners: Vec<bool> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let (path, barrier) = (&path, &barrier);
+                    scope.spawn(move || {
+                        // every thread uses its own instance, like separate processes would
+                        let cache = ExpiringCache::new(path, CreateOptions::new(), 0).unwrap();
+                        barrier.wait();
+                        cache
+                            .compare_and_swap(None, Some(json!(format!("thread-{i}"))), timeout)
+                            .unwrap()
+                    })
+                })
+                .collect();
+
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(winners.iter().filter(|won| **won).count(), 1);
+
+        let cache = ExpiringCache::new(&path, CreateOptions::new(), 0).unwrap();
+        let winner = winners.iter().position(|won| *won).unwrap();
+        let leader = json!(format!("thread-{winner}"));
+        assert_eq!(cache.get().unwrap(), Some(leader.clone()));
+
+        assert!(
+            !cache
+                .compare_and_swap(Some(&json!("other")), None, timeout)
+                .unwrap()
+        );
+        assert!(
+            cache
+                .compare_and_swap(Some(&leader), None, timeout)
+                .unwrap()
+        );
+        assert_eq!(cache.get().unwrap(), None);
+
+        // an expired value counts as absent
+        cache
+            .set(json!("stale"), Some(Duration::ZERO), timeout)
+            .unwrap();
+        assert!(
+            cache
+                .compare_and_swap(None, Some(json!("new")), timeout)
+                .unwrap()
+        );
+        assert_eq!(cache.get().unwrap(), Some(json!("new")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn list_cache_keys() {
+        assert!(is_cache_file("subscription"));
+        assert!(is_cache_file("oidc-metadata.json"));
+        assert!(!is_cache_file("subscription.lck"));
+        assert!(!is_cache_file("subscription.update-lock"));
+        assert!(!is_cache_file("subscription.tmp_AbC123"));
+        assert!(!is_cache_file("subscription.1"));
+        assert!(!is_cache_file("subscription.2.zst"));
+
+        let dir = std::env::temp_dir().join(format!("shared-cache-keys-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let timeout = Duration::from_secs(5);
+
+        for (key, ttl) in [
+            ("metadata-a", None),
+            ("metadata-b", Some(Duration::from_secs(3600))),
+            ("metadata-stale", Some(Duration::ZERO)),
+            ("status", None),
+        ] {
+            ExpiringCache::new(dir.join(key), CreateOptions::new(), 0)
+                .unwrap()
+                .set(json!(key), ttl, timeout)
+                .unwrap();
+        }
+        std::fs::write(dir.join("garbage"), "not json").unwrap();
+
+        assert_eq!(
+            list_keys(&dir, None).unwrap(),
+            ["metadata-a", "metadata-b", "status"]
+        );
+        assert_eq!(
+            list_keys(&dir, Some("metadata-")).unwrap(),
+            ["metadata-a", "metadata-b"]
+        );
+        assert!(list_keys(&dir, Some("other")).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
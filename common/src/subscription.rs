@@ -0,0 +1,396 @@
+//! Helpers for presenting and checking the subscription status.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Error, bail};
+use serde::Serialize;
+
+use proxmox_http::HttpClient;
+use proxmox_subscription::{SubscriptionInfo, SubscriptionStatus};
+
+use crate::common::retry_after::retry_after;
+use crate::common::shared_cache::ExpiringCache;
+
+/// The support level of a subscription, as encoded in its key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionLevel {
+    /// Community support, marked by a `c` in the key.
+    Community,
+    /// Basic support, marked by a `b` in the key.
+    Basic,
+    /// Standard support, marked by an `s` in the key.
+    Standard,
+    /// Premium support, marked by a `p` in the key.
+    Premium,
+}
+
+/// Structured details of a stored subscription.
+#[derive(Debug, Serialize)]
+pub struct SubscriptionDetails {
+    /// The status of the subscription.
+    pub status: SubscriptionStatus,
+    /// The name of the subscribed product.
+    pub product_name: Option<String>,
+    /// The support level, if it could be parsed from the key.
+    pub level: Option<SubscriptionLevel>,
+    /// The number of CPU sockets covered, for products licensed per socket.
+    pub sockets: Option<u32>,
+    /// The subscription key.
+    pub key: Option<String>,
+    /// The date the subscription was registered.
+    pub registration_date: Option<String>,
+    /// The date the subscription is due for renewal, i.e. when it expires.
+    pub next_due_date: Option<String>,
+    /// Epoch of the last successful check of the subscription.
+    pub check_time: Option<i64>,
+}
+
+/// Parse the support level and the number of sockets from a subscription key like
+/// `pve4s-0123456789`.
+fn parse_key(key: &str) -> (Option<SubscriptionLevel>, Option<u32>) {
+    let Some((head, _)) = key.split_once('-') else {
+        return (None, None);
+    };
+
+    let mut chars = head.chars();
+    let level = match chars.next_back() {
+        Some('c') => Some(SubscriptionLevel::Community),
+        Some('b') => Some(SubscriptionLevel::Basic),
+        Some('s') => Some(SubscriptionLevel::Standard),
+        Some('p') => Some(SubscriptionLevel::Premium),
+        _ => None,
+    };
+
+    let rest = chars.as_str();
+    let product = rest.trim_end_matches(|c: char| c.is_ascii_digit());
+    let sockets = rest[product.len()..].parse().ok();
+
+    (level, sockets)
+}
+
+/// Returns the details of the subscription `info`, or `None` if there is no subscription.
+pub fn details(info: SubscriptionInfo) -> Option<SubscriptionDetails> {
+    if info.status == SubscriptionStatus::NotFound {
+        return None;
+    }
+
+    let (level, sockets) = info.key.as_deref().map(parse_key).unwrap_or_default();
+
+    Some(SubscriptionDetails {
+        status: info.status,
+        product_name: info.productname,
+        level,
+        sockets,
+        key: info.key,
+        registration_date: info.regdate,
+        next_due_date: info.nextduedate,
+        check_time: info.checktime,
+    })
+}
+
+/// Checks that the offline subscription `info` is bound to the server `server_id` and has not
+/// expired before `today`, formatted as `YYYY-MM-DD`.
+///
+/// The signature has to be checked separately.
+pub fn check_offline(info: &SubscriptionInfo, server_id: &str, today: &str) -> Result<(), Error> {
+    match info.serverid.as_deref() {
+        Some(id) if id == server_id => (),
+        Some(id) => {
+            bail!("subscription is for server id '{id}', but this server has id '{server_id}'")
+        }
+        None => bail!("subscription is not bound to a server id"),
+    }
+
+    if info.status == SubscriptionStatus::Expired {
+        bail!("subscription has expired");
+    }
+
+    match info.nextduedate.as_deref() {
+        Some(due) if !due.is_empty() && due < today => {
+            bail!("subscription has expired on {due}")
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+/// Minimum time between two online checks of the subscription via [`refresh`], unless forced.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
+/// How long to wait for the lock of the cache of the last subscription check.
+const REFRESH_LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The shop rejected an online check of the subscription, because it was rate limited.
+#[derive(Debug)]
+pub struct RateLimited {
+    /// The number of seconds to wait before retrying, from the `Retry-After` header.
+    pub retry_after: Option<u64>,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "subscription check was rate limited by the server")?;
+        if let Some(retry_after) = self.retry_after {
+            write!(f, ", retry after {retry_after} seconds")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+/// Turn a `429 Too Many Requests` response into a [`RateLimited`] error.
+fn check_rate_limit<B>(response: http::Response<B>) -> Result<http::Response<B>, Error> {
+    if response.status() != http::StatusCode::TOO_MANY_REQUESTS {
+        return Ok(response);
+    }
+
+    Err(RateLimited {
+        retry_after: retry_after(&response),
+    }
+    .into())
+}
+
+/// A [`HttpClient`] failing with a [`RateLimited`] error on rate limited responses, instead of
+/// passing them on to the subscription check, which cannot tell them apart from other failures.
+pub struct RateLimitedClient<C>(pub C);
+
+impl<C: HttpClient<String, String>> HttpClient<String, String> for RateLimitedClient<C> {
+    fn get(
+        &self,
+        uri: &str,
+        extra_headers: Option<&HashMap<String, String>>,
+    ) -> Result<http::Response<String>, Error> {
+        check_rate_limit(self.0.get(uri, extra_headers)?)
+    }
+
+    fn post(
+        &self,
+        uri: &str,
+        body: Option<String>,
+        content_type: Option<&str>,
+        extra_headers: Option<&HashMap<String, String>>,
+    ) -> Result<http::Response<String>, Error> {
+        check_rate_limit(self.0.post(uri, body, content_type, extra_headers)?)
+    }
+
+    fn request(&self, request: http::Request<String>) -> Result<http::Response<String>, Error> {
+        check_rate_limit(self.0.request(request)?)
+    }
+}
+
+/// The error returned by [`refresh`].
+#[derive(Debug, Serialize)]
+pub struct RefreshError {
+    /// The error message.
+    pub message: String,
+    /// Whether the check was rate limited, see [`RateLimited`].
+    pub rate_limited: bool,
+    /// The number of seconds to wait before retrying a rate limited check, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+}
+
+impl From<Error> for RefreshError {
+    fn from(err: Error) -> Self {
+        let rate_limited = err.downcast_ref::<RateLimited>();
+
+        Self {
+            message: format!("{err:#}"),
+            rate_limited: rate_limited.is_some(),
+            retry_after: rate_limited.and_then(|rate_limited| rate_limited.retry_after),
+        }
+    }
+}
+
+/// Returns the subscription info from a previous check of the subscription `key`, as stored by
+/// [`refresh`], if there is one.
+fn cached_check(cached: Option<serde_json::Value>, key: &str) -> Option<SubscriptionInfo> {
+    cached
+        .and_then(|value| serde_json::from_value::<SubscriptionInfo>(value).ok())
+        .filter(|info| info.key.as_deref() == Some(key))
+}
+
+/// Check the subscription `key` online via `check`, unless it was already checked in the last
+/// [`REFRESH_INTERVAL`] and `force` is not set.
+///
+/// The result of the last check is stored in `cache`, and returned instead of checking again too
+/// early.
+pub fn refresh<F>(
+    cache: &ExpiringCache,
+    key: &str,
+    force: bool,
+    check: F,
+) -> Result<SubscriptionInfo, Error>
+where
+    F: FnOnce() -> Result<SubscriptionInfo, Error>,
+{
+    let cached = if force {
+        None
+    } else {
+        cached_check(cache.get()?, key)
+    };
+
+    if let Some(info) = cached {
+        return Ok(info);
+    }
+
+    let info = check()?;
+
+    cache.set(
+        serde_json::to_value(&info)?,
+        Some(REFRESH_INTERVAL),
+        REFRESH_LOCK_TIMEOUT,
+    )?;
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn subscription_keys() {
+        assert_eq!(
+            parse_key("pve4s-0123456789"),
+            (Some(SubscriptionLevel::Standard), Some(4))
+        );
+        assert_eq!(
+            parse_key("pve16p-0123456789"),
+            (Some(SubscriptionLevel::Premium), Some(16))
+        );
+        assert_eq!(
+            parse_key("pmgc-0123456789"),
+            (Some(SubscriptionLevel::Community), None)
+        );
+        assert_eq!(parse_key("pbsx-0123456789"), (None, None));
+        assert_eq!(parse_key("invalid"), (None, None));
+    }
+
+    #[test]
+    fn subscription_details() {
+        let info: SubscriptionInfo = serde_json::from_value(json!({
+            "status": "active",
+            "key": "pve2b-0123456789",
+            "productname": "Proxmox VE Basic Subscription 2 CPUs/year",
+            "regdate": "2025-01-01 00:00:00",
+            "nextduedate": "2026-01-01",
+            "checktime": 1_750_000_000,
+        }))
+        .unwrap();
+
+        let details = details(info).expect("subscription is present");
+        assert_eq!(details.status, SubscriptionStatus::Active);
+        assert_eq!(details.level, Some(SubscriptionLevel::Basic));
+        assert_eq!(details.sockets, Some(2));
+        assert_eq!(details.next_due_date.as_deref(), Some("2026-01-01"));
+        assert_eq!(details.check_time, Some(1_750_000_000));
+
+        let info: SubscriptionInfo =
+            serde_json::from_value(json!({ "status": "notfound" })).unwrap();
+        assert!(self::details(info).is_none());
+    }
+
+    #[test]
+    fn rate_limited_check() {
+        let response = http::Response::builder()
+            .status(http::StatusCode::OK)
+            .body(String::new())
+            .unwrap();
+        assert!(check_rate_limit(response).is_ok());
+
+        let response = http::Response::builder()
+            .status(http::StatusCode::TOO_MANY_REQUESTS)
+            .header(http::header::RETRY_AFTER, "120")
+            .body(String::new())
+            .unwrap();
+        let err = RefreshError::from(check_rate_limit(response).unwrap_err());
+        assert!(err.rate_limited);
+        assert_eq!(err.retry_after, Some(120));
+        assert_eq!(
+            err.message,
+            "subscription check was rate limited by the server, retry after 120 seconds"
+        );
+
+        let response = http::Response::builder()
+            .status(http::StatusCode::TOO_MANY_REQUESTS)
+            .header(http::header::RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT")
+            .body(String::new())
+            .unwrap();
+        let err = RefreshError::from(check_rate_limit(response).unwrap_err());
+        assert!(err.rate_limited);
+        // the date is in the past, so there is no need to wait
+        assert_eq!(err.retry_after, Some(0));
+
+        let response = http::Response::builder()
+            .status(http::StatusCode::TOO_MANY_REQUESTS)
+            .body(String::new())
+            .unwrap();
+        let err = RefreshError::from(check_rate_limit(response).unwrap_err());
+        assert!(err.rate_limited);
+        assert_eq!(err.retry_after, None);
+
+        let err = RefreshError::from(anyhow::format_err!("connection refused"));
+        assert!(!err.rate_limited);
+        assert_eq!(err.message, "connection refused");
+    }
+
+    #[test]
+    fn cached_subscription_check() {
+        let cached = json!({
+            "status": "active",
+            "key": "pve2b-0123456789",
+            "checktime": 1_750_000_000,
+        });
+
+        let info = cached_check(Some(cached.clone()), "pve2b-0123456789").unwrap();
+        assert_eq!(info.status, SubscriptionStatus::Active);
+        assert_eq!(info.checktime, Some(1_750_000_000));
+
+        assert!(cached_check(Some(cached), "pve4s-0123456789").is_none());
+        assert!(cached_check(Some(json!("garbage")), "pve2b-0123456789").is_none());
+        assert!(cached_check(None, "pve2b-0123456789").is_none());
+    }
+
+    #[test]
+    fn offline_subscription() {
+        let mut info: SubscriptionInfo = serde_json::from_value(json!({
+            "status": "active",
+            "key": "pve2b-0123456789",
+            "serverid": "0123456789ABCDEF0123456789ABCDEF",
+            "nextduedate": "2026-01-01",
+        }))
+        .unwrap();
+
+        let server_id = "0123456789ABCDEF0123456789ABCDEF";
+
+        check_offline(&info, server_id, "2025-06-01").unwrap();
+        check_offline(&info, server_id, "2026-01-01").unwrap();
+
+        let err = check_offline(&info, server_id, "2026-01-02").unwrap_err();
+        assert_eq!(err.to_string(), "subscription has expired on 2026-01-01");
+
+        let err =
+            check_offline(&info, "FEDCBA9876543210FEDCBA9876543210", "2025-06-01").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "subscription is for server id '0123456789ABCDEF0123456789ABCDEF', but this server \
+            has id 'FEDCBA9876543210FEDCBA9876543210'"
+        );
+
+        info.status = SubscriptionStatus::Expired;
+        let err = check_offline(&info, server_id, "2025-06-01").unwrap_err();
+        assert_eq!(err.to_string(), "subscription has expired");
+
+        info.serverid = None;
+        let err = check_offline(&info, server_id, "2025-06-01").unwrap_err();
+        assert_eq!(err.to_string(), "subscription is not bound to a server id");
+    }
+}
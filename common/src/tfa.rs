@@ -0,0 +1,31 @@
+//! Helpers for TFA data which are shared between the products.
+
+use serde::Serialize;
+
+use proxmox_tfa::api::TfaUserData;
+
+/// Number of recovery keys of a user.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RecoveryCounts {
+    /// The number of generated recovery keys.
+    pub total: usize,
+    /// The number of recovery keys which were already used.
+    pub used: usize,
+    /// The number of recovery keys which can still be used.
+    pub remaining: usize,
+}
+
+/// Count the total, used and remaining recovery keys of `user`.
+///
+/// Returns `None` if the user has no recovery keys.
+pub fn recovery_counts(user: &TfaUserData) -> Option<RecoveryCounts> {
+    let recovery = user.recovery.as_ref()?;
+    let total = recovery.count_total();
+    let remaining = recovery.count_available();
+
+    Some(RecoveryCounts {
+        total,
+        used: total.saturating_sub(remaining),
+        remaining,
+    })
+}
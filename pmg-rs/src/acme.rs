@@ -7,6 +7,7 @@ use nix::sys::stat::Mode;
 use proxmox_sys::fs::CreateOptions;
 use serde::{Deserialize, Serialize};
 
+use proxmox_acme::authorization::Identifier;
 use proxmox_acme::types::AccountData as AcmeAccountData;
 use proxmox_acme::{Account, Client};
 
@@ -34,6 +35,49 @@ pub struct AccountData {
     directory_url: String,
 }
 
+/// The values needed to publish the TXT record of a `dns-01` challenge.
+#[derive(Serialize)]
+pub struct Dns01Challenge {
+    /// The domain the challenge is for, without a wildcard prefix.
+    domain: String,
+
+    /// The name of the TXT record, i.e. `_acme-challenge.<domain>`.
+    record_name: String,
+
+    /// The value of the TXT record, the base64url encoded SHA-256 of the key authorization.
+    txt_value: String,
+
+    /// The challenge's URL, used to request its validation once the record is published.
+    url: String,
+}
+
+/// How far an order or authorization has progressed, derived from its status.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Progress {
+    /// The CA is not done yet (`pending` or `processing`), poll again later.
+    NotReady,
+    /// An order is ready to be finalized.
+    Ready,
+    /// The order or authorization is done.
+    Valid,
+    /// The order or authorization failed, polling again will not change that.
+    Failed,
+}
+
+impl Progress {
+    /// Get the progress for a serialized ACME status.
+    fn from_status<T: Serialize>(status: &T) -> Result<Self, Error> {
+        let status = serde_json::to_value(status)?;
+        Ok(match status.as_str() {
+            Some("pending" | "processing") => Progress::NotReady,
+            Some("ready") => Progress::Ready,
+            Some("valid") => Progress::Valid,
+            _ => Progress::Failed,
+        })
+    }
+}
+
 #[inline]
 fn is_false(b: &bool) -> bool {
     !*b
@@ -137,6 +181,27 @@ impl Inner {
         Ok(())
     }
 
+    pub fn dns_01_challenge(&mut self, authorization_url: &str) -> Result<Dns01Challenge, Error> {
+        let authorization = self.client.get_authorization(authorization_url)?;
+        let Identifier::Dns(domain) = authorization.identifier;
+
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.ty == "dns-01")
+            .ok_or_else(|| format_err!("no dns-01 challenge found for '{domain}'"))?;
+        let token = challenge
+            .token()
+            .ok_or_else(|| format_err!("dns-01 challenge for '{domain}' is missing its token"))?;
+
+        Ok(Dns01Challenge {
+            record_name: format!("_acme-challenge.{domain}"),
+            txt_value: self.client.dns_01_txt_value(token)?,
+            url: challenge.url.clone(),
+            domain,
+        })
+    }
+
     pub fn revoke_certificate(&mut self, data: &[u8], reason: Option<u32>) -> Result<(), Error> {
         Ok(self.client.revoke_certificate(data, reason)?)
     }
@@ -159,7 +224,7 @@ pub mod export {
     use proxmox_acme::order::OrderData;
     use proxmox_acme::{Authorization, Challenge, Order};
 
-    use super::{AccountData, Inner};
+    use super::{AccountData, Dns01Challenge, Inner, Progress};
 
     perlmod::declare_magic!(Box<Acme> : &Acme as "PMG::RS::Acme");
 
@@ -292,6 +357,32 @@ pub mod export {
         Ok(this.inner.lock().unwrap().client.get_order(url)?)
     }
 
+    /// Query an order given its URL, along with its progress.
+    ///
+    /// The progress is one of `not-ready` (poll again later), `ready` (can be finalized), `valid`
+    /// or `failed`, so callers can tell a pending order from a failed one without interpreting
+    /// the ACME status themselves.
+    #[export]
+    pub fn order_status(
+        #[try_from_ref] this: &Acme,
+        url: &str,
+    ) -> Result<(Progress, OrderData), Error> {
+        let order = this.inner.lock().unwrap().client.get_order(url)?;
+        Ok((Progress::from_status(&order.status)?, order))
+    }
+
+    /// Get the authorization info given an authorization URL, along with its progress.
+    ///
+    /// See `order_status` for the possible progress values. Authorizations never become `ready`.
+    #[export]
+    pub fn authorization_status(
+        #[try_from_ref] this: &Acme,
+        url: &str,
+    ) -> Result<(Progress, Authorization), Error> {
+        let authorization = this.inner.lock().unwrap().client.get_authorization(url)?;
+        Ok((Progress::from_status(&authorization.status)?, authorization))
+    }
+
     /// Get the key authorization string for a challenge given a token.
     #[export]
     pub fn key_authorization(#[try_from_ref] this: &Acme, token: &str) -> Result<String, Error> {
@@ -304,6 +395,23 @@ pub mod export {
         Ok(this.inner.lock().unwrap().client.dns_01_txt_value(token)?)
     }
 
+    /// Get the values needed to publish the TXT record for the `dns-01` challenge of an
+    /// authorization, given the authorization URL.
+    ///
+    /// Returns the `domain`, the `record_name` (`_acme-challenge.<domain>`), the `txt_value` and
+    /// the challenge's `url`. This does not depend on any DNS provider. Once the record is
+    /// published, pass the `url` to `request_challenge_validation` to let the CA validate it.
+    #[export]
+    pub fn dns_01_challenge(
+        #[try_from_ref] this: &Acme,
+        authorization_url: &str,
+    ) -> Result<Dns01Challenge, Error> {
+        this.inner
+            .lock()
+            .unwrap()
+            .dns_01_challenge(authorization_url)
+    }
+
     /// Request validation of a challenge by URL.
     ///
     /// Given an `Authorization`, it'll contain `challenges`. These contain `url`s pointing to a
@@ -377,3 +485,22 @@ pub mod export {
         this.inner.lock().unwrap().set_proxy(proxy)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_from_status() {
+        for (status, progress) in [
+            ("pending", Progress::NotReady),
+            ("processing", Progress::NotReady),
+            ("ready", Progress::Ready),
+            ("valid", Progress::Valid),
+            ("invalid", Progress::Failed),
+            ("expired", Progress::Failed),
+        ] {
+            assert_eq!(Progress::from_status(&status).unwrap(), progress);
+        }
+    }
+}
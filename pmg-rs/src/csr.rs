@@ -1,3 +1,147 @@
+//! Generation of certificate signing requests for PMG.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use anyhow::{Error, bail, format_err};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::rsa::Rsa;
+use openssl::stack::Stack;
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509Name, X509Req};
+use serde::Deserialize;
+
+use proxmox_acme::util::Csr;
+
+/// The type of the private key generated along with a CSR.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyType {
+    /// A 4096 bit RSA key.
+    #[default]
+    Rsa,
+    /// An EC key on the NIST P-256 curve.
+    EcP256,
+    /// An EC key on the NIST P-384 curve.
+    EcP384,
+}
+
+/// Generate an EC key on the curve `nid`.
+fn generate_ec_key(nid: Nid) -> Result<PKey<Private>, Error> {
+    let group = EcGroup::from_curve_name(nid)?;
+    Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+/// Parse the IP addresses passed for a CSR, failing on the first invalid one.
+fn parse_ip_addresses(ip_addresses: &[&str]) -> Result<Vec<IpAddr>, Error> {
+    ip_addresses
+        .iter()
+        .map(|ip| {
+            ip.parse()
+                .map_err(|err| format_err!("invalid IP address '{ip}': {err}"))
+        })
+        .collect()
+}
+
+/// Build and sign a CSR for the DNS names `identifiers` and `ip_addresses` with `private_key`.
+///
+/// This uses the same subject and extensions as [`Csr::generate`], except for the key usage not
+/// including key encipherment for EC keys, which cannot be used for that. If there are no DNS
+/// names, the first IP address is used as common name.
+fn build_csr(
+    identifiers: &[&str],
+    ip_addresses: &[IpAddr],
+    attributes: &HashMap<String, &str>,
+    private_key: &PKey<Private>,
+    digest: MessageDigest,
+) -> Result<X509Req, Error> {
+    let common_name = match (identifiers.first(), ip_addresses.first()) {
+        (Some(identifier), _) => identifier.to_string(),
+        (None, Some(ip)) => ip.to_string(),
+        (None, None) => bail!("cannot generate empty CSR"),
+    };
+
+    let mut name = X509Name::builder()?;
+    name.append_entry_by_nid(Nid::COMMONNAME, &common_name)?;
+    for (key, value) in attributes {
+        name.append_entry_by_text(key, value)?;
+    }
+    let name = name.build();
+
+    let mut csr = X509Req::builder()?;
+    csr.set_subject_name(&name)?;
+    csr.set_pubkey(private_key)?;
+
+    let mut san = SubjectAlternativeName::new();
+    for identifier in identifiers {
+        san.dns(identifier);
+    }
+    for ip in ip_addresses {
+        san.ip(&ip.to_string());
+    }
+
+    let mut key_usage = KeyUsage::new();
+    key_usage.digital_signature();
+    if private_key.id() == Id::RSA {
+        key_usage.key_encipherment();
+    }
+
+    let context = csr.x509v3_context(None);
+    let mut extensions = Stack::new()?;
+    extensions.push(BasicConstraints::new().build()?)?;
+    extensions.push(key_usage.build()?)?;
+    extensions.push(
+        ExtendedKeyUsage::new()
+            .server_auth()
+            .client_auth()
+            .build()?,
+    )?;
+    extensions.push(san.build(&context)?)?;
+    csr.add_extensions(&extensions)?;
+
+    csr.sign(private_key, digest)?;
+
+    Ok(csr.build())
+}
+
+/// Generates a CSR for the DNS names `identifiers` and the IP addresses `ip_addresses`, and a
+/// private key of type `key_type`.
+///
+/// Returns the DER formatted CSR and the PEM formatted pkcs8 private key.
+pub fn generate(
+    identifiers: &[&str],
+    ip_addresses: &[&str],
+    attributes: &HashMap<String, &str>,
+    key_type: KeyType,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let ip_addresses = parse_ip_addresses(ip_addresses)?;
+
+    let (private_key, digest) = match key_type {
+        KeyType::Rsa if ip_addresses.is_empty() => {
+            let csr = Csr::generate(identifiers, attributes)?;
+            return Ok((csr.data, csr.private_key_pem));
+        }
+        KeyType::Rsa => (
+            PKey::from_rsa(Rsa::generate(4096)?)?,
+            MessageDigest::sha256(),
+        ),
+        KeyType::EcP256 => (
+            generate_ec_key(Nid::X9_62_PRIME256V1)?,
+            MessageDigest::sha256(),
+        ),
+        KeyType::EcP384 => (generate_ec_key(Nid::SECP384R1)?, MessageDigest::sha384()),
+    };
+
+    let csr = build_csr(identifiers, &ip_addresses, attributes, &private_key, digest)?;
+
+    Ok((csr.to_der()?, private_key.private_key_to_pem_pkcs8()?))
+}
+
 #[perlmod::package(name = "PMG::RS::CSR")]
 pub mod export {
     use std::collections::HashMap;
@@ -5,17 +149,124 @@ pub mod export {
     use anyhow::Error;
     use serde_bytes::ByteBuf;
 
-    use proxmox_acme::util::Csr;
+    use super::KeyType;
 
     /// Generates a CSR and its accompanying private key.
     ///
-    /// The CSR is DER formatted, the private key is a PEM formatted pkcs8 private key.
+    /// The CSR is DER formatted, the private key is a PEM formatted pkcs8 private key. The key
+    /// type can be one of `rsa` (the default), `ec-p256` or `ec-p384`.
+    ///
+    /// `identifiers` are added as DNS names, the IPv4 and IPv6 addresses in `ip_addresses` as IP
+    /// address subject alternative names.
     #[export]
     pub fn generate_csr(
         identifiers: Vec<&str>,
         attributes: HashMap<String, &str>,
+        key_type: Option<KeyType>,
+        ip_addresses: Option<Vec<&str>>,
     ) -> Result<(ByteBuf, ByteBuf), Error> {
-        let csr = Csr::generate(&identifiers, &attributes)?;
-        Ok((ByteBuf::from(csr.data), ByteBuf::from(csr.private_key_pem)))
+        let (csr, private_key) = super::generate(
+            &identifiers,
+            &ip_addresses.unwrap_or_default(),
+            &attributes,
+            key_type.unwrap_or_default(),
+        )?;
+        Ok((ByteBuf::from(csr), ByteBuf::from(private_key)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::pkey::Id;
+
+    #[test]
+    fn ec_p256_csr() {
+        let attributes = HashMap::from([("O".to_string(), "Proxmox")]);
+        let (der, pem) = generate(
+            &["mail.example.com", "smtp.example.com"],
+            &[],
+            &attributes,
+            KeyType::EcP256,
+        )
+        .unwrap();
+
+        let csr = X509Req::from_der(&der).unwrap();
+        let private_key = PKey::private_key_from_pem(&pem).unwrap();
+
+        assert_eq!(private_key.id(), Id::EC);
+        assert_eq!(
+            private_key.ec_key().unwrap().group().curve_name(),
+            Some(Nid::X9_62_PRIME256V1)
+        );
+        assert!(csr.public_key().unwrap().public_eq(&private_key));
+        assert!(csr.verify(&csr.public_key().unwrap()).unwrap());
+
+        let subject: Vec<(Nid, String)> = csr
+            .subject_name()
+            .entries()
+            .map(|entry| {
+                let value = String::from_utf8(entry.data().as_slice().to_vec()).unwrap();
+                (entry.object().nid(), value)
+            })
+            .collect();
+        assert_eq!(
+            subject,
+            [
+                (Nid::COMMONNAME, "mail.example.com".to_string()),
+                (Nid::ORGANIZATIONNAME, "Proxmox".to_string()),
+            ]
+        );
+
+        let text = String::from_utf8(csr.to_text().unwrap()).unwrap();
+        assert!(text.contains("DNS:mail.example.com, DNS:smtp.example.com"));
+    }
+
+    #[test]
+    fn empty_csr() {
+        let err = generate(&[], &[], &HashMap::new(), KeyType::EcP384).unwrap_err();
+        assert_eq!(err.to_string(), "cannot generate empty CSR");
+    }
+
+    #[test]
+    fn ip_address_sans() {
+        let (der, _pem) = generate(
+            &["mail.example.com"],
+            &["192.0.2.1", "2001:db8::1"],
+            &HashMap::new(),
+            KeyType::EcP256,
+        )
+        .unwrap();
+
+        let csr = X509Req::from_der(&der).unwrap();
+        let text = String::from_utf8(csr.to_text().unwrap()).unwrap();
+        assert!(text.contains(
+            "DNS:mail.example.com, IP Address:192.0.2.1, IP Address:2001:DB8:0:0:0:0:0:1"
+        ));
+
+        // iPAddress general names are encoded as the raw octets of the address
+        assert!(der.windows(6).any(|w| w == [0x87, 4, 192, 0, 2, 1]));
+
+        let (der, _pem) = generate(&[], &["10.0.0.1"], &HashMap::new(), KeyType::EcP384).unwrap();
+        let csr = X509Req::from_der(&der).unwrap();
+        let common_name = csr
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap();
+        assert_eq!(common_name.data().as_slice(), b"10.0.0.1");
+
+        let err = generate(
+            &["mail.example.com"],
+            &["192.0.2.256"],
+            &HashMap::new(),
+            KeyType::EcP256,
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid IP address '192.0.2.256':")
+        );
     }
 }
@@ -1,6 +1,7 @@
 use anyhow::Error;
 
 use proxmox_apt_api_types::APTUpdateInfo;
+use proxmox_http::ProxyConfig;
 
 #[path = "../common/src/mod.rs"]
 pub mod common;
@@ -19,8 +20,23 @@ mod export {
     }
 }
 
-pub fn send_updates_available(_updates: &[&APTUpdateInfo]) -> Result<(), Error> {
+/// The users of mail targets are not resolved for PMG yet. It is called from `common` code.
+pub fn lookup_email_for_user(_user: &str) -> Option<String> {
+    None
+}
+
+/// Notifications are not sent via a proxy for PMG yet. It is called from `common` code.
+pub fn http_proxy_config() -> Option<ProxyConfig> {
+    None
+}
+
+pub fn send_updates_available(
+    updates: &[&APTUpdateInfo],
+) -> Result<common::apt::UpdateNotificationResult, Error> {
     tracing::warn!("update notifications are not implemented for PMG yet");
 
-    Ok(())
+    Ok(common::apt::UpdateNotificationResult {
+        updates: updates.len(),
+        notified: false,
+    })
 }
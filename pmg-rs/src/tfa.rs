@@ -17,9 +17,10 @@ use anyhow::{Error, bail, format_err};
 use nix::errno::Errno;
 use nix::sys::stat::Mode;
 
+use proxmox_tfa::api::methods::{self, TypedTfaInfo};
 use proxmox_tfa::api::{
-    RecoveryState, TfaChallenge, TfaConfig, TfaResponse, U2fConfig, UserChallengeAccess,
-    WebauthnConfig,
+    RecoveryState, TfaChallenge, TfaConfig, TfaEntry, TfaResponse, TfaUserData, U2fConfig,
+    UserChallengeAccess, WebauthnConfig,
 };
 
 #[perlmod::package(name = "PMG::RS::TFA")]
@@ -35,6 +36,8 @@ mod export {
     use perlmod::Value;
     use proxmox_tfa::api::{TfaResult, methods};
 
+    use crate::common::tfa;
+
     use super::{TfaConfig, UserAccess};
 
     perlmod::declare_magic!(Box<Tfa> : &Tfa as "PMG::RS::TFA");
@@ -144,6 +147,12 @@ mod export {
         })
     }
 
+    /// Get the ids of all users with at least one enabled TFA entry of a given type.
+    #[export]
+    fn users_with_type(#[try_from_ref] this: &Tfa, typename: &str) -> Result<Vec<String>, Error> {
+        super::users_with_type(&this.inner.lock().unwrap(), typename)
+    }
+
     /// Generates a space separated list of yubico keys of this account.
     #[export]
     fn get_yubico_keys(#[try_from_ref] this: &Tfa, userid: &str) -> Result<Option<String>, Error> {
@@ -225,6 +234,35 @@ mod export {
             .and_then(|user| user.recovery_state())
     }
 
+    /// Get the total, used and remaining number of recovery keys of a user.
+    #[export]
+    fn recovery_counts(#[try_from_ref] this: &Tfa, userid: &str) -> Option<tfa::RecoveryCounts> {
+        this.inner
+            .lock()
+            .unwrap()
+            .users
+            .get(userid)
+            .and_then(tfa::recovery_counts)
+    }
+
+    /// Replace the recovery keys of a user with a freshly generated set.
+    ///
+    /// All previously issued recovery keys of the user become invalid. The new keys are only
+    /// returned here, so they need to be shown to the user right away. This modifies the config,
+    /// so it needs to be written out.
+    #[export]
+    fn regenerate_recovery_keys(
+        #[raw] raw_this: Value,
+        userid: &str,
+    ) -> Result<Vec<String>, Error> {
+        let this: &Tfa = (&raw_this).try_into()?;
+        super::regenerate_recovery_keys(
+            &mut this.inner.lock().unwrap(),
+            &UserAccess::new(&raw_this)?,
+            userid,
+        )
+    }
+
     /// Takes the TFA challenge string (which is a json object) and verifies ther esponse against
     /// it.
     ///
@@ -341,6 +379,18 @@ mod export {
         methods::get_tfa_entry(&this.inner.lock().unwrap(), userid, id)
     }
 
+    /// Find the ids of a user's TFA entries whose description contains a substring, ignoring
+    /// case.
+    #[export]
+    fn find_entries_by_description(
+        #[try_from_ref] this: &Tfa,
+        userid: &str,
+        substring: &str,
+    ) -> Result<Vec<String>, Error> {
+        let entries = methods::list_user_tfa(&this.inner.lock().unwrap(), userid)?;
+        Ok(super::entries_by_description(&entries, substring))
+    }
+
     /// Returns `true` if the user still has other TFA entries left, `false` if the user has *no*
     /// more tfa entries.
     #[export]
@@ -352,6 +402,18 @@ mod export {
         }
     }
 
+    /// Move a TFA entry of any type from one user to another, keeping its id and state.
+    #[export]
+    fn move_entry(
+        #[try_from_ref] this: &Tfa,
+        from_userid: &str,
+        to_userid: &str,
+        entry_id: &str,
+    ) -> Result<(), Error> {
+        let mut inner = this.inner.lock().unwrap();
+        super::move_entry(&mut inner, from_userid, to_userid, entry_id)
+    }
+
     #[export]
     fn api_list_tfa(
         #[try_from_ref] this: &Tfa,
@@ -510,6 +572,130 @@ pub(crate) fn mkdir<P: AsRef<Path>>(path: P, mode: libc::mode_t) -> Result<(), E
     }
 }
 
+/// Returns the sorted ids of all users with at least one enabled TFA entry of the type `typename`.
+fn users_with_type(config: &TfaConfig, typename: &str) -> Result<Vec<String>, Error> {
+    let has_type: fn(&TfaUserData) -> bool = match typename {
+        "totp" | "oath" => |user| user.totp.iter().any(|entry| entry.info.enable),
+        "u2f" => |user| user.u2f.iter().any(|entry| entry.info.enable),
+        "webauthn" => |user| user.webauthn.iter().any(|entry| entry.info.enable),
+        "yubico" => |user| user.yubico.iter().any(|entry| entry.info.enable),
+        "recovery" => |user| {
+            user.recovery
+                .as_ref()
+                .is_some_and(|recovery| recovery.count_available() > 0)
+        },
+        _ => bail!("unrecognized TFA type {:?}", typename),
+    };
+
+    let mut users: Vec<String> = config
+        .users
+        .iter()
+        .filter(|(_userid, user)| has_type(user))
+        .map(|(userid, _user)| userid.clone())
+        .collect();
+
+    users.sort();
+
+    Ok(users)
+}
+
+/// Returns the ids of all `entries` whose description contains `substring`, ignoring case.
+fn entries_by_description(entries: &[TypedTfaInfo], substring: &str) -> Vec<String> {
+    let substring = substring.to_lowercase();
+
+    entries
+        .iter()
+        .filter(|entry| entry.info.description.to_lowercase().contains(&substring))
+        .map(|entry| entry.info.id.clone())
+        .collect()
+}
+
+/// Remove the entry `id` from `entries` and return it.
+fn take_entry<T>(entries: &mut Vec<TfaEntry<T>>, id: &str) -> Option<TfaEntry<T>> {
+    let index = entries.iter().position(|entry| entry.info.id == id)?;
+    Some(entries.remove(index))
+}
+
+/// Move the TFA entry `id` of any type from the user `from` to the user `to`, keeping its id and
+/// state.
+fn move_entry(config: &mut TfaConfig, from: &str, to: &str, id: &str) -> Result<(), Error> {
+    if from == to {
+        bail!("cannot move TFA entry {id:?} to the user it already belongs to");
+    }
+
+    if methods::get_tfa_entry(config, to, id).is_some() {
+        bail!("user {to:?} already has a TFA entry with id {id:?}");
+    }
+
+    let source = config
+        .users
+        .get_mut(from)
+        .ok_or_else(|| format_err!("no such entry"))?;
+
+    let mut moved = TfaUserData::default();
+
+    if let Some(entry) = take_entry(&mut source.totp, id) {
+        moved.totp.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.webauthn, id) {
+        moved.webauthn.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.u2f, id) {
+        moved.u2f.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.yubico, id) {
+        moved.yubico.push(entry);
+    } else if id == "recovery" && source.recovery.is_some() {
+        moved.recovery = source.recovery.take();
+    } else {
+        bail!("no such entry");
+    }
+
+    let target = config.users.entry(to.to_string()).or_default();
+    target.totp.append(&mut moved.totp);
+    target.webauthn.append(&mut moved.webauthn);
+    target.u2f.append(&mut moved.u2f);
+    target.yubico.append(&mut moved.yubico);
+    if moved.recovery.is_some() {
+        target.recovery = moved.recovery;
+    }
+
+    Ok(())
+}
+
+/// Replace the recovery keys of `userid` with a freshly generated set and return them.
+///
+/// The previous keys are invalidated. They are kept if generating the new ones fails.
+fn regenerate_recovery_keys(
+    config: &mut TfaConfig,
+    access: &UserAccess,
+    userid: &str,
+) -> Result<Vec<String>, Error> {
+    let previous = config
+        .users
+        .get_mut(userid)
+        .and_then(|user| user.recovery.take());
+
+    let result = methods::add_tfa_entry(
+        config,
+        access,
+        userid,
+        None,
+        None,
+        None,
+        None,
+        methods::TfaType::Recovery,
+        None,
+    );
+
+    match result {
+        Ok(info) => Ok(info.recovery),
+        Err(err) => {
+            if let Some(user) = config.users.get_mut(userid) {
+                user.recovery = previous;
+            }
+            Err(err)
+        }
+    }
+}
+
 #[cfg(debug_assertions)]
 #[derive(Clone)]
 #[repr(transparent)]
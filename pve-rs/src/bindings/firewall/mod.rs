@@ -2,3 +2,6 @@
 
 mod sdn;
 pub use sdn::pve_rs_firewall_sdn;
+
+mod rules;
+pub use rules::pve_rs_firewall_rules;
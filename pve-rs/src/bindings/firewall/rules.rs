@@ -0,0 +1,65 @@
+#[perlmod::package(name = "PVE::RS::Firewall::Rules", lib = "pve_rs")]
+pub mod pve_rs_firewall_rules {
+    //! The `PVE::RS::Firewall::Rules` package.
+    //!
+    //! Provides structured access to the rules of firewall configuration files, e.g. for editing
+    //! them in the GUI.
+
+    use anyhow::Error;
+
+    use crate::firewall;
+
+    pub use crate::firewall::{Definition, RefKind, Rule, RuleDiagnostic, RuleRef, Severity};
+
+    /// Parse the rules in `text`, one rule per line, into a list of structured rules.
+    ///
+    /// Empty lines and comment lines are skipped. Options which are not modeled explicitly are
+    /// kept verbatim in the `options` of a rule. Errors contain the line number.
+    ///
+    /// See [`firewall::parse_rules`].
+    #[export]
+    pub fn parse_rules(text: &str) -> Result<Vec<Rule>, Error> {
+        firewall::parse_rules(text)
+    }
+
+    /// Format structured rules in the canonical format of firewall configuration files.
+    ///
+    /// See [`firewall::format_rules`].
+    #[export]
+    pub fn format_rules(rules: Vec<Rule>) -> String {
+        firewall::format_rules(&rules)
+    }
+
+    /// Validate a firewall configuration file, or a plain list of rules.
+    ///
+    /// Returns a list of diagnostics with the line number, the severity (`warning` or `error`)
+    /// and a message, or an empty list if no problems were found.
+    ///
+    /// See [`firewall::validate_ruleset`].
+    #[export]
+    pub fn validate_ruleset(text: &str) -> Vec<RuleDiagnostic> {
+        firewall::validate_ruleset(text)
+    }
+
+    /// Find all rules and IPSet entries in a firewall configuration file referencing the alias
+    /// or IPSet `name`.
+    ///
+    /// If `name` is prefixed with `+`, only IPSets are considered. Each reference contains the
+    /// line number, the section header, the kind of the referenced object (`alias` or `ipset`)
+    /// and the referencing line. Fails if any rule or IPSet entry cannot be parsed.
+    ///
+    /// See [`firewall::find_references`].
+    #[export]
+    pub fn find_references(ruleset: &str, name: &str) -> Result<Vec<RuleRef>, Error> {
+        firewall::find_references(ruleset, name)
+    }
+
+    /// Returns the aliases and IPSets defined in a firewall configuration file, which are not
+    /// referenced anywhere in the same file. Fails if any rule or IPSet entry cannot be parsed.
+    ///
+    /// See [`firewall::list_unused`].
+    #[export]
+    pub fn list_unused_aliases(ruleset: &str) -> Result<Vec<Definition>, Error> {
+        firewall::list_unused(ruleset)
+    }
+}
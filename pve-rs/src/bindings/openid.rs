@@ -35,8 +35,11 @@ pub mod pve_rs_open_id {
         #[try_from_ref] this: &OpenId,
         state_dir: &str,
         realm: &str,
+        scopes: Option<Vec<String>>,
+        acr_values: Option<Vec<String>>,
+        prompt: Option<String>,
     ) -> Result<String, Error> {
-        proxmox_rs_oidc::authorize_url(this, state_dir, realm)
+        proxmox_rs_oidc::authorize_url(this, state_dir, realm, scopes, acr_values, prompt)
     }
 
     /// Method: Verify public auth state.
@@ -62,4 +65,35 @@ pub mod pve_rs_open_id {
     ) -> Result<Value, Error> {
         proxmox_rs_oidc::verify_authorization_code(this, code, private_auth_state, query_userinfo)
     }
+
+    /// Method: Query the claims of the provider's userinfo endpoint for an `access_token`.
+    ///
+    /// See [`proxmox_rs_oidc::fetch_userinfo`].
+    #[export]
+    pub fn fetch_userinfo(
+        #[try_from_ref] this: &OpenId,
+        access_token: &str,
+    ) -> Result<serde_json::Value, Error> {
+        proxmox_rs_oidc::fetch_userinfo(this, access_token)
+    }
+
+    /// Method: Build the URL for an RP-initiated logout at the provider.
+    ///
+    /// See [`proxmox_rs_oidc::end_session_url`].
+    #[export]
+    pub fn end_session_url(
+        #[try_from_ref] this: &OpenId,
+        id_token_hint: Option<String>,
+        post_logout_redirect: Option<String>,
+    ) -> Result<Option<String>, Error> {
+        proxmox_rs_oidc::end_session_url(this, id_token_hint, post_logout_redirect)
+    }
+
+    /// Method: Returns the raw discovery document advertised by the provider.
+    ///
+    /// See [`proxmox_rs_oidc::discovery_metadata`].
+    #[export]
+    pub fn discovery_metadata(#[try_from_ref] this: &OpenId) -> Result<serde_json::Value, Error> {
+        proxmox_rs_oidc::discovery_metadata(this)
+    }
 }
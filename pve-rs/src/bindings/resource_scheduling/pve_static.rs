@@ -6,9 +6,10 @@ pub mod pve_rs_resource_scheduling_static {
     //!
     //! See [`proxmox_resource_scheduling`].
 
+    use std::collections::HashSet;
     use std::sync::Mutex;
 
-    use anyhow::Error;
+    use anyhow::{Error, bail};
     use serde::{Deserialize, Serialize};
 
     use perlmod::Value;
@@ -54,6 +55,36 @@ pub mod pve_rs_resource_scheduling_static {
 
     type StaticResource = PveResource<StaticResourceStats>;
 
+    /// Constraints for the placement of a service.
+    #[derive(Default, Deserialize)]
+    #[serde(rename_all = "kebab-case")]
+    pub struct PlacementConstraints {
+        /// Services the service must not share a node with. Nodes running any of them are not
+        /// considered at all.
+        #[serde(default)]
+        pub anti_affinity: Vec<String>,
+        /// Nodes the service should preferably be placed on, e.g. the nodes of a group.
+        #[serde(default)]
+        pub preferred_nodes: Vec<String>,
+        /// The bonus added to the score of preferred nodes. Since scores are between 0 and 1, a
+        /// bonus of 1 or more always ranks preferred nodes first.
+        #[serde(default = "default_preference_bonus")]
+        pub preference_bonus: f64,
+    }
+
+    fn default_preference_bonus() -> f64 {
+        0.1
+    }
+
+    /// The score of a node for starting a service on it.
+    #[derive(Clone, Debug, Serialize)]
+    pub struct NodeScore {
+        /// The name of the node.
+        pub node: String,
+        /// The node's score, higher is better.
+        pub score: f64,
+    }
+
     /// Class method: Create a new [`Scheduler`] instance.
     ///
     /// See [`proxmox_resource_scheduling::usage::Usage::new`].
@@ -222,4 +253,66 @@ pub mod pve_rs_resource_scheduling_static {
             .to_scheduler::<StartedResourceAggregator>()
             .score_nodes_to_start_resource(service_stats)
     }
+
+    /// Method: Ranks the nodes for starting a service with the usage statistics `service_stats`
+    /// on, without adding the service to the scheduler.
+    ///
+    /// Returns all nodes with their score, where a higher score is better, sorted from the best to
+    /// the worst node. Nodes with the same score are sorted by name.
+    ///
+    /// The optional `constraints` exclude nodes running services the service must not share a
+    /// node with, and bias the score towards preferred nodes. Fails if no node is left after
+    /// excluding nodes.
+    ///
+    /// See [`proxmox_resource_scheduling::scheduler::Scheduler::score_nodes_to_start_resource`].
+    #[export]
+    pub fn rank_nodes(
+        #[try_from_ref] this: &Scheduler,
+        service_stats: StaticResourceStats,
+        constraints: Option<PlacementConstraints>,
+    ) -> Result<Vec<NodeScore>, Error> {
+        let usage = this.inner.lock().unwrap();
+        let constraints = constraints.unwrap_or_default();
+
+        let anti_affinity: HashSet<&str> = constraints
+            .anti_affinity
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let excluded: HashSet<String> = usage
+            .nodes_iter()
+            .filter(|(_nodename, node)| {
+                node.resources_iter().any(|sid| {
+                    let sid: &str = sid.as_ref();
+                    anti_affinity.contains(sid)
+                })
+            })
+            .map(|(nodename, _node)| nodename.to_string())
+            .collect();
+
+        let mut scores: Vec<NodeScore> = usage
+            .to_scheduler::<StartedResourceAggregator>()
+            .score_nodes_to_start_resource(service_stats)?
+            .into_iter()
+            .filter(|(node, _score)| !excluded.contains(node))
+            .map(|(node, mut score)| {
+                if constraints.preferred_nodes.contains(&node) {
+                    score += constraints.preference_bonus;
+                }
+                NodeScore { node, score }
+            })
+            .collect();
+
+        if scores.is_empty() {
+            bail!("no node satisfies the placement constraints");
+        }
+
+        scores.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.node.cmp(&b.node))
+        });
+
+        Ok(scores)
+    }
 }
@@ -5,22 +5,21 @@ pub mod pve_rs_sdn_fabrics {
     //! This provides the configuration for the SDN fabrics, as well as helper methods for reading
     //! / writing the configuration, as well as for generating ifupdown2 and FRR configuration.
 
-    use std::collections::{BTreeMap, HashMap, HashSet};
+    use std::collections::{BTreeMap, HashMap};
     use std::fmt::Write;
-    use std::net::IpAddr;
-    use std::ops::Deref;
     use std::process::Command;
     use std::sync::Mutex;
 
     use anyhow::{Context, Error, format_err};
-    use openssl::hash::{MessageDigest, hash};
     use proxmox_ve_config::sdn::fabric::section_config::node::api::{Node, NodeUpdater};
+    use serde::de::DeserializeOwned;
     use serde::{Deserialize, Serialize};
 
     use perlmod::Value;
+    use proxmox_http_error::HttpError;
 
     use proxmox_network_types::ip_address::{Cidr, Ipv4Cidr, Ipv6Cidr};
-    use proxmox_section_config::typed::SectionConfigData;
+    use proxmox_section_config::typed::{ApiSectionDataEntry, SectionConfigData};
     use proxmox_ve_config::common::valid::{Valid, Validatable};
 
     use proxmox_ve_config::sdn::config::{SdnConfig, ZoneConfig};
@@ -29,11 +28,15 @@ pub mod pve_rs_sdn_fabrics {
         Fabric as ConfigFabric, FabricId,
         api::{Fabric, FabricUpdater},
     };
-    use proxmox_ve_config::sdn::fabric::section_config::interface::InterfaceName;
-    use proxmox_ve_config::sdn::fabric::section_config::node::{Node as ConfigNode, NodeId};
+    use proxmox_ve_config::sdn::fabric::section_config::node::Node as ConfigNode;
     use proxmox_ve_config::sdn::fabric::{FabricConfig, FabricEntry};
 
-    use crate::sdn::status::{self, RunningConfig};
+    use crate::sdn::fabric;
+    use crate::sdn::status;
+
+    pub use crate::sdn::fabric::{
+        ApplyStrategy, ChangeKind, FabricChange, LintFinding, LintKind, LintSeverity,
+    };
 
     /// A SDN Fabric config instance.
     #[derive(Serialize, Deserialize)]
@@ -101,6 +104,27 @@ pub mod pve_rs_sdn_fabrics {
         )
     }
 
+    /// Remove all nodes from the raw configuration of `/etc/pve/sdn/fabrics.cfg`, whose fabric
+    /// does not exist anymore.
+    ///
+    /// Such a configuration cannot be loaded via [`config`], so this operates on the raw
+    /// configuration. Returns the cleaned up configuration, as well as the section ids of all
+    /// removed nodes.
+    ///
+    /// See [`fabric::prune_orphan_nodes`]
+    #[export]
+    pub fn prune_orphan_nodes(raw_config: &[u8]) -> Result<(String, Vec<String>), Error> {
+        let raw_config = std::str::from_utf8(raw_config)?;
+        let mut sections = Section::parse_section_config("fabrics.cfg", raw_config)?;
+
+        let removed = fabric::prune_orphan_nodes(&mut sections);
+
+        Ok((
+            Section::write_section_config("fabrics.cfg", &sections)?,
+            removed,
+        ))
+    }
+
     /// Method: Returns all fabrics and nodes from the configuration.
     #[export]
     pub fn list_all(
@@ -139,15 +163,42 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn list_fabrics_by_node(
         #[try_from_ref] this: &PerlFabricConfig,
-        node_id: NodeId,
-    ) -> BTreeMap<String, Fabric> {
-        this.fabric_config
+        node_id: &str,
+    ) -> Result<BTreeMap<String, Fabric>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+
+        Ok(this
+            .fabric_config
             .lock()
             .unwrap()
             .iter()
             .filter(|(_id, entry)| entry.get_node(&node_id).is_ok())
             .map(|(id, entry)| (id.to_string(), entry.fabric().clone()))
-            .collect()
+            .collect())
+    }
+
+    /// Method: Returns the ids of all fabrics that use a specific interface on a node.
+    ///
+    /// This includes fabrics using a VLAN interface on top of it, e.g. `ens19.100` for `ens19`.
+    /// Otherwise an interface should only be part of a single fabric, if more than one id is
+    /// returned the configuration contains a conflict.
+    ///
+    /// See [`fabric::fabrics_for_interface`]
+    #[export]
+    pub fn fabrics_for_interface(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node_id: &str,
+        interface_name: String,
+    ) -> Result<Vec<String>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+        let config = this.fabric_config.lock().unwrap();
+
+        Ok(
+            fabric::fabrics_for_interface(&config, &node_id, &interface_name)
+                .into_iter()
+                .map(|id| id.to_string())
+                .collect(),
+        )
     }
 
     /// Method: Adds a new Fabric to the configuration.
@@ -157,20 +208,19 @@ pub mod pve_rs_sdn_fabrics {
     pub fn add_fabric(
         #[try_from_ref] this: &PerlFabricConfig,
         fabric: Fabric,
+        digest: Option<&str>,
     ) -> Result<(), Error> {
-        this.fabric_config
-            .lock()
-            .unwrap()
-            .add_fabric(fabric)
-            .map_err(anyhow::Error::from)
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config.add_fabric(fabric).map_err(anyhow::Error::from)
     }
 
     /// Method: Read a Fabric from the configuration.
     #[export]
-    pub fn get_fabric(
-        #[try_from_ref] this: &PerlFabricConfig,
-        id: FabricId,
-    ) -> Result<Fabric, Error> {
+    pub fn get_fabric(#[try_from_ref] this: &PerlFabricConfig, id: &str) -> Result<Fabric, Error> {
+        let id = fabric::parse_fabric_id(id)?;
+
         this.fabric_config
             .lock()
             .unwrap()
@@ -183,12 +233,16 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn update_fabric(
         #[try_from_ref] this: &PerlFabricConfig,
-        id: FabricId,
+        id: &str,
         updater: FabricUpdater,
+        digest: Option<&str>,
     ) -> Result<(), Error> {
-        this.fabric_config
-            .lock()
-            .unwrap()
+        let id = fabric::parse_fabric_id(id)?;
+
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config
             .update_fabric(&id, updater)
             .map_err(anyhow::Error::from)
     }
@@ -197,13 +251,15 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn delete_fabric(
         #[try_from_ref] this: &PerlFabricConfig,
-        id: FabricId,
+        id: &str,
+        digest: Option<&str>,
     ) -> Result<FabricEntry, Error> {
-        this.fabric_config
-            .lock()
-            .unwrap()
-            .delete_fabric(&id)
-            .map_err(anyhow::Error::from)
+        let id = fabric::parse_fabric_id(id)?;
+
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config.delete_fabric(&id).map_err(anyhow::Error::from)
     }
 
     /// Method: List all nodes in the configuraiton.
@@ -228,8 +284,10 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn list_nodes_fabric(
         #[try_from_ref] this: &PerlFabricConfig,
-        fabric_id: FabricId,
+        fabric_id: &str,
     ) -> Result<BTreeMap<String, Node>, Error> {
+        let fabric_id = fabric::parse_fabric_id(fabric_id)?;
+
         Ok(this
             .fabric_config
             .lock()
@@ -245,9 +303,12 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn get_node(
         #[try_from_ref] this: &PerlFabricConfig,
-        fabric_id: FabricId,
-        node_id: NodeId,
+        fabric_id: &str,
+        node_id: &str,
     ) -> Result<Node, Error> {
+        let fabric_id = fabric::parse_fabric_id(fabric_id)?;
+        let node_id = fabric::parse_node_id(node_id)?;
+
         this.fabric_config
             .lock()
             .unwrap()
@@ -260,12 +321,17 @@ pub mod pve_rs_sdn_fabrics {
 
     /// Method: Add a node to a fabric.
     #[export]
-    pub fn add_node(#[try_from_ref] this: &PerlFabricConfig, node: Node) -> Result<(), Error> {
+    pub fn add_node(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node: Node,
+        digest: Option<&str>,
+    ) -> Result<(), Error> {
         let node = ConfigNode::from(node);
 
-        this.fabric_config
-            .lock()
-            .unwrap()
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config
             .get_fabric_mut(node.id().fabric_id())
             .map_err(anyhow::Error::from)?
             .add_node(node)
@@ -276,13 +342,18 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn update_node(
         #[try_from_ref] this: &PerlFabricConfig,
-        fabric_id: FabricId,
-        node_id: NodeId,
+        fabric_id: &str,
+        node_id: &str,
         updater: NodeUpdater,
+        digest: Option<&str>,
     ) -> Result<(), Error> {
-        this.fabric_config
-            .lock()
-            .unwrap()
+        let fabric_id = fabric::parse_fabric_id(fabric_id)?;
+        let node_id = fabric::parse_node_id(node_id)?;
+
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config
             .get_fabric_mut(&fabric_id)
             .map_err(anyhow::Error::from)?
             .update_node(&node_id, updater)
@@ -293,12 +364,17 @@ pub mod pve_rs_sdn_fabrics {
     #[export]
     pub fn delete_node(
         #[try_from_ref] this: &PerlFabricConfig,
-        fabric_id: FabricId,
-        node_id: NodeId,
+        fabric_id: &str,
+        node_id: &str,
+        digest: Option<&str>,
     ) -> Result<Node, Error> {
-        this.fabric_config
-            .lock()
-            .unwrap()
+        let fabric_id = fabric::parse_fabric_id(fabric_id)?;
+        let node_id = fabric::parse_node_id(node_id)?;
+
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        config
             .get_fabric_mut(&fabric_id)
             .map_err(anyhow::Error::from)?
             .delete_node(&node_id)
@@ -306,65 +382,44 @@ pub mod pve_rs_sdn_fabrics {
             .map_err(anyhow::Error::from)
     }
 
-    fn map_name(
-        mapping: &HashMap<String, String>,
-        name: &str,
-    ) -> Result<Option<InterfaceName>, Error> {
-        match name.split_once('.') {
-            Some((interface_name, vlan_id))
-                if !vlan_id.is_empty() && vlan_id.chars().all(char::is_numeric) =>
-            {
-                mapping
-                    .get(interface_name)
-                    .map(|mapped_name| {
-                        InterfaceName::from_string(format!("{mapped_name}.{vlan_id}"))
-                    })
-                    .transpose()
-            }
-            _ => mapping
-                .get(name)
-                .cloned()
-                .map(InterfaceName::from_string)
-                .transpose(),
-        }
+    /// Method: Delete a node from all fabrics it is part of.
+    ///
+    /// Returns the ids of the fabrics the node has been removed from.
+    ///
+    /// See [`fabric::delete_node_everywhere`]
+    #[export]
+    pub fn delete_node_everywhere(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node_id: &str,
+        digest: Option<&str>,
+    ) -> Result<Vec<String>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+
+        let mut config = this.fabric_config.lock().unwrap();
+        fabric::check_digest(&config, digest)?;
+
+        Ok(fabric::delete_node_everywhere(&mut config, &node_id)
+            .into_iter()
+            .map(|id| id.to_string())
+            .collect())
     }
 
     /// Method: Map all interface names of a node to a different one, according to the given
     /// mapping.
     ///
     /// Used by proxmox-network-interface-pinning
+    ///
+    /// See [`fabric::map_interfaces`]
     #[export]
     pub fn map_interfaces(
         #[try_from_ref] this: &PerlFabricConfig,
-        node_id: NodeId,
+        node_id: &str,
         mapping: HashMap<String, String>,
     ) -> Result<(), Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
         let mut config = this.fabric_config.lock().unwrap();
 
-        for entry in config.get_fabrics_mut() {
-            let Ok(node) = entry.get_node_mut(&node_id) else {
-                continue;
-            };
-
-            match node {
-                ConfigNode::Openfabric(node_section) => {
-                    for interface in node_section.properties_mut().interfaces_mut() {
-                        if let Some(mapped_name) = map_name(&mapping, interface.name())? {
-                            interface.set_name(mapped_name);
-                        }
-                    }
-                }
-                ConfigNode::Ospf(node_section) => {
-                    for interface in node_section.properties_mut().interfaces_mut() {
-                        if let Some(mapped_name) = map_name(&mapping, interface.name())? {
-                            interface.set_name(mapped_name);
-                        }
-                    }
-                }
-            }
-        }
-
-        Ok(())
+        fabric::map_interfaces(&mut config, &node_id, &mapping)
     }
 
     /// Method: Convert the configuration into the section config sections.
@@ -393,22 +448,40 @@ pub mod pve_rs_sdn_fabrics {
         this.fabric_config.lock().unwrap().write_section_config()
     }
 
+    /// Method: Compare the configuration with the raw configuration `other_raw`, in the format of
+    /// `/etc/pve/sdn/fabrics.cfg`.
+    ///
+    /// Returns all fabrics and nodes which are added, removed or modified in `other_raw`, along
+    /// with the names of the changed properties of modified ones.
+    ///
+    /// See [`fabric::diff_configs`]
+    #[export]
+    pub fn diff_configs(
+        #[try_from_ref] this: &PerlFabricConfig,
+        other_raw: &[u8],
+    ) -> Result<Vec<FabricChange>, Error> {
+        let other_raw = std::str::from_utf8(other_raw)?;
+        let other = FabricConfig::parse_section_config(other_raw)?.into_inner();
+
+        let config = this.fabric_config.lock().unwrap();
+        fabric::diff_configs(&config, &other)
+    }
+
     /// Method: Generate a digest for the whole configuration
     #[export]
     pub fn digest(#[try_from_ref] this: &PerlFabricConfig) -> Result<String, Error> {
         let config = this.fabric_config.lock().unwrap();
-        let data = serde_json::to_vec(config.deref())?;
-        let hash = hash(MessageDigest::sha256(), &data)?;
 
-        Ok(hex::encode(hash))
+        Ok(hex::encode(fabric::digest(&config)?))
     }
 
     /// Method: Return all interfaces of a node, that are part of a fabric.
     #[export]
     pub fn get_interfaces_for_node(
         #[try_from_ref] this: &PerlFabricConfig,
-        node_id: NodeId,
-    ) -> BTreeMap<String, PveInterface> {
+        node_id: &str,
+    ) -> Result<BTreeMap<String, PveInterface>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
         let config = this.fabric_config.lock().unwrap();
 
         let mut ifaces = BTreeMap::new();
@@ -422,41 +495,96 @@ pub mod pve_rs_sdn_fabrics {
             }
         }
 
-        ifaces
+        Ok(ifaces)
     }
 
     /// Method: Return all FRR daemons that need to be enabled for this fabric configuration
     /// instance.
     ///
     /// FRR is a single service and different protocols are implement in daemons which can be
-    /// activated using the `/etc/frr/daemons` file.
+    /// activated using the `/etc/frr/daemons` file. The daemons are sorted by name.
     ///
     /// <https://docs.frrouting.org/en/latest/setup.html#daemons-configuration-file>
+    ///
+    /// See [`fabric::enabled_daemons`]
     #[export]
     pub fn enabled_daemons(
         #[try_from_ref] this: &PerlFabricConfig,
-        node_id: NodeId,
-    ) -> Vec<String> {
+        node_id: &str,
+    ) -> Result<Vec<String>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
         let config = this.fabric_config.lock().unwrap();
 
-        let node_fabrics = config
-            .values()
-            .filter(|fabric| fabric.get_node(&node_id).is_ok());
+        Ok(fabric::enabled_daemons(&config, &node_id)
+            .into_iter()
+            .map(String::from)
+            .collect())
+    }
+
+    /// Method: Return whether FRR can be reloaded on the node `node_id` after applying this
+    /// configuration, or has to be restarted.
+    ///
+    /// `previous_daemons` are the daemons currently enabled on the node, e.g. as returned by
+    /// [`enabled_daemons`] for the previous configuration. Restarting FRR drops all adjacencies,
+    /// so it should only be done if the set of daemons changed.
+    ///
+    /// See [`fabric::apply_strategy`]
+    #[export]
+    pub fn frr_apply_strategy(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node_id: &str,
+        previous_daemons: Vec<String>,
+    ) -> Result<ApplyStrategy, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+        let config = this.fabric_config.lock().unwrap();
 
-        let mut daemons = HashSet::new();
+        Ok(fabric::apply_strategy(&config, &node_id, &previous_daemons))
+    }
 
-        for fabric in node_fabrics {
-            match fabric {
-                FabricEntry::Ospf(_) => {
-                    daemons.insert("ospfd");
-                }
-                FabricEntry::Openfabric(_) => {
-                    daemons.insert("fabricd");
-                }
-            };
-        }
+    /// Method: Return the FRR daemons that need to be enabled on each node of the cluster which is
+    /// part of at least one fabric, keyed by the node id.
+    ///
+    /// The daemons of each node are sorted by name, as with [`enabled_daemons`].
+    ///
+    /// See [`fabric::all_enabled_daemons`]
+    #[export]
+    pub fn all_enabled_daemons(
+        #[try_from_ref] this: &PerlFabricConfig,
+    ) -> BTreeMap<String, Vec<String>> {
+        let config = this.fabric_config.lock().unwrap();
 
-        daemons.into_iter().map(String::from).collect()
+        fabric::all_enabled_daemons(&config)
+            .into_iter()
+            .map(|(node_id, daemons)| (node_id, daemons.into_iter().map(String::from).collect()))
+            .collect()
+    }
+
+    /// Method: Checks the whole configuration for problems which are not caught when validating
+    /// it, e.g. fabrics with a single node, interfaces used by multiple fabrics, router-ids used
+    /// by multiple nodes, overlapping prefixes and nodes without an IP address.
+    ///
+    /// Returns a list of findings with their `severity` (`warning` or `error`), `kind`, the
+    /// offending `entity` and a human readable `message`. Errors are listed first.
+    ///
+    /// See [`fabric::lint`]
+    #[export]
+    pub fn lint(#[try_from_ref] this: &PerlFabricConfig) -> Vec<LintFinding> {
+        let config = this.fabric_config.lock().unwrap();
+        fabric::lint(&config)
+    }
+
+    /// Method: Validates the configuration without serializing it.
+    ///
+    /// Fails if the configuration is invalid and could not be written, or if it has problems which
+    /// make it not work as expected, e.g. interface or router-id conflicts. Otherwise returns a
+    /// list of non-fatal problems, e.g. nodes without interfaces, fabrics with a single node or
+    /// overlapping prefixes.
+    ///
+    /// See [`fabric::validate`]
+    #[export]
+    pub fn validate(#[try_from_ref] this: &PerlFabricConfig) -> Result<Vec<String>, Error> {
+        let config = this.fabric_config.lock().unwrap();
+        fabric::validate(&config)
     }
 
     /// Helper function to generate the default `/etc/network/interfaces` config for a given CIDR.
@@ -477,12 +605,90 @@ pub mod pve_rs_sdn_fabrics {
         Ok(interface)
     }
 
+    /// Helper function to render the dummy and member interfaces of a node in a fabric into
+    /// `interfaces`.
+    fn render_fabric_interfaces(
+        interfaces: &mut String,
+        fabric: &ConfigFabric,
+        node: &ConfigNode,
+    ) -> Result<(), Error> {
+        // dummy interface
+        for cidr in fabric::node_loopbacks(node)? {
+            let interface = render_interface(&format!("dummy_{}", fabric.id()), cidr, true)?;
+            writeln!(interfaces)?;
+            write!(interfaces, "{interface}")?;
+        }
+        match node {
+            ConfigNode::Openfabric(node_section) => {
+                for interface in node_section.properties().interfaces() {
+                    if let Some(ip) = interface.ip() {
+                        let interface = render_interface(interface.name(), Cidr::from(ip), false)?;
+                        writeln!(interfaces)?;
+                        write!(interfaces, "{interface}")?;
+                    }
+                    if let Some(ip) = interface.ip6() {
+                        let interface = render_interface(interface.name(), Cidr::from(ip), false)?;
+                        writeln!(interfaces)?;
+                        write!(interfaces, "{interface}")?;
+                    }
+
+                    // If no ip is configured, add auto and iface with node ip to bring interface up
+                    // OpenFabric doesn't really need an ip on the interface, but the problem
+                    // is that arp can't tell which source address to use in some cases, so
+                    // it's better if we set the node address on all the fabric interfaces.
+                    if let (None, None) = (interface.ip(), interface.ip6()) {
+                        let cidr = Cidr::from(fabric::node_interface_address(node)?);
+                        let interface = render_interface(interface.name(), cidr, false)?;
+                        writeln!(interfaces)?;
+                        write!(interfaces, "{interface}")?;
+                    }
+                }
+            }
+            ConfigNode::Ospf(node_section) => {
+                for interface in node_section.properties().interfaces() {
+                    if let Some(ip) = interface.ip() {
+                        let interface = render_interface(interface.name(), Cidr::from(ip), false)?;
+                        writeln!(interfaces)?;
+                        write!(interfaces, "{interface}")?;
+                    } else {
+                        // Use the node address if the interface has no ip, falling back to the
+                        // IPv6 address for IPv6-only fabrics.
+                        let cidr = Cidr::from(fabric::node_interface_address(node)?);
+                        let interface = render_interface(interface.name(), cidr, false)?;
+                        writeln!(interfaces)?;
+                        write!(interfaces, "{interface}")?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Method: Return the addresses of the `dummy_<fabric>` loopback interfaces of a node, keyed
+    /// by the fabric id.
+    ///
+    /// These are also the router-ids of the node in the respective fabric.
+    ///
+    /// See [`fabric::fabric_loopbacks`]
+    #[export]
+    pub fn fabric_loopbacks(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node_id: &str,
+    ) -> Result<BTreeMap<String, Vec<Cidr>>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+        let config = this.fabric_config.lock().unwrap();
+
+        fabric::fabric_loopbacks(&config, &node_id)
+    }
+
     /// Method: Generate the ifupdown2 configuration for a given node.
     #[export]
     pub fn get_interfaces_etc_network_config(
         #[try_from_ref] this: &PerlFabricConfig,
-        node_id: NodeId,
+        node_id: &str,
     ) -> Result<String, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
         let config = this.fabric_config.lock().unwrap();
         let mut interfaces = String::new();
 
@@ -494,103 +700,191 @@ pub mod pve_rs_sdn_fabrics {
         });
 
         for (fabric, node) in node_fabrics {
-            // dummy interface
-            if let Some(ip) = node.ip() {
-                let interface = render_interface(
-                    &format!("dummy_{}", fabric.id()),
-                    Cidr::new_v4(ip, 32)?,
-                    true,
-                )?;
-                writeln!(interfaces)?;
-                write!(interfaces, "{interface}")?;
-            }
-            if let Some(ip6) = node.ip6() {
-                let interface = render_interface(
-                    &format!("dummy_{}", fabric.id()),
-                    Cidr::new_v6(ip6, 128)?,
-                    true,
-                )?;
-                writeln!(interfaces)?;
-                write!(interfaces, "{interface}")?;
-            }
-            match node {
-                ConfigNode::Openfabric(node_section) => {
-                    for interface in node_section.properties().interfaces() {
-                        if let Some(ip) = interface.ip() {
-                            let interface =
-                                render_interface(interface.name(), Cidr::from(ip), false)?;
-                            writeln!(interfaces)?;
-                            write!(interfaces, "{interface}")?;
-                        }
-                        if let Some(ip) = interface.ip6() {
-                            let interface =
-                                render_interface(interface.name(), Cidr::from(ip), false)?;
-                            writeln!(interfaces)?;
-                            write!(interfaces, "{interface}")?;
-                        }
-
-                        // If no ip is configured, add auto and iface with node ip to bring interface up
-                        // OpenFabric doesn't really need an ip on the interface, but the problem
-                        // is that arp can't tell which source address to use in some cases, so
-                        // it's better if we set the node address on all the fabric interfaces.
-                        if let (None, None) = (interface.ip(), interface.ip6()) {
-                            let cidr = Cidr::from(if let Some(ip) = node.ip() {
-                                IpAddr::from(ip)
-                            } else if let Some(ip) = node.ip6() {
-                                IpAddr::from(ip)
-                            } else {
-                                anyhow::bail!("there has to be a ipv4 or ipv6 node address");
-                            });
-                            let interface = render_interface(interface.name(), cidr, false)?;
-                            writeln!(interfaces)?;
-                            write!(interfaces, "{interface}")?;
-                        }
-                    }
-                }
-                ConfigNode::Ospf(node_section) => {
-                    for interface in node_section.properties().interfaces() {
-                        if let Some(ip) = interface.ip() {
-                            let interface =
-                                render_interface(interface.name(), Cidr::from(ip), false)?;
-                            writeln!(interfaces)?;
-                            write!(interfaces, "{interface}")?;
-                        } else {
-                            let interface = render_interface(
-                                interface.name(),
-                                Cidr::from(IpAddr::from(node.ip().ok_or_else(|| {
-                                    anyhow::anyhow!("there has to be a ipv4 address")
-                                })?)),
-                                false,
-                            )?;
-                            writeln!(interfaces)?;
-                            write!(interfaces, "{interface}")?;
-                        }
-                    }
-                }
-            }
+            render_fabric_interfaces(&mut interfaces, fabric, node)?;
         }
 
         Ok(interfaces)
     }
 
+    /// Method: Generate the ifupdown2 configuration for a given node, only containing the
+    /// interfaces of a single fabric.
+    ///
+    /// Returns an error if the node is not part of the fabric.
+    #[export]
+    pub fn get_interfaces_etc_network_config_for_fabric(
+        #[try_from_ref] this: &PerlFabricConfig,
+        node_id: &str,
+        fabric_id: &str,
+    ) -> Result<String, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
+        let fabric_id = fabric::parse_fabric_id(fabric_id)?;
+        let config = this.fabric_config.lock().unwrap();
+        let mut interfaces = String::new();
+
+        let entry = config.get_fabric(&fabric_id)?;
+        let node = entry
+            .get_node(&node_id)
+            .with_context(|| format!("node '{node_id}' is not part of fabric '{fabric_id}'"))?;
+
+        render_fabric_interfaces(&mut interfaces, entry.fabric(), node)?;
+
+        Ok(interfaces)
+    }
+
+    /// The path of the running-config, which contains the applied SDN configuration.
+    const RUNNING_CONFIG_PATH: &str = "/etc/pve/sdn/.running-config";
+
+    /// Where the status exports read the running-config and the state of FRR from.
+    ///
+    /// The exports use [`LocalNode`], tests can pass a canned running-config and canned vtysh
+    /// output instead.
+    pub(crate) trait StatusSource {
+        /// Returns the raw running-config.
+        fn running_config(&self) -> Result<String, Error>;
+
+        /// Returns the output of the vtysh `command`, which is empty if FRR is not running.
+        fn vtysh(&self, command: &str) -> Result<String, Error>;
+
+        /// Returns the name of this node.
+        fn hostname(&self) -> &str;
+    }
+
+    /// Reads the running-config from the cluster filesystem and queries the FRR of this node.
+    pub(crate) struct LocalNode;
+
+    impl StatusSource for LocalNode {
+        fn running_config(&self) -> Result<String, Error> {
+            Ok(std::fs::read_to_string(RUNNING_CONFIG_PATH)?)
+        }
+
+        fn vtysh(&self, command: &str) -> Result<String, Error> {
+            let output = Command::new("sh")
+                .args(["-c", &format!("vtysh -c '{command}'")])
+                .output()?;
+
+            Ok(String::from_utf8(output.stdout)?)
+        }
+
+        fn hostname(&self) -> &str {
+            proxmox_sys::nodename()
+        }
+    }
+
     /// Read and parse the running-config and get the fabrics section
     ///
     /// This will return a valid FabricConfig. Note that we read the file manually and not through
     /// the cluster filesystem as with perl, so this will be slower. If the running config exists,
     /// but no fabric is configured, `Ok(None)` is returned.
-    fn get_fabrics_config() -> Result<Option<Valid<FabricConfig>>, anyhow::Error> {
-        let raw_config = std::fs::read_to_string("/etc/pve/sdn/.running-config")?;
-        let running_config: RunningConfig =
-            serde_json::from_str(&raw_config).with_context(|| "error parsing running-config")?;
-        let Some(fabrics) = running_config.fabrics else {
-            return Ok(None);
-        };
-        let section_config = SectionConfigData::from_iter(fabrics.ids);
-        Some(
-            FabricConfig::from_section_config(section_config)
-                .with_context(|| "error converting section config to fabricconfig"),
+    ///
+    /// See [`status::parse_fabrics_config`]
+    fn get_fabrics_config(
+        source: &impl StatusSource,
+    ) -> Result<Option<Valid<FabricConfig>>, anyhow::Error> {
+        status::parse_fabrics_config(&source.running_config()?)
+    }
+
+    /// Turn a failure to read the running-config or to query FRR into an internal server error.
+    fn internal_error(err: Error) -> HttpError {
+        HttpError::new(http::StatusCode::INTERNAL_SERVER_ERROR, format!("{err:#}"))
+    }
+
+    /// Parse `fabric_id` and read the running fabric config for the fabric status exports.
+    ///
+    /// Fails with a `NOT_FOUND` error if no fabrics are configured, so callers can distinguish a
+    /// missing fabric from other failures.
+    fn running_fabric_config(
+        source: &impl StatusSource,
+        fabric_id: &str,
+    ) -> Result<(FabricId, Valid<FabricConfig>), HttpError> {
+        let fabric_id = fabric::parse_fabric_id(fabric_id)
+            .map_err(|err| HttpError::new(http::StatusCode::BAD_REQUEST, err.to_string()))?;
+
+        match get_fabrics_config(source) {
+            Ok(Some(config)) => Ok((fabric_id, config)),
+            Ok(None) => Err(status::fabric_not_found(&fabric_id)),
+            Err(err)
+                if err
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|err| err.kind() == std::io::ErrorKind::NotFound) =>
+            {
+                Err(status::fabric_not_found(&fabric_id))
+            }
+            Err(err) => Err(internal_error(err)),
+        }
+    }
+
+    /// Run a vtysh command and parse its json output.
+    ///
+    /// FRR returns an empty string if the daemon is not running, in that case the default value is
+    /// returned.
+    fn vtysh_json<T: DeserializeOwned + Default>(
+        source: &impl StatusSource,
+        command: &str,
+        what: &str,
+    ) -> Result<T, Error> {
+        let output = source.vtysh(command)?;
+
+        if output.is_empty() {
+            return Ok(T::default());
+        }
+
+        serde_json::from_str(&output).with_context(|| format!("error parsing {what}"))
+    }
+
+    /// Get all the openfabric ipv4 and ipv6 routes from FRR.
+    fn openfabric_routes(source: &impl StatusSource) -> Result<proxmox_frr::de::Routes, Error> {
+        let mut routes: proxmox_frr::de::Routes = vtysh_json(
+            source,
+            "show ip route openfabric json",
+            "openfabric ipv4 routes",
+        )?;
+        let ipv6_routes: proxmox_frr::de::Routes = vtysh_json(
+            source,
+            "show ipv6 route openfabric json",
+            "openfabric ipv6 routes",
+        )?;
+        routes.0.extend(ipv6_routes.0);
+
+        Ok(routes)
+    }
+
+    /// Get all the ospf routes from FRR.
+    fn ospf_routes(source: &impl StatusSource) -> Result<proxmox_frr::de::Routes, Error> {
+        vtysh_json(source, "show ip route ospf json", "ospf routes")
+    }
+
+    /// Get all the openfabric neighbors from FRR.
+    fn openfabric_neighbors(
+        source: &impl StatusSource,
+    ) -> Result<proxmox_frr::de::openfabric::Neighbors, Error> {
+        vtysh_json(
+            source,
+            "show openfabric neighbor detail json",
+            "openfabric neighbors",
         )
-        .transpose()
+    }
+
+    /// Get all the ospf neighbors from FRR.
+    fn ospf_neighbors(
+        source: &impl StatusSource,
+    ) -> Result<proxmox_frr::de::ospf::Neighbors, Error> {
+        vtysh_json(source, "show ip ospf neighbor json", "ospf neighbors")
+    }
+
+    /// Get all the openfabric interfaces from FRR.
+    fn openfabric_interfaces(
+        source: &impl StatusSource,
+    ) -> Result<proxmox_frr::de::openfabric::Interfaces, Error> {
+        vtysh_json(
+            source,
+            "show openfabric interface json",
+            "openfabric interfaces",
+        )
+    }
+
+    /// Get all the ospf interfaces from FRR.
+    fn ospf_interfaces(source: &impl StatusSource) -> Result<status::ospf::Interfaces, Error> {
+        vtysh_json(source, "show ip ospf interface json", "ospf interfaces")
     }
 
     /// Get the routes that have been learned and distributed by this specific fabric on this node.
@@ -598,185 +892,90 @@ pub mod pve_rs_sdn_fabrics {
     /// Read and parse the fabric config to get the protocol and the interfaces. Parse the vtysh
     /// output and assign the routes to a fabric by using the interface list. Return a list of
     /// common route structs.
-    #[export]
-    fn routes(fabric_id: FabricId) -> Result<Vec<status::RouteStatus>, Error> {
+    ///
+    /// Fails with a `NOT_FOUND` error if the fabric does not exist.
+    #[export(serialize_error)]
+    fn routes(fabric_id: &str) -> Result<Vec<status::RouteStatus>, HttpError> {
+        fabric_routes(&LocalNode, fabric_id)
+    }
+
+    /// Get the routes of the fabric `fabric_id` from `source`, see [`routes`].
+    pub(crate) fn fabric_routes(
+        source: &impl StatusSource,
+        fabric_id: &str,
+    ) -> Result<Vec<status::RouteStatus>, HttpError> {
         // Read fabric config to get protocol of fabric
-        let config = get_fabrics_config()?;
-        let Some(config) = config else {
-            anyhow::bail!("no fabrics configured");
-        };
+        let (fabric_id, config) = running_fabric_config(source, fabric_id)?;
 
-        let fabric = config.get_fabric(&fabric_id)?;
-        match fabric {
-            FabricEntry::Openfabric(_) => {
-                let openfabric_ipv4_routes_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show ip route openfabric json'"])
-                        .output()?
-                        .stdout,
-                )?;
-
-                let openfabric_ipv6_routes_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show ipv6 route openfabric json'"])
-                        .output()?
-                        .stdout,
-                )?;
-
-                let mut openfabric_routes: proxmox_frr::de::Routes =
-                    if openfabric_ipv4_routes_string.is_empty() {
-                        proxmox_frr::de::Routes::default()
-                    } else {
-                        serde_json::from_str(&openfabric_ipv4_routes_string)
-                            .with_context(|| "error parsing openfabric ipv4 routes")?
-                    };
-                if !openfabric_ipv6_routes_string.is_empty() {
-                    let openfabric_ipv6_routes: proxmox_frr::de::Routes =
-                        serde_json::from_str(&openfabric_ipv6_routes_string)
-                            .with_context(|| "error parsing openfabric ipv6 routes")?;
-                    openfabric_routes.0.extend(openfabric_ipv6_routes.0);
-                }
-                status::get_routes(
-                    fabric_id,
-                    config,
-                    openfabric_routes,
-                    proxmox_sys::nodename(),
-                )
-            }
-            FabricEntry::Ospf(_) => {
-                let ospf_routes_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show ip route ospf json'"])
-                        .output()?
-                        .stdout,
-                )?;
-                let ospf_routes: proxmox_frr::de::Routes = if ospf_routes_string.is_empty() {
-                    proxmox_frr::de::Routes::default()
-                } else {
-                    serde_json::from_str(&ospf_routes_string)
-                        .with_context(|| "error parsing ospf routes")?
-                };
-
-                status::get_routes(fabric_id, config, ospf_routes, proxmox_sys::nodename())
-            }
+        let routes = match status::fabric_entry(&config, &fabric_id)? {
+            FabricEntry::Openfabric(_) => openfabric_routes(source),
+            FabricEntry::Ospf(_) => ospf_routes(source),
         }
+        .map_err(internal_error)?;
+
+        status::get_routes(fabric_id, config, routes, source.hostname()).map_err(internal_error)
     }
 
     /// Get the neighbors for this specific fabric on this node
     ///
     /// Read and parse the fabric config to get the fabric protocol and the interfaces (ospf).
     /// Parse the frr output of the neighbor commands and return a common format.
-    #[export]
-    fn neighbors(fabric_id: FabricId) -> Result<status::NeighborStatus, Error> {
-        // Read fabric config to get protocol of fabric
-        let config = get_fabrics_config()?;
-        let Some(config) = config else {
-            anyhow::bail!("no fabrics configured");
-        };
-
-        let fabric = config.get_fabric(&fabric_id)?;
-
-        match fabric {
-            FabricEntry::Openfabric(_) => {
-                let openfabric_neighbors_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show openfabric neighbor detail json'"])
-                        .output()?
-                        .stdout,
-                )?;
-                let openfabric_neighbors: proxmox_frr::de::openfabric::Neighbors =
-                    if openfabric_neighbors_string.is_empty() {
-                        proxmox_frr::de::openfabric::Neighbors::default()
-                    } else {
-                        serde_json::from_str(&openfabric_neighbors_string)
-                            .with_context(|| "error parsing openfabric neighbors")?
-                    };
+    ///
+    /// Fails with a `NOT_FOUND` error if the fabric does not exist.
+    #[export(serialize_error)]
+    fn neighbors(fabric_id: &str) -> Result<status::NeighborStatus, HttpError> {
+        fabric_neighbors(&LocalNode, fabric_id)
+    }
 
-                status::get_neighbors_openfabric(fabric_id, openfabric_neighbors).map(|v| v.into())
-            }
-            FabricEntry::Ospf(fabric) => {
-                let ospf_neighbors_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show ip ospf neighbor json'"])
-                        .output()?
-                        .stdout,
-                )?;
-                let ospf_neighbors: proxmox_frr::de::ospf::Neighbors =
-                    if ospf_neighbors_string.is_empty() {
-                        proxmox_frr::de::ospf::Neighbors::default()
-                    } else {
-                        serde_json::from_str(&ospf_neighbors_string)
-                            .with_context(|| "error parsing ospf neighbors")?
-                    };
-
-                status::get_neighbors_ospf(
-                    fabric_id,
-                    fabric,
-                    ospf_neighbors,
-                    proxmox_sys::nodename(),
-                )
-                .map(|v| v.into())
-            }
+    /// Get the neighbors in the fabric `fabric_id` from `source`, see [`neighbors`].
+    pub(crate) fn fabric_neighbors(
+        source: &impl StatusSource,
+        fabric_id: &str,
+    ) -> Result<status::NeighborStatus, HttpError> {
+        // Read fabric config to get protocol of fabric
+        let (fabric_id, config) = running_fabric_config(source, fabric_id)?;
+
+        match status::fabric_entry(&config, &fabric_id)? {
+            FabricEntry::Openfabric(_) => openfabric_neighbors(source).and_then(|neighbors| {
+                status::get_neighbors_openfabric(fabric_id, neighbors).map(|v| v.into())
+            }),
+            FabricEntry::Ospf(fabric) => ospf_neighbors(source).and_then(|neighbors| {
+                status::get_neighbors_ospf(fabric_id, fabric, neighbors, source.hostname())
+                    .map(|v| v.into())
+            }),
         }
+        .map_err(internal_error)
     }
 
     /// Get the interfaces for this specific fabric on this node
     ///
     /// Read and parse the fabric config to get the protocol of the fabric and retrieve the
     /// interfaces (ospf). Convert the frr output into a common format of fabric interfaces.
-    #[export]
-    fn interfaces(fabric_id: FabricId) -> Result<status::InterfaceStatus, Error> {
-        // Read fabric config to get protocol of fabric
-        let config = get_fabrics_config()?;
-        let Some(config) = config else {
-            anyhow::bail!("no fabrics configured");
-        };
-
-        let fabric = config.get_fabric(&fabric_id)?;
-
-        match fabric {
-            FabricEntry::Openfabric(_) => {
-                let openfabric_interface_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show openfabric interface json'"])
-                        .output()?
-                        .stdout,
-                )?;
-                let openfabric_interfaces: proxmox_frr::de::openfabric::Interfaces =
-                    if openfabric_interface_string.is_empty() {
-                        proxmox_frr::de::openfabric::Interfaces::default()
-                    } else {
-                        serde_json::from_str(&openfabric_interface_string)
-                            .with_context(|| "error parsing openfabric interfaces")?
-                    };
+    ///
+    /// Fails with a `NOT_FOUND` error if the fabric does not exist.
+    #[export(serialize_error)]
+    fn interfaces(fabric_id: &str) -> Result<status::InterfaceStatus, HttpError> {
+        fabric_interfaces(&LocalNode, fabric_id)
+    }
 
-                status::get_interfaces_openfabric(fabric_id, openfabric_interfaces)
+    /// Get the interfaces in the fabric `fabric_id` from `source`, see [`interfaces`].
+    pub(crate) fn fabric_interfaces(
+        source: &impl StatusSource,
+        fabric_id: &str,
+    ) -> Result<status::InterfaceStatus, HttpError> {
+        // Read fabric config to get protocol of fabric
+        let (fabric_id, config) = running_fabric_config(source, fabric_id)?;
+
+        match status::fabric_entry(&config, &fabric_id)? {
+            FabricEntry::Openfabric(_) => openfabric_interfaces(source).and_then(|interfaces| {
+                status::get_interfaces_openfabric(fabric_id, interfaces).map(|v| v.into())
+            }),
+            FabricEntry::Ospf(fabric) => ospf_interfaces(source).and_then(|interfaces| {
+                status::get_interfaces_ospf(fabric_id, fabric, interfaces, source.hostname())
                     .map(|v| v.into())
-            }
-            FabricEntry::Ospf(fabric) => {
-                let ospf_interfaces_string = String::from_utf8(
-                    Command::new("sh")
-                        .args(["-c", "vtysh -c 'show ip ospf interface json'"])
-                        .output()?
-                        .stdout,
-                )?;
-                let ospf_interfaces: proxmox_frr::de::ospf::Interfaces =
-                    if ospf_interfaces_string.is_empty() {
-                        proxmox_frr::de::ospf::Interfaces::default()
-                    } else {
-                        serde_json::from_str(&ospf_interfaces_string)
-                            .with_context(|| "error parsing ospf interfaces")?
-                    };
-
-                status::get_interfaces_ospf(
-                    fabric_id,
-                    fabric,
-                    ospf_interfaces,
-                    proxmox_sys::nodename(),
-                )
-                .map(|v| v.into())
-            }
+            }),
         }
+        .map_err(internal_error)
     }
 
     /// Return the status of all fabrics on this node.
@@ -786,62 +985,122 @@ pub mod pve_rs_sdn_fabrics {
     /// config. If there are, show "ok" as status, otherwise "not ok".
     #[export]
     fn status() -> Result<HashMap<FabricId, status::Status>, Error> {
+        fabrics_status(&LocalNode)
+    }
+
+    /// Get the status of all fabrics from `source`, see [`status`].
+    pub(crate) fn fabrics_status(
+        source: &impl StatusSource,
+    ) -> Result<HashMap<FabricId, status::Status>, Error> {
         // This gets called every few seconds from pve to get the status of the fabrics into the
         // network resources. It is possible that the .running-config doesn't exist or no fabric is
         // configured -- in that case just return nothing.
-        let config = get_fabrics_config();
+        let config = get_fabrics_config(source);
         let Ok(Some(config)) = config else {
             return Ok(HashMap::new());
         };
 
-        let openfabric_ipv4_routes_string = String::from_utf8(
-            Command::new("sh")
-                .args(["-c", "vtysh -c 'show ip route openfabric json'"])
-                .output()?
-                .stdout,
-        )?;
+        let route_status = status::RoutesParsed {
+            openfabric: openfabric_routes(source)?,
+            ospf: ospf_routes(source)?,
+        };
 
-        let openfabric_ipv6_routes_string = String::from_utf8(
-            Command::new("sh")
-                .args(["-c", "vtysh -c 'show ipv6 route openfabric json'"])
-                .output()?
-                .stdout,
-        )?;
+        status::get_status(config, route_status, source.hostname())
+    }
 
-        let ospf_routes_string = String::from_utf8(
-            Command::new("sh")
-                .args(["-c", "vtysh -c 'show ip route ospf json'"])
-                .output()?
-                .stdout,
-        )?;
+    /// Combined status, neighbors, interfaces and routes of a single fabric on this node.
+    #[derive(Serialize)]
+    pub struct FabricOverview {
+        /// The status of the fabric, `None` if this node is not part of the fabric.
+        status: Option<status::Status>,
+        /// The neighbors of this node in the fabric.
+        neighbors: status::NeighborStatus,
+        /// The interfaces of this node in the fabric.
+        interfaces: status::InterfaceStatus,
+        /// The routes learned and distributed by the fabric.
+        routes: Vec<status::RouteStatus>,
+    }
 
-        let mut openfabric_routes: proxmox_frr::de::Routes =
-            if openfabric_ipv4_routes_string.is_empty() {
-                proxmox_frr::de::Routes::default()
-            } else {
-                serde_json::from_str(&openfabric_ipv4_routes_string)
-                    .with_context(|| "error parsing openfabric ipv4 routes")?
-            };
-        if !openfabric_ipv6_routes_string.is_empty() {
-            let openfabric_ipv6_routes: proxmox_frr::de::Routes =
-                serde_json::from_str(&openfabric_ipv6_routes_string)
-                    .with_context(|| "error parsing openfabric ipv6 routes")?;
-            openfabric_routes.0.extend(openfabric_ipv6_routes.0);
-        }
+    /// Get the status, neighbors, interfaces and routes for this specific fabric on this node.
+    ///
+    /// This is equivalent to calling [`status`], [`neighbors`], [`interfaces`] and [`routes`],
+    /// but only queries FRR once for every needed piece of information.
+    ///
+    /// Fails with a `NOT_FOUND` error if the fabric does not exist.
+    #[export(serialize_error)]
+    fn fabric_overview(fabric_id: &str) -> Result<FabricOverview, HttpError> {
+        let (fabric_id, config) = running_fabric_config(&LocalNode, fabric_id)?;
+        status::fabric_entry(&config, &fabric_id)?;
 
-        let ospf_routes: proxmox_frr::de::Routes = if ospf_routes_string.is_empty() {
-            proxmox_frr::de::Routes::default()
-        } else {
-            serde_json::from_str(&ospf_routes_string)
-                .with_context(|| "error parsing ospf routes")?
-        };
+        get_fabric_overview(&LocalNode, fabric_id, config).map_err(internal_error)
+    }
 
-        let route_status = status::RoutesParsed {
-            openfabric: openfabric_routes,
-            ospf: ospf_routes,
-        };
+    /// Get the overview of the existing fabric `fabric_id`, see [`fabric_overview`].
+    fn get_fabric_overview(
+        source: &impl StatusSource,
+        fabric_id: FabricId,
+        config: Valid<FabricConfig>,
+    ) -> Result<FabricOverview, Error> {
+        let hostname = source.hostname();
+
+        let (route_status, fabric_routes, neighbors, interfaces) =
+            match config.get_fabric(&fabric_id)? {
+                FabricEntry::Openfabric(_) => {
+                    let routes = openfabric_routes(source)?;
+                    (
+                        status::RoutesParsed {
+                            openfabric: routes.clone(),
+                            ospf: Default::default(),
+                        },
+                        routes,
+                        status::get_neighbors_openfabric(
+                            fabric_id.clone(),
+                            openfabric_neighbors(source)?,
+                        )?
+                        .into(),
+                        status::get_interfaces_openfabric(
+                            fabric_id.clone(),
+                            openfabric_interfaces(source)?,
+                        )?
+                        .into(),
+                    )
+                }
+                FabricEntry::Ospf(fabric) => {
+                    let routes = ospf_routes(source)?;
+                    (
+                        status::RoutesParsed {
+                            openfabric: Default::default(),
+                            ospf: routes.clone(),
+                        },
+                        routes,
+                        status::get_neighbors_ospf(
+                            fabric_id.clone(),
+                            fabric,
+                            ospf_neighbors(source)?,
+                            hostname,
+                        )?
+                        .into(),
+                        status::get_interfaces_ospf(
+                            fabric_id.clone(),
+                            fabric,
+                            ospf_interfaces(source)?,
+                            hostname,
+                        )?
+                        .into(),
+                    )
+                }
+            };
+
+        let routes =
+            status::get_routes(fabric_id.clone(), config.clone(), fabric_routes, hostname)?;
+        let status = status::get_status(config, route_status, hostname)?.remove(&fabric_id);
 
-        status::get_status(config, route_status, proxmox_sys::nodename())
+        Ok(FabricOverview {
+            status,
+            neighbors,
+            interfaces,
+            routes,
+        })
     }
 
     /// Get all the L3 routes for the passed zone.
@@ -850,15 +1109,11 @@ pub mod pve_rs_sdn_fabrics {
     /// zone.
     #[export]
     fn l3vpn_routes(zone: String) -> Result<status::L3VPNRoutes, Error> {
-        let command = format!("vtysh -c 'show ip route vrf vrf_{zone} json'");
-        let l3vpn_routes_string =
-            String::from_utf8(Command::new("sh").args(["-c", &command]).output()?.stdout)?;
-        let l3vpn_routes: proxmox_frr::de::Routes = if l3vpn_routes_string.is_empty() {
-            proxmox_frr::de::Routes::default()
-        } else {
-            serde_json::from_str(&l3vpn_routes_string)
-                .with_context(|| "error parsing l3vpn routes")?
-        };
+        let l3vpn_routes: proxmox_frr::de::Routes = vtysh_json(
+            &LocalNode,
+            &format!("show ip route vrf vrf_{zone} json"),
+            "l3vpn routes",
+        )?;
 
         status::get_l3vpn_routes(&format!("vrf_{zone}"), l3vpn_routes)
     }
@@ -870,10 +1125,34 @@ pub mod pve_rs_sdn_fabrics {
     /// To filter by vnet, get the VNI of the vnet from the config and use it in the command.
     #[export]
     fn l2vpn_routes(vnet: String) -> Result<status::L2VPNRoutes, Error> {
+        get_l2vpn_routes_page(&LocalNode, &vnet, 0, None).map(|page| page.routes)
+    }
+
+    /// Get a page of the L2 routes for the passed vnet, together with the total number of routes.
+    ///
+    /// Returns at most `limit` routes (all if unset), starting at `offset` (default 0). The routes
+    /// are sorted by their prefix, so the pages are consistent across calls.
+    ///
+    /// See [`l2vpn_routes`].
+    #[export]
+    fn l2vpn_routes_page(
+        vnet: String,
+        offset: Option<usize>,
+        limit: Option<usize>,
+    ) -> Result<status::L2VPNRoutePage, Error> {
+        get_l2vpn_routes_page(&LocalNode, &vnet, offset.unwrap_or(0), limit)
+    }
+
+    /// Query FRR for the L2 routes of `vnet` and convert the requested page of them.
+    pub(crate) fn get_l2vpn_routes_page(
+        source: &impl StatusSource,
+        vnet: &str,
+        offset: usize,
+        limit: Option<usize>,
+    ) -> Result<status::L2VPNRoutePage, Error> {
         // read config to get the vni of the vnet
-        let raw_config = std::fs::read_to_string("/etc/pve/sdn/.running-config")?;
         let running_config: proxmox_ve_config::sdn::config::RunningConfig =
-            serde_json::from_str(&raw_config)?;
+            serde_json::from_str(&source.running_config()?)?;
         let parsed_config = SdnConfig::try_from(running_config)?;
 
         let vni = parsed_config
@@ -884,13 +1163,276 @@ pub mod pve_rs_sdn_fabrics {
             .tag()
             .ok_or_else(|| format_err!("vnet {vnet} has no tag"))?;
 
-        let command = format!("vtysh -c 'show bgp l2vpn evpn route vni {vni} type 2 json'");
+        // map the VNIs back to vnet names, so the UI does not need to do that itself
+        let vnets: HashMap<u32, String> = parsed_config
+            .zones()
+            .flat_map(ZoneConfig::vnets)
+            .filter_map(|vnet_config| {
+                let name = vnet_config.name().as_ref().to_string();
+                vnet_config.tag().map(|tag| (tag, name))
+            })
+            .collect();
+
         let l2vpn_routes_string =
-            String::from_utf8(Command::new("sh").args(["-c", &command]).output()?.stdout)?;
+            source.vtysh(&format!("show bgp l2vpn evpn route vni {vni} type 2 json"))?;
 
         let routes = serde_json::from_str(&l2vpn_routes_string)
             .with_context(|| "error parsing l2vpn routes")?;
 
-        status::get_l2vpn_routes(routes)
+        let mut page = status::get_l2vpn_routes_page(routes, Some(vni), offset, limit)?;
+        page.routes.set_vnets(&vnets);
+
+        Ok(page)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::sync::Mutex;
+
+    use anyhow::Error;
+    use proxmox_section_config::typed::SectionConfigData;
+    use proxmox_ve_config::sdn::fabric::FabricConfig;
+    use proxmox_ve_config::sdn::fabric::section_config::Section;
+
+    use super::pve_rs_sdn_fabrics::{
+        PerlFabricConfig, StatusSource, fabric_neighbors, fabric_routes, fabrics_status,
+        get_interfaces_etc_network_config,
+    };
+
+    /// A canned running-config together with the vtysh output of a single node.
+    struct CannedNode {
+        running_config: &'static str,
+        vtysh: HashMap<&'static str, &'static str>,
+    }
+
+    impl StatusSource for CannedNode {
+        fn running_config(&self) -> Result<String, Error> {
+            Ok(self.running_config.to_string())
+        }
+
+        fn vtysh(&self, command: &str) -> Result<String, Error> {
+            // unknown commands behave like a stopped FRR
+            Ok(self
+                .vtysh
+                .get(command)
+                .copied()
+                .unwrap_or_default()
+                .to_string())
+        }
+
+        fn hostname(&self) -> &str {
+            "node2"
+        }
+    }
+
+    const RUNNING_CONFIG: &str = r#"{
+      "fabrics": {
+        "ids": {
+          "test": {
+            "area": "0",
+            "type": "ospf_fabric",
+            "id": "test",
+            "ip_prefix": "172.16.6.0/24"
+          },
+          "test_node1": {
+            "ip": "172.16.6.1",
+            "type": "ospf_node",
+            "id": "test_node1",
+            "interfaces": ["name=ens19"]
+          },
+          "test_node2": {
+            "ip": "172.16.6.2",
+            "type": "ospf_node",
+            "id": "test_node2",
+            "interfaces": ["name=ens19", "name=ens20"]
+          }
+        }
+      }
+    }"#;
+
+    const OSPF_ROUTES: &str = r#"{
+      "172.16.6.1/32": [
+        {
+          "prefix": "172.16.6.1/32",
+          "prefixLen": 32,
+          "protocol": "ospf",
+          "vrfId": 0,
+          "vrfName": "default",
+          "selected": true,
+          "destSelected": true,
+          "distance": 110,
+          "metric": 20,
+          "installed": true,
+          "table": 254,
+          "internalStatus": 16,
+          "internalFlags": 8,
+          "internalNextHopNum": 1,
+          "internalNextHopActiveNum": 1,
+          "nexthopGroupId": 74,
+          "installedNexthopGroupId": 74,
+          "uptime": "00:00:32",
+          "nexthops": [
+            {
+              "flags": 11,
+              "fib": true,
+              "ip": "172.16.6.1",
+              "afi": "ipv4",
+              "interfaceIndex": 3,
+              "interfaceName": "ens19",
+              "active": true,
+              "onLink": true,
+              "weight": 1
+            }
+          ]
+        }
+      ],
+      "10.0.0.0/24": [
+        {
+          "prefix": "10.0.0.0/24",
+          "prefixLen": 24,
+          "protocol": "ospf",
+          "vrfId": 0,
+          "vrfName": "default",
+          "selected": true,
+          "destSelected": true,
+          "distance": 110,
+          "metric": 20,
+          "installed": true,
+          "table": 254,
+          "internalStatus": 16,
+          "internalFlags": 8,
+          "internalNextHopNum": 1,
+          "internalNextHopActiveNum": 1,
+          "nexthopGroupId": 75,
+          "installedNexthopGroupId": 75,
+          "uptime": "00:00:32",
+          "nexthops": [
+            {
+              "flags": 11,
+              "fib": true,
+              "ip": "10.0.0.1",
+              "afi": "ipv4",
+              "interfaceIndex": 7,
+              "interfaceName": "vmbr0",
+              "active": true,
+              "weight": 1
+            }
+          ]
+        }
+      ]
+    }"#;
+
+    fn canned_node(vtysh: &[(&'static str, &'static str)]) -> CannedNode {
+        CannedNode {
+            running_config: RUNNING_CONFIG,
+            vtysh: vtysh.iter().copied().collect(),
+        }
+    }
+
+    #[test]
+    fn status_and_routes_from_running_config_and_vtysh() {
+        let node = canned_node(&[("show ip route ospf json", OSPF_ROUTES)]);
+
+        let status = serde_json::to_value(fabrics_status(&node).expect("error getting status"))
+            .expect("error serializing status");
+        assert_eq!(
+            status,
+            serde_json::json!({
+                "test": {
+                    "type": "network",
+                    "status": "ok",
+                    "protocol": "ospf",
+                    "network": "test",
+                    "network-type": "fabric",
+                }
+            })
+        );
+
+        let routes = fabric_routes(&node, "test").expect("error getting routes");
+        assert_eq!(
+            serde_json::to_value(routes).expect("error serializing routes"),
+            serde_json::json!([{ "route": "172.16.6.1/32", "via": ["172.16.6.1"] }])
+        );
+    }
+
+    #[test]
+    fn status_without_frr() {
+        let node = canned_node(&[]);
+
+        let status = serde_json::to_value(fabrics_status(&node).expect("error getting status"))
+            .expect("error serializing status");
+        assert_eq!(status["test"]["status"], "not ok");
+
+        let routes = fabric_routes(&node, "test").expect("error getting routes");
+        assert!(routes.is_empty());
+    }
+
+    #[test]
+    fn unknown_fabric() {
+        let node = canned_node(&[("show ip route ospf json", OSPF_ROUTES)]);
+
+        let err = fabric_routes(&node, "gone").unwrap_err();
+        assert_eq!(err.code, http::StatusCode::NOT_FOUND);
+
+        let err = fabric_neighbors(&node, "gone").unwrap_err();
+        assert_eq!(err.code, http::StatusCode::NOT_FOUND);
+
+        let node = CannedNode {
+            running_config: "{}",
+            vtysh: HashMap::new(),
+        };
+        assert!(
+            fabrics_status(&node)
+                .expect("error getting status")
+                .is_empty()
+        );
+
+        let err = fabric_routes(&node, "test").unwrap_err();
+        assert_eq!(err.code, http::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn interfaces_ipv6_only_ospf_node() {
+        let fabrics: BTreeMap<String, Section> = serde_json::from_str(
+            r#"{
+              "v6": {
+                "area": "0",
+                "type": "ospf_fabric",
+                "id": "v6",
+                "ip6_prefix": "fd00:6::/64"
+              },
+              "v6_node1": {
+                "type": "ospf_node",
+                "id": "v6_node1",
+                "ip6": "fd00:6::1",
+                "interfaces": ["name=ens19"]
+              }
+            }"#,
+        )
+        .expect("error parsing sections");
+
+        let config = FabricConfig::from_section_config(SectionConfigData::from_iter(fabrics))
+            .expect("error converting section config to fabricconfig");
+        let config = PerlFabricConfig {
+            fabric_config: Mutex::new(config.into_inner()),
+        };
+
+        let interfaces = get_interfaces_etc_network_config(&config, "node1")
+            .expect("error generating interfaces config");
+
+        assert_eq!(
+            interfaces,
+            "\nauto dummy_v6\n\
+             iface dummy_v6 inet6 static\n\
+             \taddress fd00:6::1/128\n\
+             \tlink-type dummy\n\
+             \tip-forward 1\n\
+             \nauto ens19\n\
+             iface ens19 inet6 static\n\
+             \taddress fd00:6::1/128\n\
+             \tip-forward 1\n"
+        );
     }
 }
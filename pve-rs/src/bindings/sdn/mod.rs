@@ -10,9 +10,9 @@ pub mod pve_rs_sdn {
     use proxmox_frr::ser::{FrrConfig, serializer::to_raw_config};
 
     use proxmox_ve_config::common::valid::Validatable;
-    use proxmox_ve_config::sdn::fabric::section_config::node::NodeId;
 
     use crate::bindings::pve_rs_sdn_fabrics::PerlFabricConfig;
+    use crate::sdn::{fabric, frr};
 
     /// Return the FRR configuration for the passed FrrConfig and the FabricsConfig as an array of
     /// strings, where each line represents a line in the FRR configuration.
@@ -20,10 +20,29 @@ pub mod pve_rs_sdn {
     pub fn get_frr_raw_config(
         mut frr_config: FrrConfig,
         #[try_from_ref] cfg: &PerlFabricConfig,
-        node_id: NodeId,
+        node_id: &str,
     ) -> Result<Vec<String>, Error> {
+        let node_id = fabric::parse_node_id(node_id)?;
         let fabric_config = cfg.fabric_config.lock().unwrap().clone().into_valid()?;
         proxmox_ve_config::sdn::fabric::frr::build_fabric(node_id, fabric_config, &mut frr_config)?;
         to_raw_config(&frr_config)
     }
+
+    /// Return the difference between the `current` FRR configuration and the one generated by
+    /// [`get_frr_raw_config`] in the unified diff format, with each line representing a line of
+    /// the diff.
+    ///
+    /// Returns an empty array if there are no changes.
+    ///
+    /// See [`frr::config_diff`]
+    #[export]
+    pub fn frr_config_diff(
+        frr_config: FrrConfig,
+        #[try_from_ref] cfg: &PerlFabricConfig,
+        node_id: &str,
+        current: Vec<String>,
+    ) -> Result<Vec<String>, Error> {
+        let desired = get_frr_raw_config(frr_config, cfg, node_id)?;
+        Ok(frr::config_diff(&current, &desired))
+    }
 }
@@ -10,8 +10,8 @@
 
 use std::convert::TryFrom;
 use std::fs::File;
-use std::io::{self, Read};
-use std::os::unix::fs::OpenOptionsExt;
+use std::io::{self, Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::path::{Path, PathBuf};
 
@@ -20,8 +20,12 @@ use nix::errno::Errno;
 use nix::sys::stat::Mode;
 use serde_json::Value as JsonValue;
 
+use proxmox_http::ProxyConfig;
+use proxmox_sys::fs::CreateOptions;
+
+use proxmox_tfa::api::methods::{self, TypedTfaInfo};
 use proxmox_tfa::api::{
-    RecoveryState, TfaChallenge, TfaConfig, TfaResponse, TfaUserData, U2fConfig,
+    RecoveryState, TfaChallenge, TfaConfig, TfaEntry, TfaResponse, TfaUserData, U2fConfig,
     UserChallengeAccess, WebauthnConfig,
 };
 
@@ -47,6 +51,8 @@ pub mod pve_rs_tfa {
     use perlmod::Value;
     use proxmox_tfa::api::{TfaResult, methods};
 
+    use crate::common::tfa;
+
     use super::{TfaConfig, UserAccess};
 
     perlmod::declare_magic!(Box<Tfa> : &Tfa as "PVE::RS::TFA");
@@ -156,6 +162,23 @@ pub mod pve_rs_tfa {
         output
     }
 
+    /// Method: Merge the users of another TFA configuration into this one.
+    ///
+    /// Existing users are skipped, unless `overwrite` is set, in which case they are replaced.
+    /// The U2F and WebAuthn configuration of the imported config is ignored. Returns the ids of
+    /// the imported users. This modifies the config, so it needs to be written out.
+    #[export]
+    pub fn import_users(
+        #[try_from_ref] this: &Tfa,
+        json: &[u8],
+        overwrite: bool,
+    ) -> Result<Vec<String>, Error> {
+        let imported: TfaConfig = serde_json::from_slice(json)
+            .map_err(|err| format_err!("failed to parse imported TFA config: {err}"))?;
+        let mut inner = this.inner.lock().unwrap();
+        Ok(super::import_users(&mut inner, imported, overwrite))
+    }
+
     /// Method: Get a list of all the user names in this config.
     /// PVE uses this to verify users and purge the invalid ones.
     #[export]
@@ -227,6 +250,15 @@ pub mod pve_rs_tfa {
         })
     }
 
+    /// Method: Get the ids of all users with at least one enabled TFA entry of a given type.
+    #[export]
+    pub fn users_with_type(
+        #[try_from_ref] this: &Tfa,
+        typename: &str,
+    ) -> Result<Vec<String>, Error> {
+        super::users_with_type(&this.inner.lock().unwrap(), typename)
+    }
+
     /// Method: Generates a space separated list of yubico keys of this account.
     #[export]
     pub fn get_yubico_keys(
@@ -294,6 +326,41 @@ pub mod pve_rs_tfa {
             .and_then(|user| user.recovery_state())
     }
 
+    /// Method: Get the total, used and remaining number of recovery keys of a user.
+    ///
+    /// Returns `undef` if the user has no recovery keys. This complements [`recovery_state`],
+    /// which only lists the available keys.
+    #[export]
+    pub fn recovery_counts(
+        #[try_from_ref] this: &Tfa,
+        userid: &str,
+    ) -> Option<tfa::RecoveryCounts> {
+        this.inner
+            .lock()
+            .unwrap()
+            .users
+            .get(userid)
+            .and_then(tfa::recovery_counts)
+    }
+
+    /// Method: Replace the recovery keys of a user with a freshly generated set.
+    ///
+    /// All previously issued recovery keys of the user become invalid. The new keys are only
+    /// returned here, so they need to be shown to the user right away. This modifies the config,
+    /// so it needs to be written out.
+    #[export]
+    pub fn regenerate_recovery_keys(
+        #[raw] raw_this: Value,
+        userid: &str,
+    ) -> Result<Vec<String>, Error> {
+        let this: &Tfa = (&raw_this).try_into()?;
+        super::regenerate_recovery_keys(
+            &mut this.inner.lock().unwrap(),
+            &UserAccess::new(&raw_this)?,
+            userid,
+        )
+    }
+
     /// Method: Takes the TFA challenge string (which is a json object) and verifies ther esponse against
     /// it.
     ///
@@ -404,6 +471,27 @@ pub mod pve_rs_tfa {
         Ok(totp.time(std::time::SystemTime::now())?.to_string())
     }
 
+    /// The data needed to enroll a new TOTP entry, see [`generate_totp_uri`].
+    #[derive(Debug, serde::Serialize)]
+    pub struct TotpSetup {
+        /// The base32 encoded secret, for manual entry into an authenticator app.
+        pub secret_base32: String,
+        /// The `otpauth://` URI to pass as `totp` parameter to [`api_add_tfa_entry`].
+        pub otpauth_uri: String,
+        /// The data to encode into a QR code for authenticator apps.
+        pub qr_payload: String,
+    }
+
+    /// Generate a new TOTP secret and its `otpauth://` URI for a user, using the default number
+    /// of digits and period.
+    ///
+    /// This does not modify the config, the entry still needs to be added via
+    /// [`api_add_tfa_entry`].
+    #[export]
+    pub fn generate_totp_uri(userid: &str, issuer: &str) -> Result<TotpSetup, Error> {
+        super::generate_totp_setup(userid, issuer)
+    }
+
     /// Method: API call implementation for `GET /access/tfa/{userid}`
     ///
     /// See [`methods::list_user_tfa`].
@@ -415,6 +503,36 @@ pub mod pve_rs_tfa {
         methods::list_user_tfa(&this.inner.lock().unwrap(), userid)
     }
 
+    /// A TFA entry as listed by [`list_user_tfa_detailed`].
+    #[derive(Debug, PartialEq, serde::Serialize)]
+    pub struct TfaEntryAudit {
+        /// The entry id.
+        pub id: String,
+        /// The TFA type of the entry.
+        #[serde(rename = "type")]
+        pub ty: methods::TfaType,
+        /// The description of the entry.
+        pub description: String,
+        /// The creation time as unix epoch, `undef` if unknown, e.g. for entries converted from
+        /// the version 1 config.
+        pub created: Option<i64>,
+        /// Whether the entry is enabled.
+        pub enabled: bool,
+    }
+
+    /// Method: List the TFA entries of a user with their id, type, description, creation time
+    /// and enabled state.
+    ///
+    /// Contrary to [`api_list_user_tfa`], the entries all have the same shape.
+    #[export]
+    pub fn list_user_tfa_detailed(
+        #[try_from_ref] this: &Tfa,
+        userid: &str,
+    ) -> Result<Vec<TfaEntryAudit>, Error> {
+        let entries = methods::list_user_tfa(&this.inner.lock().unwrap(), userid)?;
+        Ok(super::audit_entries(entries))
+    }
+
     /// Method: API call implementation for `GET /access/tfa/{userid}/{ID}`.
     ///
     /// See [`methods::get_tfa_entry`].
@@ -427,6 +545,19 @@ pub mod pve_rs_tfa {
         methods::get_tfa_entry(&this.inner.lock().unwrap(), userid, id)
     }
 
+    /// Method: Find the ids of a user's TFA entries whose description contains a substring.
+    ///
+    /// The match is case-insensitive and covers entries of all types.
+    #[export]
+    pub fn find_entries_by_description(
+        #[try_from_ref] this: &Tfa,
+        userid: &str,
+        substring: &str,
+    ) -> Result<Vec<String>, Error> {
+        let entries = methods::list_user_tfa(&this.inner.lock().unwrap(), userid)?;
+        Ok(super::entries_by_description(&entries, substring))
+    }
+
     /// Method: API call implementation for `DELETE /access/tfa/{userid}/{ID}`.
     ///
     /// Returns `true` if the user still has other TFA entries left, `false` if the user has *no*
@@ -446,6 +577,21 @@ pub mod pve_rs_tfa {
         }
     }
 
+    /// Method: Move a TFA entry of any type from one user to another, e.g. when merging accounts.
+    ///
+    /// The entry keeps its id and state. Fails if the entry does not exist, or the target user
+    /// already has an entry with the same id.
+    #[export]
+    pub fn move_entry(
+        #[try_from_ref] this: &Tfa,
+        from_userid: &str,
+        to_userid: &str,
+        entry_id: &str,
+    ) -> Result<(), Error> {
+        let mut inner = this.inner.lock().unwrap();
+        super::move_entry(&mut inner, from_userid, to_userid, entry_id)
+    }
+
     /// Method: API method implementation for `GET /access/tfa`.
     ///
     /// See [`methods::list_tfa`].
@@ -557,6 +703,51 @@ pub mod pve_rs_tfa {
         )
     }
 
+    /// The result of [`self_check`].
+    #[derive(Debug, serde::Serialize)]
+    pub struct TfaSelfCheck {
+        /// Whether TFA challenges can be stored.
+        pub challenge_dir_ok: bool,
+        /// The reason why the check failed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub detail: Option<String>,
+    }
+
+    /// Check whether the directory for TFA challenges can be created, and files in it can be
+    /// written and locked.
+    ///
+    /// This uses a throwaway file and does not touch any user's challenge data.
+    #[export]
+    pub fn self_check() -> TfaSelfCheck {
+        let result = super::mkdir("/run/pve-private", 0o700)
+            .and_then(|()| super::check_challenge_dir("/run/pve-private/tfa-challenges"));
+
+        TfaSelfCheck {
+            challenge_dir_ok: result.is_ok(),
+            detail: result.err().map(|err| err.to_string()),
+        }
+    }
+
+    /// Check that the Yubico validation servers are reachable and accept the API credentials.
+    ///
+    /// This sends a signed request with an invalid OTP to each of the `urls`, or the Yubico
+    /// servers if none are given, and expects a correctly signed `BAD_OTP` response. The
+    /// requests go through the HTTP proxy configured for the cluster, if any.
+    #[export]
+    pub fn check_yubico_connectivity(
+        api_id: &str,
+        api_key: &str,
+        urls: Option<Vec<String>>,
+    ) -> Result<(), Error> {
+        let urls = urls.unwrap_or_else(|| vec![super::YUBICO_DEFAULT_URL.to_string()]);
+        let proxy_config = crate::http_proxy_config();
+        for url in urls {
+            super::check_yubico_server(&url, api_id, api_key, proxy_config.clone())
+                .map_err(|err| format_err!("yubico: {url}: {err}"))?;
+        }
+        Ok(())
+    }
+
     /// TFA lockout information.
     #[derive(serde::Serialize)]
     #[serde(rename_all = "kebab-case")]
@@ -969,6 +1160,186 @@ fn generate_legacy_config(out: &mut perlmod::Hash, config: &TfaConfig) {
     out.insert("users", Value::new_ref(&users));
 }
 
+/// Returns the sorted ids of all users with at least one enabled TFA entry of the type `typename`.
+fn users_with_type(config: &TfaConfig, typename: &str) -> Result<Vec<String>, Error> {
+    let has_type: fn(&TfaUserData) -> bool = match typename {
+        "totp" | "oath" => |user| user.totp.iter().any(|entry| entry.info.enable),
+        "u2f" => |user| user.u2f.iter().any(|entry| entry.info.enable),
+        "webauthn" => |user| user.webauthn.iter().any(|entry| entry.info.enable),
+        "yubico" => |user| user.yubico.iter().any(|entry| entry.info.enable),
+        "recovery" => |user| {
+            user.recovery
+                .as_ref()
+                .is_some_and(|recovery| recovery.count_available() > 0)
+        },
+        _ => bail!("unrecognized TFA type {:?}", typename),
+    };
+
+    let mut users: Vec<String> = config
+        .users
+        .iter()
+        .filter(|(_userid, user)| has_type(user))
+        .map(|(userid, _user)| userid.clone())
+        .collect();
+
+    users.sort();
+
+    Ok(users)
+}
+
+/// Returns the ids of all `entries` whose description contains `substring`, ignoring case.
+fn entries_by_description(entries: &[TypedTfaInfo], substring: &str) -> Vec<String> {
+    let substring = substring.to_lowercase();
+
+    entries
+        .iter()
+        .filter(|entry| entry.info.description.to_lowercase().contains(&substring))
+        .map(|entry| entry.info.id.clone())
+        .collect()
+}
+
+/// Convert the listed TFA `entries` to [`pve_rs_tfa::TfaEntryAudit`]s.
+///
+/// A creation time of 0, as used by entries converted from the version 1 config, is reported as
+/// unknown.
+fn audit_entries(entries: Vec<TypedTfaInfo>) -> Vec<pve_rs_tfa::TfaEntryAudit> {
+    entries
+        .into_iter()
+        .map(|entry| pve_rs_tfa::TfaEntryAudit {
+            id: entry.info.id,
+            ty: entry.ty,
+            description: entry.info.description,
+            created: (entry.info.created != 0).then_some(entry.info.created),
+            enabled: entry.info.enable,
+        })
+        .collect()
+}
+
+/// Remove the entry `id` from `entries` and return it.
+fn take_entry<T>(entries: &mut Vec<TfaEntry<T>>, id: &str) -> Option<TfaEntry<T>> {
+    let index = entries.iter().position(|entry| entry.info.id == id)?;
+    Some(entries.remove(index))
+}
+
+/// Move the TFA entry `id` of any type from the user `from` to the user `to`, keeping its id and
+/// state.
+fn move_entry(config: &mut TfaConfig, from: &str, to: &str, id: &str) -> Result<(), Error> {
+    if from == to {
+        bail!("cannot move TFA entry {id:?} to the user it already belongs to");
+    }
+
+    if methods::get_tfa_entry(config, to, id).is_some() {
+        bail!("user {to:?} already has a TFA entry with id {id:?}");
+    }
+
+    let source = config
+        .users
+        .get_mut(from)
+        .ok_or_else(|| format_err!("no such entry"))?;
+
+    let mut moved = TfaUserData::default();
+
+    if let Some(entry) = take_entry(&mut source.totp, id) {
+        moved.totp.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.webauthn, id) {
+        moved.webauthn.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.u2f, id) {
+        moved.u2f.push(entry);
+    } else if let Some(entry) = take_entry(&mut source.yubico, id) {
+        moved.yubico.push(entry);
+    } else if id == "recovery" && source.recovery.is_some() {
+        moved.recovery = source.recovery.take();
+    } else {
+        bail!("no such entry");
+    }
+
+    let target = config.users.entry(to.to_string()).or_default();
+    target.totp.append(&mut moved.totp);
+    target.webauthn.append(&mut moved.webauthn);
+    target.u2f.append(&mut moved.u2f);
+    target.yubico.append(&mut moved.yubico);
+    if moved.recovery.is_some() {
+        target.recovery = moved.recovery;
+    }
+
+    Ok(())
+}
+
+/// Replace the recovery keys of `userid` with a freshly generated set and return them.
+///
+/// The previous keys are invalidated. They are kept if generating the new ones fails.
+fn regenerate_recovery_keys(
+    config: &mut TfaConfig,
+    access: &UserAccess,
+    userid: &str,
+) -> Result<Vec<String>, Error> {
+    let previous = config
+        .users
+        .get_mut(userid)
+        .and_then(|user| user.recovery.take());
+
+    let result = methods::add_tfa_entry(
+        config,
+        access,
+        userid,
+        None,
+        None,
+        None,
+        None,
+        methods::TfaType::Recovery,
+        None,
+    );
+
+    match result {
+        Ok(info) => Ok(info.recovery),
+        Err(err) => {
+            if let Some(user) = config.users.get_mut(userid) {
+                user.recovery = previous;
+            }
+            Err(err)
+        }
+    }
+}
+
+/// Generate a TOTP entry with a random secret for `userid`, using the default digits and period.
+fn generate_totp_setup(userid: &str, issuer: &str) -> Result<pve_rs_tfa::TotpSetup, Error> {
+    let mut secret = [0u8; 20];
+    openssl::rand::rand_bytes(&mut secret)?;
+
+    let totp = proxmox_tfa::totp::Totp::builder()
+        .secret(secret.to_vec())
+        .account_name(userid.to_owned())
+        .issuer(issuer.to_owned())
+        .build();
+    let otpauth_uri = totp.to_uri()?;
+
+    Ok(pve_rs_tfa::TotpSetup {
+        secret_base32: base32::encode(base32::Alphabet::RFC4648 { padding: false }, &secret),
+        qr_payload: otpauth_uri.clone(),
+        otpauth_uri,
+    })
+}
+
+/// Merge the users of `imported` into `config`, replacing existing users only if `overwrite` is
+/// set.
+///
+/// Returns the sorted ids of the imported users.
+fn import_users(config: &mut TfaConfig, imported: TfaConfig, overwrite: bool) -> Vec<String> {
+    let mut userids = Vec::new();
+
+    for (userid, data) in imported.users {
+        if !overwrite && config.users.contains_key(&userid) {
+            continue;
+        }
+        config.users.insert(userid.clone(), data);
+        userids.push(userid);
+    }
+
+    userids.sort();
+
+    userids
+}
+
 /// Attach the path to errors from [`nix::mkir()`].
 fn mkdir<P: AsRef<Path>>(path: P, mode: libc::mode_t) -> Result<(), Error> {
     let path = path.as_ref();
@@ -979,6 +1350,126 @@ fn mkdir<P: AsRef<Path>>(path: P, mode: libc::mode_t) -> Result<(), Error> {
     }
 }
 
+/// Create the challenge directory `dir` if needed and check that files in it can be created,
+/// locked, written and removed.
+fn check_challenge_dir<P: AsRef<Path>>(dir: P) -> Result<(), Error> {
+    let dir = dir.as_ref();
+    mkdir(dir, 0o700)?;
+
+    // user ids always contain an '@', so this cannot clash with a user's challenge file
+    let path = dir.join(format!(".self-check-{}", std::process::id()));
+
+    let result = UserChallengeData::open_locked(&path, true).and_then(|file| {
+        let mut file = file.ok_or_else(|| format_err!("failed to create {path:?}"))?;
+        file.write_all(b"{}")
+            .and_then(|()| file.sync_all())
+            .map_err(|err| format_err!("failed to write {path:?}: {err}"))
+    });
+
+    let removed = std::fs::remove_file(&path);
+    result?;
+    removed.map_err(|err| format_err!("failed to remove {path:?}: {err}"))
+}
+
+/// The validation server used if none is configured.
+const YUBICO_DEFAULT_URL: &str = "https://api.yubico.com/wsapi/2.0/verify";
+
+/// Compute the signature of Yubico validation request or response parameters with the decoded
+/// API key.
+fn yubico_signature(params: &[(String, String)], key: &[u8]) -> Result<String, Error> {
+    let mut params: Vec<String> = params
+        .iter()
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    params.sort();
+
+    let key = openssl::pkey::PKey::hmac(key)?;
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha1(), &key)?;
+    signer.update(params.join("&").as_bytes())?;
+
+    Ok(proxmox_base64::encode(signer.sign_to_vec()?))
+}
+
+/// Parse a Yubico validation response, returning its status and whether it was signed.
+///
+/// The servers do not sign every response, e.g. not if the API id is unknown, so the signature
+/// is only verified if there is one.
+fn parse_yubico_response(body: &str, key: &[u8]) -> Result<(String, bool), Error> {
+    let mut params: Vec<(String, String)> = body
+        .lines()
+        .filter_map(|line| line.trim_end().split_once('='))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+
+    let status = params
+        .iter()
+        .find_map(|(name, value)| (name == "status").then(|| value.clone()))
+        .ok_or_else(|| format_err!("response has no status"))?;
+
+    let Some(index) = params.iter().position(|(name, _)| name == "h") else {
+        return Ok((status, false));
+    };
+    let signature = params.remove(index).1;
+    if yubico_signature(&params, key)? != signature {
+        bail!("response signature verification failed");
+    }
+
+    Ok((status, true))
+}
+
+/// Send a signed validation request with an invalid OTP to the Yubico validation server `url`,
+/// via the HTTP proxy in `proxy_config` if one is set.
+///
+/// A signed `BAD_OTP` status means that the server is reachable and accepted the credentials.
+fn check_yubico_server(
+    url: &str,
+    api_id: &str,
+    api_key: &str,
+    proxy_config: Option<ProxyConfig>,
+) -> Result<(), Error> {
+    use proxmox_http::HttpClient;
+
+    let key = proxmox_base64::decode(api_key).map_err(|_| format_err!("invalid API key"))?;
+
+    let mut nonce = [0u8; 16];
+    openssl::rand::rand_bytes(&mut nonce)?;
+
+    let mut params = vec![
+        ("id".to_string(), api_id.to_string()),
+        ("nonce".to_string(), hex::encode(nonce)),
+        ("otp".to_string(), "invalid".to_string()),
+        ("timestamp".to_string(), "1".to_string()),
+    ];
+    params.push(("h".to_string(), yubico_signature(&params, &key)?));
+
+    let url = url::Url::parse_with_params(url, &params)?;
+    let request = http::Request::builder()
+        .method("GET")
+        .uri(url.as_str())
+        .body(String::new())?;
+
+    let client = proxmox_http::client::sync::Client::new(proxmox_http::HttpOptions {
+        proxy_config,
+        ..Default::default()
+    });
+    let response = client
+        .request(request)
+        .map_err(|err| format_err!("request failed: {err}"))?;
+    if !response.status().is_success() {
+        bail!("request failed: {}", response.status());
+    }
+
+    match parse_yubico_response(response.body(), &key)? {
+        (status, true) if status == "BAD_OTP" => Ok(()),
+        (status, false) if status == "BAD_OTP" => {
+            bail!("the response to a valid API key must be signed")
+        }
+        (status, _) if status == "BAD_SIGNATURE" => bail!("the API key was rejected"),
+        (status, _) if status == "NO_SUCH_CLIENT" => bail!("the API id was rejected"),
+        (status, _) => bail!("unexpected status {status}"),
+    }
+}
+
 #[cfg(debug_assertions)]
 #[derive(Clone)]
 #[repr(transparent)]
@@ -1042,16 +1533,8 @@ impl proxmox_tfa::api::OpenUserChallengeData for UserAccess {
 
         let path = challenge_data_path(userid, self.is_debug());
 
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .truncate(false)
-            .mode(0o600)
-            .open(&path)
-            .map_err(|err| format_err!("failed to create challenge file {:?}: {}", &path, err))?;
-
-        UserChallengeData::lock_file(file.as_raw_fd())?;
+        let mut file = UserChallengeData::open_locked(&path, true)?
+            .ok_or_else(|| format_err!("failed to create challenge file {:?}", &path))?;
 
         // the file may be empty, so read to a temporary buffer first:
         let mut data = Vec::with_capacity(4096);
@@ -1078,7 +1561,7 @@ impl proxmox_tfa::api::OpenUserChallengeData for UserAccess {
         Ok(Box::new(UserChallengeData {
             inner,
             path,
-            lock: file,
+            _lock: file,
         }))
     }
 
@@ -1086,20 +1569,10 @@ impl proxmox_tfa::api::OpenUserChallengeData for UserAccess {
     fn open_no_create(&self, userid: &str) -> Result<Option<Box<dyn UserChallengeAccess>>, Error> {
         let path = challenge_data_path(userid, self.is_debug());
 
-        let mut file = match std::fs::OpenOptions::new()
-            .read(true)
-            .write(true)
-            .truncate(false)
-            .mode(0o600)
-            .open(&path)
-        {
-            Ok(file) => file,
-            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
-            Err(err) => return Err(err.into()),
+        let Some(mut file) = UserChallengeData::open_locked(&path, false)? else {
+            return Ok(None);
         };
 
-        UserChallengeData::lock_file(file.as_raw_fd())?;
-
         let inner = serde_json::from_reader(&mut file).map_err(|err| {
             format_err!("failed to read challenge data for user {}: {}", userid, err)
         })?;
@@ -1107,7 +1580,7 @@ impl proxmox_tfa::api::OpenUserChallengeData for UserAccess {
         Ok(Some(Box::new(UserChallengeData {
             inner,
             path,
-            lock: file,
+            _lock: file,
         })))
     }
 
@@ -1132,7 +1605,8 @@ impl proxmox_tfa::api::OpenUserChallengeData for UserAccess {
 struct UserChallengeData {
     inner: proxmox_tfa::api::TfaUserChallenges,
     path: PathBuf,
-    lock: File,
+    /// Holds the lock on the challenge file until we are done.
+    _lock: File,
 }
 
 impl proxmox_tfa::api::UserChallengeAccess for UserChallengeData {
@@ -1157,41 +1631,457 @@ impl UserChallengeData {
         Ok(())
     }
 
-    /// Rewind & truncate the file for an update.
-    fn rewind(&mut self) -> Result<(), Error> {
-        use std::io::{Seek, SeekFrom};
-
-        let pos = self.lock.seek(SeekFrom::Start(0))?;
-        if pos != 0 {
-            bail!(
-                "unexpected result trying to rewind file, position is {}",
-                pos
-            );
-        }
-
-        let rc = unsafe { libc::ftruncate(self.lock.as_raw_fd(), 0) };
-        if rc != 0 {
-            let err = io::Error::last_os_error();
-            bail!("failed to truncate challenge data: {}", err);
+    /// Open and lock the challenge file at `path`, creating it if `create` is set.
+    ///
+    /// Returns `None` if the file does not exist and `create` is not set. Since [`save`] replaces
+    /// the file, the file we got the lock for may have been replaced or removed while we were
+    /// waiting, in which case it is opened again.
+    ///
+    /// [`save`]: UserChallengeData::save()
+    fn open_locked(path: &Path, create: bool) -> Result<Option<File>, Error> {
+        loop {
+            let file = match std::fs::OpenOptions::new()
+                .create(create)
+                .read(true)
+                .write(true)
+                .truncate(false)
+                .mode(0o600)
+                .open(path)
+            {
+                Ok(file) => file,
+                Err(err) if !create && err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => bail!("failed to open challenge file {:?}: {}", path, err),
+            };
+
+            Self::lock_file(file.as_raw_fd())?;
+
+            let locked = file.metadata()?;
+            match std::fs::metadata(path) {
+                Ok(current) if current.dev() == locked.dev() && current.ino() == locked.ino() => {
+                    return Ok(Some(file));
+                }
+                Ok(_) => continue,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            }
         }
-
-        Ok(())
     }
 
-    /// Save the current data. Note that we do not replace the file here since we lock the file
-    /// itself, as it is in `/run`, and the typical error case for this particular situation
-    /// (machine loses power) simply prevents some login, but that'll probably fail anyway for
-    /// other reasons then...
+    /// Save the current data.
+    ///
+    /// The data is written to a temporary file which is synced to disk and then renamed over the
+    /// challenge file while we still hold the lock, so readers never see partially written data,
+    /// not even after a power loss.
     ///
     /// This currently consumes selfe as we never perform more than 1 insertion/removal, and this
     /// way also unlocks early.
     fn save(&mut self) -> Result<(), Error> {
-        self.rewind()?;
+        let data = serde_json::to_vec(&self.inner).map_err(|err| {
+            format_err!("failed to update challenge file {:?}: {}", self.path, err)
+        })?;
 
-        serde_json::to_writer(&mut &self.lock, &self.inner).map_err(|err| {
+        let options = CreateOptions::new().perm(Mode::from_bits_truncate(0o600));
+        proxmox_sys::fs::replace_file(&self.path, &data, options, true).map_err(|err| {
             format_err!("failed to update challenge file {:?}: {}", self.path, err)
         })?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proxmox_tfa::api::{TfaInfo, TfaResult, TfaType};
+
+    use crate::common::tfa::{RecoveryCounts, recovery_counts};
+
+    use super::*;
+
+    fn info(id: &str, enable: bool) -> TfaInfo {
+        TfaInfo {
+            id: id.to_string(),
+            description: id.to_string(),
+            created: 0,
+            enable,
+        }
+    }
+
+    #[test]
+    fn users_with_factor_type() {
+        let mut config = TfaConfig::default();
+
+        let mut user = TfaUserData::default();
+        user.totp.push(TfaEntry::from_parts(
+            info("totp", true),
+            "otpauth://totp/root@pam?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+                .parse()
+                .unwrap(),
+        ));
+        user.yubico.push(TfaEntry::from_parts(
+            info("yubico", false),
+            "cccccc".to_string(),
+        ));
+        config.users.insert("root@pam".to_string(), user);
+
+        let mut user = TfaUserData::default();
+        user.yubico.push(TfaEntry::from_parts(
+            info("yubico", true),
+            "cccccc".to_string(),
+        ));
+        config.users.insert("admin@pve".to_string(), user);
+
+        config
+            .users
+            .insert("nobody@pve".to_string(), TfaUserData::default());
+
+        assert_eq!(users_with_type(&config, "totp").unwrap(), ["root@pam"]);
+        assert_eq!(users_with_type(&config, "oath").unwrap(), ["root@pam"]);
+        assert_eq!(users_with_type(&config, "yubico").unwrap(), ["admin@pve"]);
+        assert!(users_with_type(&config, "webauthn").unwrap().is_empty());
+        assert!(users_with_type(&config, "recovery").unwrap().is_empty());
+        assert!(users_with_type(&config, "sms").is_err());
+    }
+
+    #[test]
+    fn find_by_description() {
+        let entry = |ty, id: &str, description: &str| TypedTfaInfo {
+            ty,
+            info: TfaInfo {
+                id: id.to_string(),
+                description: description.to_string(),
+                created: 0,
+                enable: true,
+            },
+        };
+
+        let entries = [
+            entry(TfaType::Totp, "totp-1", "Phone Authenticator"),
+            entry(TfaType::Webauthn, "webauthn-1", "YubiKey 5 (office)"),
+            entry(TfaType::Webauthn, "webauthn-2", "Laptop fingerprint"),
+            entry(TfaType::Totp, "totp-2", "Backup PHONE"),
+        ];
+
+        assert_eq!(
+            entries_by_description(&entries, "phone"),
+            ["totp-1", "totp-2"]
+        );
+        assert_eq!(entries_by_description(&entries, "OFFICE"), ["webauthn-1"]);
+        assert_eq!(
+            entries_by_description(&entries, "o"),
+            ["totp-1", "webauthn-1", "webauthn-2", "totp-2"]
+        );
+        assert!(entries_by_description(&entries, "tablet").is_empty());
+    }
+
+    #[test]
+    fn audit_listing() {
+        let mut user = TfaUserData::default();
+        user.yubico.push(TfaEntry::from_parts(
+            TfaInfo {
+                id: "yubico-1".to_string(),
+                description: "Office key".to_string(),
+                created: 1700000000,
+                enable: false,
+            },
+            "cccccc".to_string(),
+        ));
+        let mut config = parse_old_config(
+            b"root@pam:yubico:eyJrZXlzIjoiY2NjY2NjIn0=\n", // {"keys":"cccccc"}
+        )
+        .unwrap();
+        config.users.insert("admin@pve".to_string(), user);
+
+        let entries = methods::list_user_tfa(&config, "root@pam").unwrap();
+        assert_eq!(
+            audit_entries(entries),
+            [pve_rs_tfa::TfaEntryAudit {
+                id: "v1-entry".to_string(),
+                ty: TfaType::Yubico,
+                description: "<old version 1 entry>".to_string(),
+                created: None,
+                enabled: true,
+            }]
+        );
+
+        let entries = methods::list_user_tfa(&config, "admin@pve").unwrap();
+        assert_eq!(
+            audit_entries(entries),
+            [pve_rs_tfa::TfaEntryAudit {
+                id: "yubico-1".to_string(),
+                ty: TfaType::Yubico,
+                description: "Office key".to_string(),
+                created: Some(1700000000),
+                enabled: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn move_webauthn_entry() {
+        let mut config = TfaConfig::default();
+
+        let user: TfaUserData = serde_json::from_value(serde_json::json!({
+            "webauthn": [{
+                "id": "webauthn-1",
+                "description": "YubiKey",
+                "created": 1700000000,
+                "enable": false,
+                "cred_id": "AAECAwQFBgcICQoLDA0ODw",
+                "cred": {
+                    "type_": "ES256",
+                    "key": {
+                        "EC_EC2": {
+                            "curve": "SECP256R1",
+                            "x": [1; 32],
+                            "y": [2; 32],
+                        },
+                    },
+                },
+                "counter": 42,
+                "user_verified": false,
+                "registration_policy": "preferred",
+            }],
+        }))
+        .expect("error parsing webauthn entry");
+        config.users.insert("old@pve".to_string(), user);
+
+        let mut user = TfaUserData::default();
+        user.yubico.push(TfaEntry::from_parts(
+            info("webauthn-1", true),
+            "cccccc".to_string(),
+        ));
+        config.users.insert("taken@pve".to_string(), user);
+
+        assert!(move_entry(&mut config, "old@pve", "taken@pve", "webauthn-1").is_err());
+        assert!(move_entry(&mut config, "old@pve", "new@pve", "missing").is_err());
+        assert!(move_entry(&mut config, "nobody@pve", "new@pve", "webauthn-1").is_err());
+
+        move_entry(&mut config, "old@pve", "new@pve", "webauthn-1").unwrap();
+
+        assert!(config.users["old@pve"].webauthn.is_empty());
+        let moved = &config.users["new@pve"].webauthn;
+        assert_eq!(moved.len(), 1);
+        assert_eq!(moved[0].info.id, "webauthn-1");
+        assert_eq!(moved[0].info.description, "YubiKey");
+        assert_eq!(moved[0].info.created, 1700000000);
+        assert!(!moved[0].info.enable);
+
+        assert!(move_entry(&mut config, "old@pve", "new@pve", "webauthn-1").is_err());
+    }
+
+    #[test]
+    fn totp_setup() {
+        let setup = generate_totp_setup("root@pam", "Proxmox VE").unwrap();
+
+        let totp: proxmox_tfa::totp::Totp = setup.otpauth_uri.parse().unwrap();
+        assert_eq!(
+            base32::decode(
+                base32::Alphabet::RFC4648 { padding: false },
+                &setup.secret_base32
+            )
+            .unwrap(),
+            totp.secret()
+        );
+        assert_eq!(totp.secret().len(), 20);
+        assert_eq!(totp.digits(), 6);
+        assert_eq!(totp.period().as_secs(), 30);
+        assert_eq!(setup.qr_payload, setup.otpauth_uri);
+
+        let other = generate_totp_setup("root@pam", "Proxmox VE").unwrap();
+        assert_ne!(setup.secret_base32, other.secret_base32);
+    }
+
+    #[test]
+    fn import_users_merge() {
+        let yubico = |key: &str| {
+            let mut user = TfaUserData::default();
+            user.yubico
+                .push(TfaEntry::from_parts(info("yubico", true), key.to_string()));
+            user
+        };
+
+        let mut config = TfaConfig::default();
+        config.users.insert("root@pam".to_string(), yubico("old"));
+
+        let mut imported = TfaConfig::default();
+        imported.users.insert("root@pam".to_string(), yubico("new"));
+        imported
+            .users
+            .insert("admin@pve".to_string(), yubico("new"));
+        let imported = serde_json::to_vec(&imported).unwrap();
+
+        let mut merged = config.clone();
+        let imported_users = import_users(
+            &mut merged,
+            serde_json::from_slice(&imported).unwrap(),
+            false,
+        );
+        assert_eq!(imported_users, ["admin@pve"]);
+        assert_eq!(merged.users["root@pam"].yubico[0].entry, "old");
+        assert_eq!(merged.users["admin@pve"].yubico[0].entry, "new");
+
+        let mut merged = config.clone();
+        let imported_users = import_users(
+            &mut merged,
+            serde_json::from_slice(&imported).unwrap(),
+            true,
+        );
+        assert_eq!(imported_users, ["admin@pve", "root@pam"]);
+        assert_eq!(merged.users["root@pam"].yubico[0].entry, "new");
+        assert_eq!(merged.users["admin@pve"].yubico[0].entry, "new");
+    }
+
+    #[test]
+    fn challenge_file_replacement() {
+        let path =
+            std::env::temp_dir().join(format!("pve-rs-tfa-challenge-{}", std::process::id()));
+
+        let save = |path: &PathBuf| {
+            let lock = UserChallengeData::open_locked(path, true).unwrap().unwrap();
+            let mut data = UserChallengeData {
+                inner: Default::default(),
+                path: path.clone(),
+                _lock: lock,
+            };
+            data.save().unwrap();
+        };
+        save(&path);
+
+        let writer = std::thread::spawn({
+            let path = path.clone();
+            move || {
+                for _ in 0..500 {
+                    save(&path);
+                }
+            }
+        });
+
+        // readers which do not take the lock must still always see complete data
+        while !writer.is_finished() {
+            let data = std::fs::read(&path).unwrap();
+            serde_json::from_slice::<JsonValue>(&data).expect("truncated challenge data");
+        }
+        writer.join().unwrap();
+
+        let file = UserChallengeData::open_locked(&path, false).unwrap();
+        assert!(file.is_some());
+        std::fs::remove_file(&path).unwrap();
+        assert!(
+            UserChallengeData::open_locked(&path, false)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn challenge_dir_check() {
+        let dir = std::env::temp_dir().join(format!("pve-rs-tfa-check-{}", std::process::id()));
+
+        check_challenge_dir(&dir).unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir(&dir).unwrap();
+
+        let err = check_challenge_dir(dir.join("missing-parent")).unwrap_err();
+        assert!(err.to_string().starts_with("failed to create directory"));
+    }
+
+    #[test]
+    fn yubico_signatures() {
+        let key = b"0123456789abcdefghij";
+        let param = |name: &str, value: &str| (name.to_string(), value.to_string());
+
+        let params = [
+            param("otp", "cc"),
+            param("timestamp", "1"),
+            param("id", "1"),
+            param("nonce", "abc"),
+        ];
+        assert_eq!(
+            yubico_signature(&params, key).unwrap(),
+            "de9wzCd1MPMIZ0RYhWthPNny2aU="
+        );
+
+        let response = "h=cvTLr463L7YfKrH4hbCTLQxiaFg=\r\n\
+            t=2024-01-01T00:00:00Z0000\r\n\
+            nonce=abcdef0123456789abcdef\r\n\
+            status=BAD_OTP\r\n\r\n";
+        assert_eq!(
+            parse_yubico_response(response, key).unwrap(),
+            ("BAD_OTP".to_string(), true)
+        );
+
+        let err = parse_yubico_response(response, b"wrong key").unwrap_err();
+        assert_eq!(err.to_string(), "response signature verification failed");
+
+        // unknown clients get an unsigned response
+        assert_eq!(
+            parse_yubico_response("status=NO_SUCH_CLIENT\r\n", key).unwrap(),
+            ("NO_SUCH_CLIENT".to_string(), false)
+        );
+
+        let err = parse_yubico_response("h=abc\r\n", key).unwrap_err();
+        assert_eq!(err.to_string(), "response has no status");
+    }
+
+    /// Challenge data access for tests which only use factors without stored challenges.
+    struct NoChallengeData;
+
+    impl proxmox_tfa::api::OpenUserChallengeData for NoChallengeData {
+        fn open(&self, _userid: &str) -> Result<Box<dyn UserChallengeAccess>, Error> {
+            bail!("no challenge data in tests");
+        }
+
+        fn open_no_create(
+            &self,
+            _userid: &str,
+        ) -> Result<Option<Box<dyn UserChallengeAccess>>, Error> {
+            Ok(None)
+        }
+
+        fn remove(&self, _userid: &str) -> Result<bool, Error> {
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn recovery_key_counts() {
+        let mut config = TfaConfig::default();
+        let keys = config.add_recovery("root@pam").unwrap();
+        let total = keys.len();
+
+        assert_eq!(
+            recovery_counts(&config.users["root@pam"]),
+            Some(RecoveryCounts {
+                total,
+                used: 0,
+                remaining: total,
+            })
+        );
+
+        let challenge = config
+            .authentication_challenge(&NoChallengeData, "root@pam", None)
+            .unwrap()
+            .expect("recovery keys require a challenge");
+        let result = config.verify(
+            &NoChallengeData,
+            "root@pam",
+            &challenge,
+            TfaResponse::Recovery(keys[3].clone()),
+            None,
+        );
+        assert!(matches!(result, TfaResult::Success { .. }));
+
+        let user = &config.users["root@pam"];
+        assert_eq!(
+            recovery_counts(user),
+            Some(RecoveryCounts {
+                total,
+                used: 1,
+                remaining: total - 1,
+            })
+        );
+        assert!(user.recovery_state().is_some());
+
+        assert_eq!(recovery_counts(&TfaUserData::default()), None);
+    }
+}
@@ -0,0 +1,810 @@
+//! Structured access to the rules of PVE firewall configuration files.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use anyhow::{Error, bail, format_err};
+use serde::{Deserialize, Serialize};
+
+use proxmox_ve_config::firewall::fw_macros::fw_macros;
+use proxmox_ve_config::firewall::types::alias::Alias;
+use proxmox_ve_config::firewall::types::ipset::{IpsetAddress, IpsetEntry};
+use proxmox_ve_config::firewall::types::rule::{Kind, Rule as FirewallRule};
+use proxmox_ve_config::firewall::types::rule_match::IpAddrMatch;
+
+/// A single firewall rule, as found in the `[RULES]` section of a firewall configuration file.
+///
+/// Options which are not modeled explicitly, e.g. `-i` or `-log`, are kept verbatim in
+/// `options`, so parsing and formatting a rule does not lose any information.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Rule {
+    /// Whether the rule is enabled, disabled rules are prefixed with `|`.
+    pub enabled: bool,
+    /// The rule type, one of `IN`, `OUT`, `FORWARD` or `GROUP`.
+    pub direction: String,
+    /// The action, e.g. `ACCEPT`, a macro like `SSH(ACCEPT)` or the name of a security group.
+    pub action: String,
+    /// The protocol (`-p`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proto: Option<String>,
+    /// The source address, ipset or alias (`-source`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The destination address, ipset or alias (`-dest`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>,
+    /// The destination ports (`-dport`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dport: Option<String>,
+    /// The source ports (`-sport`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sport: Option<String>,
+    /// All other options as `[name, value]` pairs without the leading `-`, in their original
+    /// order.
+    #[serde(default)]
+    pub options: Vec<(String, String)>,
+    /// The comment of the rule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Parse a single rule line.
+///
+/// The line is checked with the rule parser of the firewall, so only rules the firewall accepts
+/// are returned. The fields are taken verbatim from the line.
+pub fn parse_rule(line: &str) -> Result<Rule, Error> {
+    parse_firewall_rule(line).map(|(rule, _)| rule)
+}
+
+/// Parse a single rule line, returning the rule along with the one parsed by the firewall.
+fn parse_firewall_rule(line: &str) -> Result<(Rule, FirewallRule), Error> {
+    let parsed: FirewallRule = line.trim().parse()?;
+
+    let (line, comment) = match line.split_once('#') {
+        Some((line, comment)) => (line, Some(comment.trim())),
+        None => (line, None),
+    };
+
+    let mut line = line.trim();
+    let enabled = match line.strip_prefix('|') {
+        Some(rest) => {
+            line = rest.trim_start();
+            false
+        }
+        None => true,
+    };
+
+    let mut tokens = line.split_whitespace();
+
+    let direction = tokens
+        .next()
+        .ok_or_else(|| format_err!("missing rule type"))?
+        .to_ascii_uppercase();
+
+    let action = tokens
+        .next()
+        .ok_or_else(|| format_err!("missing action"))?
+        .to_string();
+
+    let mut rule = Rule {
+        enabled,
+        direction,
+        action,
+        comment: comment.filter(|c| !c.is_empty()).map(str::to_string),
+        ..Default::default()
+    };
+
+    while let Some(token) = tokens.next() {
+        let Some(name) = token.strip_prefix('-') else {
+            bail!("expected an option, got '{token}'");
+        };
+
+        let value = tokens
+            .next()
+            .ok_or_else(|| format_err!("missing value for option '{token}'"))?
+            .to_string();
+
+        let field = match name {
+            "p" => &mut rule.proto,
+            "source" => &mut rule.source,
+            "dest" => &mut rule.dest,
+            "dport" => &mut rule.dport,
+            "sport" => &mut rule.sport,
+            _ => {
+                rule.options.push((name.to_string(), value));
+                continue;
+            }
+        };
+
+        if field.replace(value).is_some() {
+            bail!("duplicate option '{token}'");
+        }
+    }
+
+    Ok((rule, parsed))
+}
+
+/// Parse the rules in `text`, one per line, skipping empty lines and comment lines.
+///
+/// Errors contain the (1-based) line number of the offending line.
+pub fn parse_rules(text: &str) -> Result<Vec<Rule>, Error> {
+    rule_lines(text)
+        .map(|(number, line)| parse_rule(line).map_err(|err| format_err!("line {number}: {err}")))
+        .collect()
+}
+
+/// Returns all lines of `text` containing a rule, along with their (1-based) line number.
+pub fn rule_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+        .filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('#')
+        })
+}
+
+/// Format a rule in the canonical format of firewall configuration files.
+pub fn format_rule(rule: &Rule) -> String {
+    let mut line = String::new();
+
+    if !rule.enabled {
+        line.push('|');
+    }
+
+    // writing into a string cannot fail
+    let _ = write!(line, "{} {}", rule.direction, rule.action);
+
+    for (name, value) in [
+        ("source", &rule.source),
+        ("dest", &rule.dest),
+        ("p", &rule.proto),
+        ("dport", &rule.dport),
+        ("sport", &rule.sport),
+    ] {
+        if let Some(value) = value {
+            let _ = write!(line, " -{name} {value}");
+        }
+    }
+
+    for (name, value) in &rule.options {
+        let _ = write!(line, " -{name} {value}");
+    }
+
+    if let Some(comment) = &rule.comment {
+        let _ = write!(line, " # {comment}");
+    }
+
+    line
+}
+
+/// Format rules in the canonical format of firewall configuration files, one per line.
+pub fn format_rules(rules: &[Rule]) -> String {
+    rules.iter().map(|rule| format_rule(rule) + "\n").collect()
+}
+
+/// The severity of a [`RuleDiagnostic`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// The line is valid, but likely not what was intended.
+    Warning,
+    /// The line is invalid and would be rejected or ignored by the firewall.
+    Error,
+}
+
+/// A problem found on a single line of a firewall configuration file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleDiagnostic {
+    /// The (1-based) line number.
+    pub line: usize,
+    /// How severe the problem is.
+    pub severity: Severity,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+/// A line of a firewall configuration file, classified by the section it appears in.
+enum Entry<'a> {
+    /// A section header, without the brackets.
+    Section(&'a str),
+    /// A rule in the `[RULES]` section or a security group.
+    Rule(&'a str),
+    /// An alias definition in the `[ALIASES]` section.
+    Alias(&'a str),
+    /// An entry of an IPSet.
+    Ipset(&'a str),
+}
+
+/// Split a firewall configuration file into its entries, along with their (1-based) line number.
+///
+/// Lines before the first section header are treated as rules, so plain lists of rules can be
+/// handled as well. Lines of sections which do not contain rules, aliases or IPSets are skipped.
+fn entries<'a>(text: &'a str) -> Vec<(usize, Entry<'a>)> {
+    let mut entries = Vec::new();
+    let mut section: Option<fn(&'a str) -> Entry<'a>> = Some(Entry::Rule);
+
+    for (number, line) in rule_lines(text) {
+        let line = line.trim();
+
+        let Some(header) = line.strip_prefix('[') else {
+            if let Some(entry) = section {
+                entries.push((number, entry(line)));
+            }
+            continue;
+        };
+
+        let header = header
+            .split_once(']')
+            .map_or(header, |(header, _)| header)
+            .trim();
+        let kind = header.split_whitespace().next().unwrap_or_default();
+
+        section = match kind.to_ascii_lowercase().as_str() {
+            "rules" | "group" => Some(Entry::Rule),
+            "aliases" => Some(Entry::Alias),
+            "ipset" => Some(Entry::Ipset),
+            _ => None,
+        };
+
+        entries.push((number, Entry::Section(header)));
+    }
+
+    entries
+}
+
+/// Returns the name of the IPSet defined by a section `header`, if any.
+fn ipset_name(header: &str) -> Option<&str> {
+    let (kind, name) = header.split_once(char::is_whitespace)?;
+    kind.eq_ignore_ascii_case("ipset").then(|| name.trim())
+}
+
+/// Returns the name of the alias defined by an alias `line`.
+fn alias_name(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or_default()
+}
+
+/// Strip the `dc/` or `guest/` scope from the name of an alias or IPSet.
+fn strip_scope(name: &str) -> &str {
+    name.strip_prefix("dc/")
+        .or_else(|| name.strip_prefix("guest/"))
+        .unwrap_or(name)
+}
+
+/// The kind of object referenced by an address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RefKind {
+    /// An alias, referenced by its name.
+    Alias,
+    /// An IPSet, referenced by its name prefixed with `+`.
+    Ipset,
+}
+
+/// Returns the aliases and IPSets referenced by the source and destination of a parsed rule,
+/// with their names lowercased and without scope.
+fn rule_references(rule: &FirewallRule) -> Vec<(RefKind, String)> {
+    let Kind::Match(rule_match) = rule.kind() else {
+        return Vec::new();
+    };
+    let Some(ip) = rule_match.ip() else {
+        return Vec::new();
+    };
+
+    [ip.src(), ip.dst()]
+        .into_iter()
+        .flatten()
+        .filter_map(|address| match address {
+            IpAddrMatch::Alias(name) => Some((RefKind::Alias, name.name().to_ascii_lowercase())),
+            IpAddrMatch::Set(name) => Some((RefKind::Ipset, name.name().to_ascii_lowercase())),
+            IpAddrMatch::Ip(_) => None,
+        })
+        .collect()
+}
+
+/// Returns the alias referenced by an IPSet entry, if any, with its name lowercased and without
+/// scope.
+fn ipset_entry_references(entry: &IpsetEntry) -> Vec<(RefKind, String)> {
+    match &entry.address {
+        IpsetAddress::Alias(name) => vec![(RefKind::Alias, name.name().to_ascii_lowercase())],
+        IpsetAddress::Cidr(_) | IpsetAddress::Range(_) => Vec::new(),
+    }
+}
+
+/// Collects the diagnostics of [`validate_ruleset`].
+struct Validator {
+    /// The (lowercased) names of all defined aliases.
+    aliases: HashSet<String>,
+    /// The (lowercased) names of all defined IPSets.
+    ipsets: HashSet<String>,
+    /// Enabled rules by their match, with the line and action of the first occurrence.
+    matches: HashMap<Rule, (usize, String)>,
+    diagnostics: Vec<RuleDiagnostic>,
+}
+
+impl Validator {
+    fn push(&mut self, line: usize, severity: Severity, message: String) {
+        self.diagnostics.push(RuleDiagnostic {
+            line,
+            severity,
+            message,
+        });
+    }
+
+    fn check_reference(&mut self, line: usize, kind: RefKind, name: &str) {
+        let (defined, kind) = match kind {
+            RefKind::Alias => (&self.aliases, "alias"),
+            RefKind::Ipset => (&self.ipsets, "IPSet"),
+        };
+
+        if !defined.contains(name) {
+            self.push(
+                line,
+                Severity::Warning,
+                format!("undefined {kind} '{name}'"),
+            );
+        }
+    }
+
+    fn check_rule(&mut self, line: usize, rule: Rule, parsed: &FirewallRule) {
+        if let Kind::Match(rule_match) = parsed.kind() {
+            if let Some(name) = rule_match.fw_macro() {
+                if !fw_macros().keys().any(|m| m.eq_ignore_ascii_case(name)) {
+                    self.push(line, Severity::Error, format!("unknown macro '{name}'"));
+                }
+            }
+        }
+
+        for (kind, name) in rule_references(parsed) {
+            self.check_reference(line, kind, &name);
+        }
+
+        if !rule.enabled || rule.direction == "GROUP" {
+            return;
+        }
+
+        let action = rule.action.clone();
+        let key = Rule {
+            action: String::new(),
+            comment: None,
+            ..rule
+        };
+
+        match self.matches.get(&key) {
+            Some((first, first_action)) if *first_action != action => {
+                let message = format!(
+                    "conflicts with the rule on line {first}, which has the same match but \
+                     action '{first_action}'"
+                );
+                self.push(line, Severity::Warning, message);
+            }
+            Some(_) => (),
+            None => {
+                self.matches.insert(key, (line, action));
+            }
+        }
+    }
+}
+
+/// Validate a firewall configuration file, or a plain list of rules.
+///
+/// Rules, aliases and IPSet entries are checked with the parsers of the firewall, which reject
+/// e.g. unknown actions, invalid addresses and ports out of range. Rules are additionally checked
+/// for unknown macros and conflicts with an earlier rule of the same section, i.e. rules with the
+/// same direction and match but a different action. Aliases and IPSets which are referenced, but
+/// not defined in `text`, are reported as warnings, since they may be defined at the datacenter
+/// level.
+///
+/// Returns an empty list if no problems were found.
+pub fn validate_ruleset(text: &str) -> Vec<RuleDiagnostic> {
+    let entries = entries(text);
+
+    let mut validator = Validator {
+        aliases: HashSet::new(),
+        ipsets: HashSet::new(),
+        matches: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+
+    for (_, entry) in &entries {
+        match entry {
+            Entry::Alias(line) => {
+                validator
+                    .aliases
+                    .insert(alias_name(line).to_ascii_lowercase());
+            }
+            Entry::Section(header) => {
+                if let Some(name) = ipset_name(header) {
+                    validator.ipsets.insert(name.to_ascii_lowercase());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    for (line, entry) in entries {
+        match entry {
+            Entry::Section(_) => validator.matches.clear(),
+            Entry::Rule(text) => match parse_firewall_rule(text) {
+                Ok((rule, parsed)) => validator.check_rule(line, rule, &parsed),
+                Err(err) => validator.push(line, Severity::Error, format!("{err:#}")),
+            },
+            Entry::Alias(text) => {
+                if let Err(err) = text.parse::<Alias>() {
+                    validator.push(line, Severity::Error, format!("{err:#}"));
+                }
+            }
+            Entry::Ipset(text) => match text.parse::<IpsetEntry>() {
+                Ok(ipset_entry) => {
+                    for (kind, name) in ipset_entry_references(&ipset_entry) {
+                        validator.check_reference(line, kind, &name);
+                    }
+                }
+                Err(err) => validator.push(line, Severity::Error, format!("{err:#}")),
+            },
+        }
+    }
+
+    validator.diagnostics
+}
+
+/// A line of a firewall configuration file referencing an alias or IPSet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RuleRef {
+    /// The (1-based) line number.
+    pub line: usize,
+    /// The header of the section the line is in, e.g. `RULES` or `IPSET management`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+    /// Whether an alias or an IPSet is referenced.
+    pub kind: RefKind,
+    /// The line itself, without surrounding whitespace.
+    pub text: String,
+}
+
+/// An alias or IPSet defined in a firewall configuration file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Definition {
+    /// The (1-based) line number of the alias or the IPSet header.
+    pub line: usize,
+    /// Whether this is an alias or an IPSet.
+    pub kind: RefKind,
+    /// The name of the alias or IPSet.
+    pub name: String,
+}
+
+/// Returns the aliases and IPSets referenced by a rule or an IPSet entry, with their names
+/// lowercased and without scope.
+///
+/// Fails if the rule or entry cannot be parsed, as it is then unknown what it references, see
+/// [`validate_ruleset`] for finding all such lines.
+fn references(entry: &Entry) -> Result<Vec<(RefKind, String)>, Error> {
+    let mut references = match entry {
+        Entry::Rule(text) => rule_references(&text.parse()?),
+        Entry::Ipset(text) => ipset_entry_references(&text.parse()?),
+        Entry::Section(_) | Entry::Alias(_) => return Ok(Vec::new()),
+    };
+
+    references.sort();
+    references.dedup();
+    Ok(references)
+}
+
+/// Find all rules and IPSet entries in a firewall configuration file referencing `name`.
+///
+/// `name` may be an alias or an IPSet name, optionally scoped with `dc/` or `guest/`. If it is
+/// prefixed with `+`, only IPSets are considered. Names are compared case-insensitively.
+///
+/// Fails if any rule or IPSet entry cannot be parsed, since it might reference `name`.
+pub fn find_references(text: &str, name: &str) -> Result<Vec<RuleRef>, Error> {
+    let (kinds, name) = match name.strip_prefix('+') {
+        Some(name) => (&[RefKind::Ipset][..], name),
+        None => (&[RefKind::Alias, RefKind::Ipset][..], name),
+    };
+    let name = strip_scope(name).to_ascii_lowercase();
+
+    let mut section = None;
+    let mut refs = Vec::new();
+
+    for (line, entry) in entries(text) {
+        let text = match entry {
+            Entry::Section(header) => {
+                section = Some(header);
+                continue;
+            }
+            Entry::Rule(text) | Entry::Alias(text) | Entry::Ipset(text) => text,
+        };
+
+        let references = references(&entry).map_err(|err| format_err!("line {line}: {err:#}"))?;
+        for (kind, reference) in references {
+            if kinds.contains(&kind) && reference == name {
+                refs.push(RuleRef {
+                    line,
+                    section: section.map(str::to_string),
+                    kind,
+                    text: text.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(refs)
+}
+
+/// Returns the aliases and IPSets defined in a firewall configuration file, which are not
+/// referenced by any rule or IPSet entry of the same file.
+///
+/// Note that objects may still be referenced from other files, e.g. datacenter level aliases
+/// from the firewall configuration of a guest.
+///
+/// Fails if any rule or IPSet entry cannot be parsed, since it might reference any of them.
+pub fn list_unused(text: &str) -> Result<Vec<Definition>, Error> {
+    let entries = entries(text);
+
+    let mut referenced: HashSet<(RefKind, String)> = HashSet::new();
+    for (line, entry) in &entries {
+        referenced.extend(references(entry).map_err(|err| format_err!("line {line}: {err:#}"))?);
+    }
+
+    Ok(entries
+        .iter()
+        .filter_map(|(line, entry)| {
+            let (kind, name) = match entry {
+                Entry::Alias(text) => (RefKind::Alias, alias_name(text)),
+                Entry::Section(header) => (RefKind::Ipset, ipset_name(header)?),
+                Entry::Rule(_) | Entry::Ipset(_) => return None,
+            };
+
+            if referenced.contains(&(kind, name.to_ascii_lowercase())) {
+                return None;
+            }
+
+            Some(Definition {
+                line: *line,
+                kind,
+                name: name.to_string(),
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_rule_fields() {
+        let rule = parse_rule(
+            "|in SSH(ACCEPT) -i net0 -source 10.0.0.0/8 -dest +dc/management -p tcp \
+             -dport 22 -sport 1024:65535 -log nolog # admin access ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rule,
+            Rule {
+                enabled: false,
+                direction: "IN".to_string(),
+                action: "SSH(ACCEPT)".to_string(),
+                proto: Some("tcp".to_string()),
+                source: Some("10.0.0.0/8".to_string()),
+                dest: Some("+dc/management".to_string()),
+                dport: Some("22".to_string()),
+                sport: Some("1024:65535".to_string()),
+                options: vec![
+                    ("i".to_string(), "net0".to_string()),
+                    ("log".to_string(), "nolog".to_string()),
+                ],
+                comment: Some("admin access".to_string()),
+            }
+        );
+
+        let rule = parse_rule("GROUP webservers -i net1").unwrap();
+        assert_eq!(rule.direction, "GROUP");
+        assert_eq!(rule.action, "webservers");
+        assert!(rule.enabled);
+    }
+
+    #[test]
+    fn parse_rule_errors() {
+        for line in [
+            "",
+            "IN",
+            "SIDEWAYS ACCEPT",
+            "IN ALLOW",
+            "IN ACCEPT tcp",
+            "IN ACCEPT -p",
+            "IN ACCEPT -source 10.0.0.256",
+            "IN ACCEPT -p tcp -dport 70000",
+        ] {
+            assert!(parse_rule(line).is_err(), "'{line}' should not parse");
+        }
+
+        let err = parse_rules("IN ACCEPT\n\n# comment\nOUT").unwrap_err();
+        assert!(err.to_string().starts_with("line 4: "), "{err}");
+    }
+
+    #[test]
+    fn round_trip() {
+        let text = "\
+            IN ACCEPT -source 192.168.0.0/16 -p tcp -dport 8006 -i net0 # web ui\n\
+            |OUT DROP -dest +dc/blocked -log warning\n\
+            FORWARD ACCEPT -p udp -sport 53\n\
+            GROUP webservers -i net1\n";
+
+        let rules = parse_rules(text).unwrap();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(format_rules(&rules), text);
+        assert_eq!(parse_rules(&format_rules(&rules)).unwrap(), rules);
+
+        // options are brought into the canonical order
+        let rules = parse_rules("in ACCEPT -p tcp -i net0 -source 10.0.0.1").unwrap();
+        assert_eq!(
+            format_rules(&rules),
+            "IN ACCEPT -source 10.0.0.1 -p tcp -i net0\n"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        let text = "\
+            [OPTIONS]\n\
+            enable: 1\n\
+            \n\
+            [ALIASES]\n\
+            mgmt 10.0.0.0/24 # management network\n\
+            broken 10.0.0.1/33\n\
+            \n\
+            [IPSET web] # web servers\n\
+            192.168.1.10\n\
+            !192.168.1.11\n\
+            dc/mgmt\n\
+            \n\
+            [RULES]\n\
+            IN ACCEPT -source +dc/web -p tcp -dport 443,8000:8080\n\
+            IN SSH(ACCEPT) -source dc/mgmt\n\
+            IN Telnet(DROP) -source 192.168.1.1-192.168.1.20\n\
+            IN Sshh(ACCEPT)\n\
+            IN ACCEPT -source 10.0.0.256\n\
+            IN ACCEPT -p tcp -dport 70000\n\
+            IN DROP -source +dc/web -p tcp -dport 443,8000:8080 # oops\n\
+            |IN REJECT -source +dc/web -p tcp -dport 443,8000:8080\n\
+            OUT ACCEPT -dest +guest/missing -source dc/other\n\
+            OUT ALLOW\n\
+            OUT\n\
+            \n\
+            [group webservers]\n\
+            IN DROP -source +dc/web -p tcp -dport 443,8000:8080\n";
+
+        let diagnostics = validate_ruleset(text);
+
+        // errors are reported by the parsers of the firewall, only check where they are
+        let lines: Vec<(usize, Severity)> =
+            diagnostics.iter().map(|d| (d.line, d.severity)).collect();
+        assert_eq!(
+            lines,
+            [
+                (6, Severity::Error),
+                (17, Severity::Error),
+                (18, Severity::Error),
+                (19, Severity::Error),
+                (20, Severity::Warning),
+                (22, Severity::Warning),
+                (22, Severity::Warning),
+                (23, Severity::Error),
+                (24, Severity::Error),
+            ]
+        );
+
+        let warnings: Vec<&str> = diagnostics
+            .iter()
+            .filter(|d| d.severity == Severity::Warning)
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                "conflicts with the rule on line 14, which has the same match but action \
+                 'ACCEPT'",
+                "undefined alias 'other'",
+                "undefined IPSet 'missing'",
+            ]
+        );
+
+        assert!(validate_ruleset("IN ACCEPT -p tcp -dport 22\nOUT DROP -log nolog\n").is_empty());
+    }
+
+    #[test]
+    fn alias_and_ipset_references() {
+        let text = "\
+            [ALIASES]\n\
+            mgmt 10.0.0.0/24\n\
+            backup 10.0.1.0/24\n\
+            \n\
+            [IPSET web]\n\
+            192.168.1.10\n\
+            !dc/MGMT\n\
+            \n\
+            [IPSET old]\n\
+            192.168.2.0/24\n\
+            \n\
+            [RULES]\n\
+            IN ACCEPT -source dc/mgmt -dest +dc/web -p tcp -dport 443\n\
+            IN ACCEPT -source dc/mgmt -dest +dc/web\n\
+            \n\
+            [group webservers]\n\
+            IN ACCEPT -source +dc/web\n";
+
+        let lines = |refs: Vec<RuleRef>| -> Vec<(usize, RefKind)> {
+            refs.into_iter().map(|r| (r.line, r.kind)).collect()
+        };
+
+        let refs = find_references(text, "mgmt").unwrap();
+        assert_eq!(
+            lines(refs.clone()),
+            [
+                (7, RefKind::Alias),
+                (13, RefKind::Alias),
+                (14, RefKind::Alias)
+            ]
+        );
+        assert_eq!(refs[0].section.as_deref(), Some("IPSET web"));
+        assert_eq!(refs[0].text, "!dc/MGMT");
+        assert_eq!(refs[1].section.as_deref(), Some("RULES"));
+
+        assert_eq!(
+            lines(find_references(text, "+web").unwrap()),
+            [
+                (13, RefKind::Ipset),
+                (14, RefKind::Ipset),
+                (17, RefKind::Ipset)
+            ]
+        );
+        assert_eq!(lines(find_references(text, "+mgmt").unwrap()), []);
+        assert_eq!(lines(find_references(text, "backup").unwrap()), []);
+
+        let unused: Vec<(usize, RefKind, String)> = list_unused(text)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.line, d.kind, d.name))
+            .collect();
+        assert_eq!(
+            unused,
+            [
+                (3, RefKind::Alias, "backup".to_string()),
+                (9, RefKind::Ipset, "old".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn references_from_unparseable_rules() {
+        // the broken rule might reference anything, so nothing can be reported
+        let text = "\
+            [ALIASES]\n\
+            mgmt 10.0.0.0/24\n\
+            \n\
+            [RULES]\n\
+            IN ACCEPT -source dc/mgmt -dport\n";
+
+        let err = find_references(text, "mgmt").unwrap_err();
+        assert!(err.to_string().starts_with("line 5: "), "{err}");
+
+        let err = list_unused(text).unwrap_err();
+        assert!(err.to_string().starts_with("line 5: "), "{err}");
+    }
+
+    #[test]
+    fn macros_from_firewall() {
+        for line in [
+            "IN SPICEproxy(ACCEPT)",
+            "IN spiceproxy(ACCEPT)",
+            "IN SSH(DROP)",
+        ] {
+            assert!(
+                validate_ruleset(line).is_empty(),
+                "'{line}' should be valid"
+            );
+        }
+    }
+}
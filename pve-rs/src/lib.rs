@@ -8,36 +8,64 @@ use anyhow::Error;
 use serde_json::json;
 
 use proxmox_apt_api_types::APTUpdateInfo;
+use proxmox_http::ProxyConfig;
+use proxmox_notify::context::Context;
+use proxmox_notify::context::pve::PVE_CONTEXT;
 use proxmox_notify::{Config, Notification, Severity};
 
 #[path = "../common/src/mod.rs"]
 mod common;
 
+mod firewall;
 mod sdn;
 
 pub mod bindings;
 
-fn send_notification(notification: &Notification) -> Result<(), Error> {
+fn read_notification_config() -> Result<Config, Error> {
     let config = proxmox_sys::fs::file_read_optional_string("/etc/pve/notifications.cfg")?
         .unwrap_or_default();
     let private_config =
         proxmox_sys::fs::file_read_optional_string("/etc/pve/priv/notifications.cfg")?
             .unwrap_or_default();
 
-    let config = Config::new(&config, &private_config)?;
+    let (config, _) = common::notify::parse_config(&config, &private_config)?;
+    Ok(config)
+}
 
-    proxmox_notify::api::common::send(&config, notification)?;
+/// Look up the email address of `user`, used to resolve the users of mail targets. It is called
+/// from `common` code.
+pub fn lookup_email_for_user(user: &str) -> Option<String> {
+    PVE_CONTEXT.lookup_email_for_user(user)
+}
 
-    Ok(())
+/// Returns the HTTP proxy configured for the cluster, used when probing notification targets and
+/// Yubico validation servers. It is called from `common` code.
+pub fn http_proxy_config() -> Option<ProxyConfig> {
+    PVE_CONTEXT.http_proxy_config()
 }
 
 /// This is the produce specific code to send available upadte information via the notification
 /// system. It is called from `common` code.
-pub fn send_updates_available(updates: &[&APTUpdateInfo]) -> Result<(), Error> {
-    let hostname = proxmox_sys::nodename().to_string();
+pub fn send_updates_available(
+    updates: &[&APTUpdateInfo],
+) -> Result<common::apt::UpdateNotificationResult, Error> {
+    send_updates(
+        &read_notification_config()?,
+        proxmox_sys::nodename(),
+        updates,
+    )
+}
 
+/// Send the notification about the available `updates` according to `config`.
+///
+/// The updates only count as notified if a matcher routed the notification to a target.
+fn send_updates(
+    config: &Config,
+    hostname: &str,
+    updates: &[&APTUpdateInfo],
+) -> Result<common::apt::UpdateNotificationResult, Error> {
     let metadata = HashMap::from([
-        ("hostname".into(), hostname.clone()),
+        ("hostname".into(), hostname.to_string()),
         ("type".into(), "package-updates".into()),
     ]);
 
@@ -71,6 +99,96 @@ pub fn send_updates_available(updates: &[&APTUpdateInfo]) -> Result<(), Error> {
     let notification =
         Notification::from_template(Severity::Info, "package-updates", template_data, metadata);
 
-    send_notification(&notification)?;
-    Ok(())
+    let fanout = common::notify::send(config, &notification, lookup_email_for_user)?;
+
+    Ok(common::apt::UpdateNotificationResult {
+        updates: updates.len(),
+        notified: fanout.targets > 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use proxmox_apt_api_types::APTUpdateInfo;
+    use proxmox_notify::Config;
+    use proxmox_notify::context::pve::PVE_CONTEXT;
+
+    use super::send_updates;
+    use crate::common::apt::UpdateNotificationResult;
+    use crate::common::notify;
+
+    fn update(package: &str) -> APTUpdateInfo {
+        serde_json::from_value(serde_json::json!({
+            "Package": package,
+            "Title": package,
+            "Arch": "amd64",
+            "Description": "",
+            "Version": "2.0",
+            "OldVersion": "1.0",
+            "Origin": "Proxmox",
+            "Priority": "optional",
+            "Section": "admin",
+        }))
+        .expect("valid update info")
+    }
+
+    /// Parse a configuration which replaces the built-in default matcher with `matchers`.
+    fn config(matchers: &str) -> Config {
+        let raw_config = format!(
+            "matcher: default-matcher\n\
+             \tdisable true\n\
+             \n\
+             gotify: disabled\n\
+             \tserver http://127.0.0.1:1\n\
+             \tdisable true\n\
+             \n\
+             gotify: enabled\n\
+             \tserver http://127.0.0.1:1\n\
+             \n\
+             {matchers}"
+        );
+        let raw_private_config = "gotify: disabled\n\
+                                  \ttoken secret\n\
+                                  \n\
+                                  gotify: enabled\n\
+                                  \ttoken secret\n";
+
+        notify::parse_config(&raw_config, raw_private_config)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn updates_notified() {
+        proxmox_notify::context::set_context(&PVE_CONTEXT);
+
+        let (vim, kernel) = (update("vim"), update("proxmox-kernel-6.8"));
+        let updates = [&vim, &kernel];
+        let send = |matchers: &str| send_updates(&config(matchers), "node1", &updates).unwrap();
+
+        let not_notified = UpdateNotificationResult {
+            updates: 2,
+            notified: false,
+        };
+
+        // no matcher routes the notification
+        assert_eq!(send(""), not_notified);
+        assert_eq!(
+            send("matcher: backups\n\tmatch-field exact:type=vzdump\n\ttarget enabled\n"),
+            not_notified
+        );
+        // the only target is disabled
+        assert_eq!(
+            send("matcher: updates\n\tmatch-field exact:type=package-updates\n\ttarget disabled\n"),
+            not_notified
+        );
+
+        assert_eq!(
+            send("matcher: updates\n\tmatch-field exact:type=package-updates\n\ttarget enabled\n"),
+            UpdateNotificationResult {
+                updates: 2,
+                notified: true,
+            }
+        );
+    }
 }
@@ -0,0 +1,1451 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::net::IpAddr;
+
+use anyhow::{Context, Error, bail, format_err};
+use openssl::hash::{MessageDigest, hash};
+use serde::Serialize;
+
+use proxmox_network_types::ip_address::Cidr;
+use proxmox_section_config::typed::SectionConfigData;
+use proxmox_ve_config::common::valid::Validatable;
+use proxmox_ve_config::sdn::fabric::section_config::Section;
+use proxmox_ve_config::sdn::fabric::section_config::fabric::FabricId;
+use proxmox_ve_config::sdn::fabric::section_config::interface::InterfaceName;
+use proxmox_ve_config::sdn::fabric::section_config::node::api::Node as ApiNode;
+use proxmox_ve_config::sdn::fabric::section_config::node::{Node as ConfigNode, NodeId};
+use proxmox_ve_config::sdn::fabric::{FabricConfig, FabricEntry};
+
+/// Description of the allowed format of a [`FabricId`], used in error messages.
+const FABRIC_ID_FORMAT: &str = "must be at most 8 characters long, only contain ASCII letters, \
+    digits and '-', and start and end with a letter or digit";
+
+/// Description of the allowed format of a [`NodeId`], used in error messages.
+const NODE_ID_FORMAT: &str = "must be a valid hostname, only containing ASCII letters, digits \
+    and '-', and starting and ending with a letter or digit";
+
+/// Parse a [`FabricId`] passed in from perl, returning an error describing the allowed format if
+/// it is invalid.
+pub fn parse_fabric_id(id: &str) -> Result<FabricId, Error> {
+    FabricId::from_string(id.to_string())
+        .map_err(|err| format_err!("invalid fabric id '{id}': {FABRIC_ID_FORMAT} ({err})"))
+}
+
+/// Parse a [`NodeId`] passed in from perl, returning an error describing the allowed format if it
+/// is invalid.
+pub fn parse_node_id(id: &str) -> Result<NodeId, Error> {
+    NodeId::from_string(id.to_string())
+        .map_err(|err| format_err!("invalid node id '{id}': {NODE_ID_FORMAT} ({err})"))
+}
+
+/// Compute the SHA256 digest of a [`FabricConfig`].
+pub fn digest(config: &FabricConfig) -> Result<Vec<u8>, Error> {
+    let data = serde_json::to_vec(config)?;
+    Ok(hash(MessageDigest::sha256(), &data)?.to_vec())
+}
+
+/// Check the hex encoded `digest` passed in from perl against the digest of `config`.
+///
+/// If no digest is passed, the check is skipped.
+pub fn check_digest(config: &FabricConfig, digest: Option<&str>) -> Result<(), Error> {
+    let Some(expected) = digest else {
+        return Ok(());
+    };
+
+    let expected = hex::decode(expected).map_err(|err| format_err!("invalid digest: {err}"))?;
+
+    if expected != self::digest(config)? {
+        bail!("detected modified configuration - file changed by other user? Try again.");
+    }
+
+    Ok(())
+}
+
+/// Returns the names of all interfaces configured on a fabric node.
+pub fn node_interface_names(node: &ConfigNode) -> Vec<&str> {
+    match node {
+        ConfigNode::Openfabric(n) => n
+            .properties()
+            .interfaces()
+            .map(|i| i.name().as_str())
+            .collect(),
+        ConfigNode::Ospf(n) => n
+            .properties()
+            .interfaces()
+            .map(|i| i.name().as_str())
+            .collect(),
+    }
+}
+
+/// Returns the address of a fabric node, which is used for its interfaces without an address of
+/// their own.
+///
+/// The IPv4 address is preferred, the IPv6 address is only used for IPv6-only nodes.
+pub fn node_interface_address(node: &ConfigNode) -> Result<IpAddr, Error> {
+    if let Some(ip) = node.ip() {
+        Ok(IpAddr::from(ip))
+    } else if let Some(ip) = node.ip6() {
+        Ok(IpAddr::from(ip))
+    } else {
+        bail!("there has to be a ipv4 or ipv6 node address");
+    }
+}
+
+/// Returns the addresses of the dummy loopback interface of a fabric node, which are also used
+/// as its router-ids.
+pub fn node_loopbacks(node: &ConfigNode) -> Result<Vec<Cidr>, Error> {
+    let mut cidrs = Vec::new();
+
+    if let Some(ip) = node.ip() {
+        cidrs.push(Cidr::new_v4(ip, 32)?);
+    }
+    if let Some(ip6) = node.ip6() {
+        cidrs.push(Cidr::new_v6(ip6, 128)?);
+    }
+
+    Ok(cidrs)
+}
+
+/// Returns the loopback addresses of the node `node_id` in all fabrics it is part of, keyed by
+/// the fabric id.
+///
+/// See [`node_loopbacks`]
+pub fn fabric_loopbacks(
+    config: &FabricConfig,
+    node_id: &NodeId,
+) -> Result<BTreeMap<String, Vec<Cidr>>, Error> {
+    config
+        .values()
+        .filter_map(|entry| {
+            entry
+                .get_node(node_id)
+                .ok()
+                .map(|node| Ok((entry.fabric().id().to_string(), node_loopbacks(node)?)))
+        })
+        .collect()
+}
+
+/// Split a VLAN interface name like `ens19.100` into the name of the parent interface and the
+/// VLAN id.
+///
+/// Returns `None` if `name` is not a VLAN interface.
+fn split_vlan_name(name: &str) -> Option<(&str, &str)> {
+    name.split_once('.')
+        .filter(|(_name, vlan_id)| !vlan_id.is_empty() && vlan_id.chars().all(char::is_numeric))
+}
+
+/// Map an interface name according to `mapping`, keeping the VLAN id of VLAN interfaces.
+///
+/// Returns `None` if there is no mapping for the interface.
+fn map_name(mapping: &HashMap<String, String>, name: &str) -> Result<Option<InterfaceName>, Error> {
+    match split_vlan_name(name) {
+        Some((interface_name, vlan_id)) => mapping
+            .get(interface_name)
+            .map(|mapped_name| InterfaceName::from_string(format!("{mapped_name}.{vlan_id}")))
+            .transpose(),
+        _ => mapping
+            .get(name)
+            .cloned()
+            .map(InterfaceName::from_string)
+            .transpose(),
+    }
+}
+
+/// Map all interface names of the node `node_id` to a different one, according to `mapping`.
+///
+/// All new names are validated before any interface gets renamed, so an invalid mapping leaves
+/// the configuration untouched.
+pub fn map_interfaces(
+    config: &mut FabricConfig,
+    node_id: &NodeId,
+    mapping: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let mut renames: HashMap<String, InterfaceName> = HashMap::new();
+
+    for entry in config.values() {
+        let Ok(node) = entry.get_node(node_id) else {
+            continue;
+        };
+
+        for name in node_interface_names(node) {
+            if renames.contains_key(name) {
+                continue;
+            }
+
+            let mapped_name = map_name(mapping, name)
+                .with_context(|| format!("could not map interface '{name}'"))?;
+
+            if let Some(mapped_name) = mapped_name {
+                renames.insert(name.to_string(), mapped_name);
+            }
+        }
+    }
+
+    for entry in config.get_fabrics_mut() {
+        let Ok(node) = entry.get_node_mut(node_id) else {
+            continue;
+        };
+
+        match node {
+            ConfigNode::Openfabric(node_section) => {
+                for interface in node_section.properties_mut().interfaces_mut() {
+                    if let Some(mapped_name) = renames.get(interface.name().as_str()) {
+                        interface.set_name(mapped_name.clone());
+                    }
+                }
+            }
+            ConfigNode::Ospf(node_section) => {
+                for interface in node_section.properties_mut().interfaces_mut() {
+                    if let Some(mapped_name) = renames.get(interface.name().as_str()) {
+                        interface.set_name(mapped_name.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the ids of all fabrics which use the interface `interface_name` on the node `node_id`.
+///
+/// VLAN interfaces on top of `interface_name` (e.g. `ens19.100` for `ens19`) count as using it,
+/// like in [`map_name`]. An interface should only ever be part of a single fabric, but we return
+/// all of them, so callers can detect (and report) the conflicting case.
+pub fn fabrics_for_interface(
+    config: &FabricConfig,
+    node_id: &NodeId,
+    interface_name: &str,
+) -> Vec<FabricId> {
+    let mut fabrics: Vec<FabricId> = config
+        .values()
+        .filter(|entry| {
+            entry.get_node(node_id).is_ok_and(|node| {
+                node_interface_names(node).into_iter().any(|name| {
+                    name == interface_name
+                        || split_vlan_name(name)
+                            .is_some_and(|(parent, _vlan_id)| parent == interface_name)
+                })
+            })
+        })
+        .map(|entry| entry.fabric().id().clone())
+        .collect();
+
+    fabrics.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    fabrics
+}
+
+/// Returns the FRR daemons which need to be enabled for the fabrics the node `node_id` is part
+/// of, sorted by name.
+pub fn enabled_daemons(config: &FabricConfig, node_id: &NodeId) -> BTreeSet<&'static str> {
+    config
+        .values()
+        .filter(|entry| entry.get_node(node_id).is_ok())
+        .map(fabric_daemon)
+        .collect()
+}
+
+/// Returns the FRR daemons which need to be enabled on every node that is part of at least one
+/// fabric, keyed by the node id.
+pub fn all_enabled_daemons(config: &FabricConfig) -> BTreeMap<String, BTreeSet<&'static str>> {
+    let mut daemons: BTreeMap<String, BTreeSet<&'static str>> = BTreeMap::new();
+
+    for entry in config.values() {
+        for (node_id, _node) in entry.nodes() {
+            daemons
+                .entry(node_id.to_string())
+                .or_default()
+                .insert(fabric_daemon(entry));
+        }
+    }
+
+    daemons
+}
+
+/// How FRR has to be applied after generating a new configuration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyStrategy {
+    /// Only the configuration changed, reloading FRR is sufficient.
+    Reload,
+    /// The set of enabled daemons changed, so FRR has to be restarted.
+    Restart,
+}
+
+/// Returns whether FRR can be reloaded on the node `node_id` or has to be restarted, because
+/// the daemons it needs differ from `previous_daemons`.
+pub fn apply_strategy(
+    config: &FabricConfig,
+    node_id: &NodeId,
+    previous_daemons: &[String],
+) -> ApplyStrategy {
+    let previous: BTreeSet<&str> = previous_daemons.iter().map(String::as_str).collect();
+
+    if enabled_daemons(config, node_id) == previous {
+        ApplyStrategy::Reload
+    } else {
+        ApplyStrategy::Restart
+    }
+}
+
+/// Returns the FRR daemon implementing the protocol of a fabric.
+fn fabric_daemon(entry: &FabricEntry) -> &'static str {
+    match entry {
+        FabricEntry::Ospf(_) => "ospfd",
+        FabricEntry::Openfabric(_) => "fabricd",
+    }
+}
+
+/// Removes the node `node_id` from all fabrics it is part of.
+///
+/// Returns the ids of all fabrics the node has been removed from, which is empty if the node was
+/// not part of any fabric.
+pub fn delete_node_everywhere(config: &mut FabricConfig, node_id: &NodeId) -> Vec<FabricId> {
+    let mut fabrics = Vec::new();
+
+    for entry in config.get_fabrics_mut() {
+        if entry.delete_node(node_id).is_ok() {
+            fabrics.push(entry.fabric().id().clone());
+        }
+    }
+
+    fabrics.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+    fabrics
+}
+
+/// Removes all node sections whose fabric does not exist.
+///
+/// This can happen if the fabric got removed out-of-band, e.g. by editing the configuration file
+/// manually. Such a configuration cannot be converted into a [`FabricConfig`], so this operates
+/// on the raw sections. Returns the section ids of the removed nodes.
+pub fn prune_orphan_nodes(sections: &mut SectionConfigData<Section>) -> Vec<String> {
+    let fabric_ids: HashSet<&str> = sections
+        .iter()
+        .filter_map(|(_id, section)| match section {
+            Section::OpenfabricFabric(fabric) => Some(fabric.id().as_str()),
+            Section::OspfFabric(fabric) => Some(fabric.id().as_str()),
+            Section::OpenfabricNode(_) | Section::OspfNode(_) => None,
+        })
+        .collect();
+
+    let mut orphans: Vec<String> = sections
+        .iter()
+        .filter(|(_id, section)| {
+            let fabric_id = match section {
+                Section::OpenfabricNode(node) => node.id().fabric_id(),
+                Section::OspfNode(node) => node.id().fabric_id(),
+                Section::OpenfabricFabric(_) | Section::OspfFabric(_) => return false,
+            };
+
+            !fabric_ids.contains(fabric_id.as_str())
+        })
+        .map(|(id, _section)| id.to_string())
+        .collect();
+
+    orphans.sort();
+
+    for id in &orphans {
+        sections.remove(id);
+    }
+
+    orphans
+}
+
+/// The severity of a [`LintFinding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    /// The configuration works, but is most likely not what the user intended.
+    Warning,
+    /// The configuration will not work as expected.
+    Error,
+}
+
+/// The check which produced a [`LintFinding`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintKind {
+    /// A fabric has less than two nodes, so there is nothing to route between.
+    SingleNodeFabric,
+    /// An interface of a node is part of more than one fabric.
+    InterfaceConflict,
+    /// The same address is used as router-id by different nodes, or by the same node in different
+    /// fabrics.
+    RouterIdConflict,
+    /// The prefixes of two fabrics overlap.
+    OverlappingPrefix,
+    /// A node has neither an IPv4 nor an IPv6 address.
+    MissingAddress,
+    /// A node has no interfaces configured, so it cannot reach any other node of the fabric.
+    NoInterfaces,
+}
+
+/// A single problem found by [`lint`].
+#[derive(Clone, Debug, Serialize)]
+pub struct LintFinding {
+    /// How severe the problem is.
+    pub severity: LintSeverity,
+    /// The check which found the problem.
+    pub kind: LintKind,
+    /// The fabric, node, interface or address the finding refers to.
+    pub entity: String,
+    /// A human readable description of the problem.
+    pub message: String,
+}
+
+impl LintFinding {
+    fn new(severity: LintSeverity, kind: LintKind, entity: String, message: String) -> Self {
+        Self {
+            severity,
+            kind,
+            entity,
+            message,
+        }
+    }
+}
+
+/// Returns whether the prefixes `a` and `b` of an address family with `bits` bits overlap.
+fn prefixes_overlap(a: u128, a_len: u8, b: u128, b_len: u8, bits: u32) -> bool {
+    let shift = bits - u32::from(a_len.min(b_len));
+    a.checked_shr(shift).unwrap_or(0) == b.checked_shr(shift).unwrap_or(0)
+}
+
+/// Checks the whole configuration for problems which are not caught by validating it.
+///
+/// The findings are sorted by severity, errors first.
+pub fn lint(config: &FabricConfig) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+    let mut interfaces: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    let mut router_ids: BTreeMap<IpAddr, BTreeSet<(String, String)>> = BTreeMap::new();
+
+    for entry in config.values() {
+        let fabric_id = entry.fabric().id().to_string();
+
+        if entry.nodes().count() < 2 {
+            findings.push(LintFinding::new(
+                LintSeverity::Warning,
+                LintKind::SingleNodeFabric,
+                fabric_id.clone(),
+                format!("fabric '{fabric_id}' has less than two nodes"),
+            ));
+        }
+
+        for (node_id, node) in entry.nodes() {
+            let names = node_interface_names(node);
+
+            if names.is_empty() {
+                findings.push(LintFinding::new(
+                    LintSeverity::Warning,
+                    LintKind::NoInterfaces,
+                    node.id().to_string(),
+                    format!("node '{node_id}' in fabric '{fabric_id}' has no interfaces"),
+                ));
+            }
+
+            for name in names {
+                interfaces
+                    .entry((node_id.to_string(), name.to_string()))
+                    .or_default()
+                    .push(fabric_id.clone());
+            }
+
+            let ips = [node.ip().map(IpAddr::from), node.ip6().map(IpAddr::from)];
+
+            if ips.iter().all(Option::is_none) {
+                findings.push(LintFinding::new(
+                    LintSeverity::Error,
+                    LintKind::MissingAddress,
+                    node.id().to_string(),
+                    format!("node '{node_id}' in fabric '{fabric_id}' has no IP address"),
+                ));
+            }
+
+            for ip in ips.into_iter().flatten() {
+                router_ids
+                    .entry(ip)
+                    .or_default()
+                    .insert((node_id.to_string(), fabric_id.clone()));
+            }
+        }
+    }
+
+    for ((node_id, name), fabrics) in interfaces {
+        if fabrics.len() > 1 {
+            findings.push(LintFinding::new(
+                LintSeverity::Error,
+                LintKind::InterfaceConflict,
+                format!("{node_id}:{name}"),
+                format!(
+                    "interface '{name}' on node '{node_id}' is part of multiple fabrics: {}",
+                    fabrics.join(", "),
+                ),
+            ));
+        }
+    }
+
+    for (ip, nodes) in router_ids {
+        if nodes.len() > 1 {
+            let nodes: Vec<String> = nodes
+                .into_iter()
+                .map(|(node_id, fabric_id)| format!("{node_id} ({fabric_id})"))
+                .collect();
+
+            findings.push(LintFinding::new(
+                LintSeverity::Error,
+                LintKind::RouterIdConflict,
+                ip.to_string(),
+                format!(
+                    "address {ip} is used by multiple nodes: {}",
+                    nodes.join(", "),
+                ),
+            ));
+        }
+    }
+
+    let fabrics: Vec<_> = config.values().map(|entry| entry.fabric()).collect();
+
+    for (i, a) in fabrics.iter().enumerate() {
+        for b in &fabrics[i + 1..] {
+            let v4_overlap = match (a.ip_prefix(), b.ip_prefix()) {
+                (Some(a), Some(b)) => prefixes_overlap(
+                    u32::from(*a.address()).into(),
+                    a.mask(),
+                    u32::from(*b.address()).into(),
+                    b.mask(),
+                    32,
+                ),
+                _ => false,
+            };
+
+            let v6_overlap = match (a.ip6_prefix(), b.ip6_prefix()) {
+                (Some(a), Some(b)) => prefixes_overlap(
+                    u128::from(*a.address()),
+                    a.mask(),
+                    u128::from(*b.address()),
+                    b.mask(),
+                    128,
+                ),
+                _ => false,
+            };
+
+            if v4_overlap || v6_overlap {
+                let (a, b) = (a.id(), b.id());
+                findings.push(LintFinding::new(
+                    LintSeverity::Warning,
+                    LintKind::OverlappingPrefix,
+                    format!("{a}, {b}"),
+                    format!("the prefixes of fabric '{a}' and fabric '{b}' overlap"),
+                ));
+            }
+        }
+    }
+
+    findings.sort_by(|a, b| b.severity.cmp(&a.severity));
+    findings
+}
+
+/// Validates the configuration without serializing it.
+///
+/// Fails if the configuration is invalid or if [`lint`] finds any problem with
+/// [`LintSeverity::Error`], otherwise returns the messages of the remaining warnings.
+pub fn validate(config: &FabricConfig) -> Result<Vec<String>, Error> {
+    config.clone().into_valid()?;
+
+    let (errors, warnings): (Vec<_>, Vec<_>) = lint(config)
+        .into_iter()
+        .partition(|finding| finding.severity == LintSeverity::Error);
+
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.into_iter().map(|finding| finding.message).collect();
+        bail!("invalid fabric configuration: {}", messages.join("; "));
+    }
+
+    Ok(warnings
+        .into_iter()
+        .map(|finding| finding.message)
+        .collect())
+}
+
+/// The kind of a [`FabricChange`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    /// The entity only exists in the other configuration.
+    Added,
+    /// The entity only exists in the current configuration.
+    Removed,
+    /// The entity exists in both configurations, but its properties differ.
+    Modified,
+}
+
+/// A fabric or node which differs between two configurations, as returned by [`diff_configs`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FabricChange {
+    /// How the fabric or node changed.
+    pub change: ChangeKind,
+    /// The fabric which changed, or which contains the changed node.
+    pub fabric: String,
+    /// The node of the fabric, unset if the change refers to the fabric itself.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+    /// The properties which differ, only set for modified fabrics and nodes.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+}
+
+/// Compares a fabric or node between two configurations, based on their API representation.
+///
+/// Returns the kind of the change and the names of the changed properties, or `None` if the
+/// entity is the same in both configurations.
+fn diff_entity<T: Serialize>(
+    current: Option<T>,
+    other: Option<T>,
+) -> Result<Option<(ChangeKind, Vec<String>)>, Error> {
+    let (current, other) = match (current, other) {
+        (Some(current), Some(other)) => (current, other),
+        (None, Some(_)) => return Ok(Some((ChangeKind::Added, Vec::new()))),
+        (Some(_), None) => return Ok(Some((ChangeKind::Removed, Vec::new()))),
+        (None, None) => return Ok(None),
+    };
+
+    let current = serde_json::to_value(current)?;
+    let other = serde_json::to_value(other)?;
+
+    if current == other {
+        return Ok(None);
+    }
+
+    let mut fields = BTreeSet::new();
+
+    if let (Some(current), Some(other)) = (current.as_object(), other.as_object()) {
+        for key in current.keys().chain(other.keys()) {
+            if current.get(key) != other.get(key) {
+                fields.insert(key.to_string());
+            }
+        }
+    }
+
+    Ok(Some((ChangeKind::Modified, Vec::from_iter(fields))))
+}
+
+/// Returns all fabrics and nodes which have been added, removed or modified in `other` compared
+/// to `current`.
+///
+/// The changes are sorted by fabric id, every fabric is followed by the changes of its nodes.
+pub fn diff_configs(
+    current: &FabricConfig,
+    other: &FabricConfig,
+) -> Result<Vec<FabricChange>, Error> {
+    type Pair<'a, T> = (Option<&'a T>, Option<&'a T>);
+
+    let mut fabrics: BTreeMap<String, Pair<FabricEntry>> = BTreeMap::new();
+
+    for entry in current.values() {
+        fabrics
+            .entry(entry.fabric().id().to_string())
+            .or_default()
+            .0 = Some(entry);
+    }
+
+    for entry in other.values() {
+        fabrics
+            .entry(entry.fabric().id().to_string())
+            .or_default()
+            .1 = Some(entry);
+    }
+
+    let mut changes = Vec::new();
+
+    for (fabric_id, (current, other)) in fabrics {
+        if let Some((change, fields)) = diff_entity(
+            current.map(FabricEntry::fabric),
+            other.map(FabricEntry::fabric),
+        )? {
+            changes.push(FabricChange {
+                change,
+                fabric: fabric_id.clone(),
+                node: None,
+                fields,
+            });
+        }
+
+        let mut nodes: BTreeMap<String, Pair<ConfigNode>> = BTreeMap::new();
+
+        for (node_id, node) in current.into_iter().flat_map(FabricEntry::nodes) {
+            nodes.entry(node_id.to_string()).or_default().0 = Some(node);
+        }
+
+        for (node_id, node) in other.into_iter().flat_map(FabricEntry::nodes) {
+            nodes.entry(node_id.to_string()).or_default().1 = Some(node);
+        }
+
+        for (node_id, (current, other)) in nodes {
+            if let Some((change, fields)) = diff_entity(
+                current.cloned().map(ApiNode::from),
+                other.cloned().map(ApiNode::from),
+            )? {
+                changes.push(FabricChange {
+                    change,
+                    fabric: fabric_id.clone(),
+                    node: Some(node_id),
+                    fields,
+                });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proxmox_ve_config::sdn::fabric::section_config::interface::InterfaceName;
+
+    use crate::sdn::status::RunningConfig;
+
+    fn sample_config() -> FabricConfig {
+        let raw_config = r#"{
+              "fabrics": {
+                "ids": {
+                  "test": {
+                    "area": "0",
+                    "type": "ospf_fabric",
+                    "id": "test",
+                    "ip_prefix": "172.16.6.0/24"
+                  },
+                  "test_node1": {
+                    "interfaces": [
+                      "name=ens19",
+                      "name=ens20"
+                    ],
+                    "id": "test_node1",
+                    "ip": "172.16.6.1",
+                    "type": "ospf_node"
+                  },
+                  "test_node2": {
+                    "ip": "172.16.6.2",
+                    "type": "ospf_node",
+                    "id": "test_node2",
+                    "interfaces": [
+                      "name=ens19",
+                      "name=ens20"
+                    ]
+                  },
+                  "test1": {
+                    "area": "1",
+                    "type": "ospf_fabric",
+                    "id": "test1",
+                    "ip_prefix": "172.16.7.0/24"
+                  },
+                  "test1_node1": {
+                    "interfaces": [
+                      "name=ens21",
+                      "name=ens22"
+                    ],
+                    "id": "test1_node1",
+                    "ip": "172.16.7.1",
+                    "type": "ospf_node"
+                  }
+                }
+              }
+            }
+            "#;
+
+        let running_config: RunningConfig =
+            serde_json::from_str(raw_config).expect("error parsing running-config");
+        let section_config = SectionConfigData::from_iter(
+            running_config.fabrics.expect("no fabrics configured").ids,
+        );
+        FabricConfig::from_section_config(section_config)
+            .expect("error converting section config to fabricconfig")
+            .into_inner()
+    }
+
+    fn fabric_id(id: &str) -> FabricId {
+        FabricId::from_string(id.to_owned()).expect("error parsing fabricId")
+    }
+
+    fn node_id(id: &str) -> NodeId {
+        NodeId::from_string(id.to_owned()).expect("error parsing nodeId")
+    }
+
+    #[test]
+    fn interface_address_ipv6_only() {
+        let config = sample_config();
+        let node = config
+            .get_fabric(&fabric_id("test"))
+            .expect("fabric exists")
+            .get_node(&node_id("node1"))
+            .expect("node exists");
+
+        assert_eq!(
+            node_interface_address(node).unwrap(),
+            "172.16.6.1".parse::<IpAddr>().unwrap()
+        );
+
+        let running_config: RunningConfig = serde_json::from_str(
+            r#"{
+              "fabrics": {
+                "ids": {
+                  "v6": {
+                    "area": "0",
+                    "type": "ospf_fabric",
+                    "id": "v6",
+                    "ip6_prefix": "fd00:6::/64"
+                  },
+                  "v6_node1": {
+                    "type": "ospf_node",
+                    "id": "v6_node1",
+                    "ip6": "fd00:6::1",
+                    "interfaces": [
+                      "name=ens19"
+                    ]
+                  }
+                }
+              }
+            }"#,
+        )
+        .expect("error parsing running-config");
+
+        let config = FabricConfig::from_section_config(SectionConfigData::from_iter(
+            running_config.fabrics.expect("no fabrics configured").ids,
+        ))
+        .expect("error converting section config to fabricconfig");
+
+        let node = config
+            .get_fabric(&fabric_id("v6"))
+            .expect("fabric exists")
+            .get_node(&node_id("node1"))
+            .expect("node exists");
+
+        assert_eq!(
+            node_interface_address(node).unwrap(),
+            "fd00:6::1".parse::<IpAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn node_fabric_loopbacks() {
+        let config = sample_config();
+
+        let loopbacks = |node| {
+            fabric_loopbacks(&config, &node_id(node))
+                .unwrap()
+                .into_iter()
+                .map(|(fabric, cidrs)| {
+                    let cidrs = cidrs.iter().map(|cidr| cidr.to_string()).collect();
+                    (fabric, cidrs)
+                })
+                .collect::<Vec<(String, Vec<String>)>>()
+        };
+
+        assert_eq!(
+            loopbacks("node1"),
+            [
+                ("test".to_string(), vec!["172.16.6.1/32".to_string()]),
+                ("test1".to_string(), vec!["172.16.7.1/32".to_string()]),
+            ]
+        );
+        assert_eq!(
+            loopbacks("node2"),
+            [("test".to_string(), vec!["172.16.6.2/32".to_string()])]
+        );
+        assert!(loopbacks("node3").is_empty());
+    }
+
+    #[test]
+    fn prune_orphans() {
+        let raw_config = r#"{
+              "fabrics": {
+                "ids": {
+                  "test": {
+                    "area": "0",
+                    "type": "ospf_fabric",
+                    "id": "test",
+                    "ip_prefix": "172.16.6.0/24"
+                  },
+                  "test_node1": {
+                    "interfaces": [
+                      "name=ens19"
+                    ],
+                    "id": "test_node1",
+                    "ip": "172.16.6.1",
+                    "type": "ospf_node"
+                  },
+                  "gone_node1": {
+                    "interfaces": [
+                      "name=ens20"
+                    ],
+                    "id": "gone_node1",
+                    "ip": "172.16.7.1",
+                    "type": "ospf_node"
+                  }
+                }
+              }
+            }
+            "#;
+
+        let running_config: RunningConfig =
+            serde_json::from_str(raw_config).expect("error parsing running-config");
+        let mut sections = SectionConfigData::from_iter(
+            running_config.fabrics.expect("no fabrics configured").ids,
+        );
+
+        assert_eq!(
+            prune_orphan_nodes(&mut sections),
+            vec!["gone_node1".to_string()]
+        );
+        assert!(prune_orphan_nodes(&mut sections).is_empty());
+
+        let config = FabricConfig::from_section_config(sections)
+            .expect("pruned config should be valid")
+            .into_inner();
+
+        assert!(
+            config
+                .get_fabric(&fabric_id("test"))
+                .expect("fabric exists")
+                .get_node(&node_id("node1"))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn delete_node_from_all_fabrics() {
+        let mut config = sample_config();
+
+        assert_eq!(
+            delete_node_everywhere(&mut config, &node_id("node1")),
+            vec![fabric_id("test"), fabric_id("test1")]
+        );
+        assert!(
+            config
+                .values()
+                .all(|entry| entry.get_node(&node_id("node1")).is_err())
+        );
+        assert!(
+            config
+                .get_fabric(&fabric_id("test"))
+                .expect("fabric exists")
+                .get_node(&node_id("node2"))
+                .is_ok()
+        );
+
+        assert!(delete_node_everywhere(&mut config, &node_id("node1")).is_empty());
+        assert!(delete_node_everywhere(&mut config, &node_id("node3")).is_empty());
+    }
+
+    #[test]
+    fn invalid_ids() {
+        let err = parse_fabric_id("invalid_fabric").expect_err("fabric id should be invalid");
+        assert!(
+            err.to_string()
+                .starts_with("invalid fabric id 'invalid_fabric': must be")
+        );
+
+        let err = parse_fabric_id("").expect_err("fabric id should be invalid");
+        assert!(err.to_string().starts_with("invalid fabric id '': must be"));
+
+        let err = parse_node_id("node_1").expect_err("node id should be invalid");
+        assert!(
+            err.to_string()
+                .starts_with("invalid node id 'node_1': must be")
+        );
+
+        let err = parse_node_id("-node1").expect_err("node id should be invalid");
+        assert!(
+            err.to_string()
+                .starts_with("invalid node id '-node1': must be")
+        );
+
+        assert_eq!(
+            parse_fabric_id("test1").expect("valid fabric id").as_str(),
+            "test1"
+        );
+        assert_eq!(
+            parse_node_id("node1").expect("valid node id").as_str(),
+            "node1"
+        );
+    }
+
+    #[test]
+    fn digest_mismatch() {
+        let mut config = sample_config();
+        let current = hex::encode(digest(&config).expect("digest can be computed"));
+
+        check_digest(&config, None).expect("no digest should always pass");
+        check_digest(&config, Some(&current)).expect("current digest should pass");
+
+        let err = check_digest(&config, Some("xyz")).expect_err("digest should be invalid");
+        assert!(err.to_string().starts_with("invalid digest:"));
+
+        config
+            .delete_fabric(&fabric_id("test1"))
+            .expect("fabric exists");
+
+        let err = check_digest(&config, Some(&current)).expect_err("digest should mismatch");
+        assert!(
+            err.to_string()
+                .starts_with("detected modified configuration")
+        );
+    }
+
+    #[test]
+    fn map_node_interfaces() {
+        let mut config = sample_config();
+
+        let mapping = HashMap::from([
+            ("ens19".to_string(), "nic0".to_string()),
+            ("ens21".to_string(), "nic2".to_string()),
+        ]);
+
+        map_interfaces(&mut config, &node_id("node1"), &mapping).expect("valid mapping");
+
+        let node = config
+            .get_fabric(&fabric_id("test"))
+            .expect("fabric exists")
+            .get_node(&node_id("node1"))
+            .expect("node exists");
+        assert_eq!(node_interface_names(node), vec!["nic0", "ens20"]);
+
+        let node = config
+            .get_fabric(&fabric_id("test1"))
+            .expect("fabric exists")
+            .get_node(&node_id("node1"))
+            .expect("node exists");
+        assert_eq!(node_interface_names(node), vec!["nic2", "ens22"]);
+
+        // other nodes must not be touched
+        let node = config
+            .get_fabric(&fabric_id("test"))
+            .expect("fabric exists")
+            .get_node(&node_id("node2"))
+            .expect("node exists");
+        assert_eq!(node_interface_names(node), vec!["ens19", "ens20"]);
+    }
+
+    #[test]
+    fn map_node_interfaces_invalid() {
+        let mut config = sample_config();
+        let digest_before = digest(&config).expect("digest can be computed");
+
+        let mapping = HashMap::from([
+            ("ens19".to_string(), "nic0".to_string()),
+            ("ens22".to_string(), "this-name-is-too-long".to_string()),
+        ]);
+
+        let err = map_interfaces(&mut config, &node_id("node1"), &mapping)
+            .expect_err("mapping should be invalid");
+        assert_eq!(err.to_string(), "could not map interface 'ens22'");
+
+        assert_eq!(
+            digest(&config).expect("digest can be computed"),
+            digest_before
+        );
+    }
+
+    #[test]
+    fn interface_in_single_fabric() {
+        let config = sample_config();
+
+        assert_eq!(
+            fabrics_for_interface(&config, &node_id("node1"), "ens19"),
+            vec![fabric_id("test")]
+        );
+        assert_eq!(
+            fabrics_for_interface(&config, &node_id("node1"), "ens22"),
+            vec![fabric_id("test1")]
+        );
+        assert!(fabrics_for_interface(&config, &node_id("node1"), "ens23").is_empty());
+        assert!(fabrics_for_interface(&config, &node_id("node3"), "ens19").is_empty());
+    }
+
+    #[test]
+    fn interface_in_conflicting_fabrics() {
+        let mut config = sample_config();
+
+        // move ens21 to ens19 on node1, which is already used by the fabric `test`
+        let ConfigNode::Ospf(node_section) = config
+            .get_fabric_mut(&fabric_id("test1"))
+            .expect("fabric exists")
+            .get_node_mut(&node_id("node1"))
+            .expect("node exists")
+        else {
+            panic!("not an ospf node");
+        };
+
+        for interface in node_section.properties_mut().interfaces_mut() {
+            if interface.name().as_str() == "ens21" {
+                interface.set_name(
+                    InterfaceName::from_string("ens19".to_owned()).expect("valid interface name"),
+                );
+            }
+        }
+
+        assert_eq!(
+            fabrics_for_interface(&config, &node_id("node1"), "ens19"),
+            vec![fabric_id("test"), fabric_id("test1")]
+        );
+    }
+
+    #[test]
+    fn interface_in_vlan_fabric() {
+        let mut config = sample_config();
+
+        // use a VLAN on top of ens19 for the fabric `test1` on node1
+        let ConfigNode::Ospf(node_section) = config
+            .get_fabric_mut(&fabric_id("test1"))
+            .expect("fabric exists")
+            .get_node_mut(&node_id("node1"))
+            .expect("node exists")
+        else {
+            panic!("not an ospf node");
+        };
+
+        for interface in node_section.properties_mut().interfaces_mut() {
+            if interface.name().as_str() == "ens21" {
+                interface.set_name(
+                    InterfaceName::from_string("ens19.100".to_owned())
+                        .expect("valid interface name"),
+                );
+            }
+        }
+
+        assert_eq!(
+            fabrics_for_interface(&config, &node_id("node1"), "ens19"),
+            vec![fabric_id("test"), fabric_id("test1")]
+        );
+        assert_eq!(
+            fabrics_for_interface(&config, &node_id("node1"), "ens19.100"),
+            vec![fabric_id("test1")]
+        );
+        assert!(fabrics_for_interface(&config, &node_id("node1"), "ens19.200").is_empty());
+        assert!(fabrics_for_interface(&config, &node_id("node1"), "ens1").is_empty());
+    }
+
+    #[test]
+    fn enabled_daemons_sorted() {
+        let openfabric: RunningConfig = serde_json::from_str(
+            r#"{
+              "fabrics": {
+                "ids": {
+                  "fab": {
+                    "type": "openfabric_fabric",
+                    "id": "fab",
+                    "ip_prefix": "172.16.8.0/24"
+                  },
+                  "fab_node1": {
+                    "type": "openfabric_node",
+                    "id": "fab_node1",
+                    "ip": "172.16.8.1",
+                    "interfaces": [
+                      "name=ens23"
+                    ]
+                  }
+                }
+              }
+            }"#,
+        )
+        .expect("error parsing running-config");
+
+        let sections = sample_config()
+            .into_valid()
+            .expect("sample config should be valid")
+            .into_section_config()
+            .into_iter()
+            .chain(openfabric.fabrics.expect("no fabrics configured").ids);
+
+        let config = FabricConfig::from_section_config(SectionConfigData::from_iter(sections))
+            .expect("error converting section config to fabricconfig")
+            .into_inner();
+
+        assert_eq!(
+            Vec::from_iter(enabled_daemons(&config, &node_id("node1"))),
+            ["fabricd", "ospfd"]
+        );
+        assert_eq!(
+            Vec::from_iter(enabled_daemons(&config, &node_id("node2"))),
+            ["ospfd"]
+        );
+        assert!(enabled_daemons(&config, &node_id("node3")).is_empty());
+
+        assert_eq!(
+            all_enabled_daemons(&config)
+                .iter()
+                .map(|(node, daemons)| (node.as_str(), Vec::from_iter(daemons.iter().copied())))
+                .collect::<Vec<_>>(),
+            [
+                ("node1", vec!["fabricd", "ospfd"]),
+                ("node2", vec!["ospfd"])
+            ]
+        );
+    }
+
+    #[test]
+    fn frr_apply_strategy() {
+        let config = sample_config();
+        let daemons = |daemons: &[&str]| Vec::from_iter(daemons.iter().map(|d| d.to_string()));
+
+        assert_eq!(
+            apply_strategy(&config, &node_id("node1"), &daemons(&["ospfd"])),
+            ApplyStrategy::Reload
+        );
+        assert_eq!(
+            apply_strategy(&config, &node_id("node1"), &daemons(&["ospfd", "ospfd"])),
+            ApplyStrategy::Reload
+        );
+        assert_eq!(
+            apply_strategy(&config, &node_id("node1"), &daemons(&["fabricd", "ospfd"])),
+            ApplyStrategy::Restart
+        );
+        assert_eq!(
+            apply_strategy(&config, &node_id("node1"), &[]),
+            ApplyStrategy::Restart
+        );
+        assert_eq!(
+            apply_strategy(&config, &node_id("node3"), &[]),
+            ApplyStrategy::Reload
+        );
+    }
+
+    #[test]
+    fn lint_findings() {
+        let mut config = sample_config();
+
+        assert_eq!(
+            lint(&config)
+                .iter()
+                .map(|finding| (finding.kind, finding.entity.as_str()))
+                .collect::<Vec<_>>(),
+            [(LintKind::SingleNodeFabric, "test1")]
+        );
+
+        // move ens21 to ens19 on node1, which is already used by the fabric `test`
+        let ConfigNode::Ospf(node_section) = config
+            .get_fabric_mut(&fabric_id("test1"))
+            .expect("fabric exists")
+            .get_node_mut(&node_id("node1"))
+            .expect("node exists")
+        else {
+            panic!("not an ospf node");
+        };
+
+        for interface in node_section.properties_mut().interfaces_mut() {
+            if interface.name().as_str() == "ens21" {
+                interface.set_name(
+                    InterfaceName::from_string("ens19".to_owned()).expect("valid interface name"),
+                );
+            }
+        }
+
+        let findings = lint(&config);
+
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.severity, finding.kind, finding.entity.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    LintSeverity::Error,
+                    LintKind::InterfaceConflict,
+                    "node1:ens19"
+                ),
+                (LintSeverity::Warning, LintKind::SingleNodeFabric, "test1"),
+            ]
+        );
+        assert_eq!(
+            findings[0].message,
+            "interface 'ens19' on node 'node1' is part of multiple fabrics: test, test1"
+        );
+
+        assert_eq!(
+            validate(&config).unwrap_err().to_string(),
+            "invalid fabric configuration: interface 'ens19' on node 'node1' is part of multiple \
+            fabrics: test, test1"
+        );
+    }
+
+    #[test]
+    fn validate_config() {
+        assert_eq!(
+            validate(&sample_config()).expect("sample config should be valid"),
+            ["fabric 'test1' has less than two nodes"]
+        );
+    }
+
+    #[test]
+    fn overlapping_fabrics() {
+        let running_config: RunningConfig = serde_json::from_str(
+            r#"{
+              "fabrics": {
+                "ids": {
+                  "test": {
+                    "area": "0",
+                    "type": "ospf_fabric",
+                    "id": "test",
+                    "ip_prefix": "172.16.6.0/24"
+                  },
+                  "test_node1": {
+                    "type": "ospf_node",
+                    "id": "test_node1",
+                    "ip": "172.16.6.1",
+                    "interfaces": [
+                      "name=ens19"
+                    ]
+                  },
+                  "test_node2": {
+                    "type": "ospf_node",
+                    "id": "test_node2",
+                    "ip": "172.16.6.2",
+                    "interfaces": [
+                      "name=ens19"
+                    ]
+                  },
+                  "test1": {
+                    "area": "1",
+                    "type": "ospf_fabric",
+                    "id": "test1",
+                    "ip_prefix": "172.16.0.0/16"
+                  },
+                  "test1_node1": {
+                    "type": "ospf_node",
+                    "id": "test1_node1",
+                    "ip": "172.16.7.1",
+                    "interfaces": [
+                      "name=ens20"
+                    ]
+                  },
+                  "test1_node3": {
+                    "type": "ospf_node",
+                    "id": "test1_node3",
+                    "ip": "172.16.6.2",
+                    "interfaces": [
+                      "name=ens20"
+                    ]
+                  }
+                }
+              }
+            }"#,
+        )
+        .expect("error parsing running-config");
+
+        let config = FabricConfig::from_section_config(SectionConfigData::from_iter(
+            running_config.fabrics.expect("no fabrics configured").ids,
+        ))
+        .expect("error converting section config to fabricconfig")
+        .into_inner();
+
+        let findings = lint(&config);
+
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.severity, finding.kind, finding.entity.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    LintSeverity::Error,
+                    LintKind::RouterIdConflict,
+                    "172.16.6.2"
+                ),
+                (
+                    LintSeverity::Warning,
+                    LintKind::OverlappingPrefix,
+                    "test, test1"
+                ),
+            ]
+        );
+        assert_eq!(
+            findings[0].message,
+            "address 172.16.6.2 is used by multiple nodes: node2 (test), node3 (test1)"
+        );
+        assert_eq!(
+            findings[1].message,
+            "the prefixes of fabric 'test' and fabric 'test1' overlap"
+        );
+    }
+
+    #[test]
+    fn diff_fabric_configs() {
+        let current = sample_config();
+
+        assert!(diff_configs(&current, &current).unwrap().is_empty());
+
+        let running_config: RunningConfig = serde_json::from_str(
+            r#"{
+              "fabrics": {
+                "ids": {
+                  "test": {
+                    "area": "2",
+                    "type": "ospf_fabric",
+                    "id": "test",
+                    "ip_prefix": "172.16.6.0/24"
+                  },
+                  "test_node1": {
+                    "interfaces": [
+                      "name=ens19",
+                      "name=ens20"
+                    ],
+                    "id": "test_node1",
+                    "ip": "172.16.6.1",
+                    "type": "ospf_node"
+                  },
+                  "test_node3": {
+                    "ip": "172.16.6.3",
+                    "type": "ospf_node",
+                    "id": "test_node3",
+                    "interfaces": [
+                      "name=ens19"
+                    ]
+                  }
+                }
+              }
+            }"#,
+        )
+        .expect("error parsing running-config");
+
+        let other = FabricConfig::from_section_config(SectionConfigData::from_iter(
+            running_config.fabrics.expect("no fabrics configured").ids,
+        ))
+        .expect("error converting section config to fabricconfig")
+        .into_inner();
+
+        let change = |change, fabric: &str, node: Option<&str>, fields: &[&str]| FabricChange {
+            change,
+            fabric: fabric.to_string(),
+            node: node.map(str::to_string),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        };
+
+        assert_eq!(
+            diff_configs(&current, &other).unwrap(),
+            [
+                change(ChangeKind::Modified, "test", None, &["area"]),
+                change(ChangeKind::Removed, "test", Some("node2"), &[]),
+                change(ChangeKind::Added, "test", Some("node3"), &[]),
+                change(ChangeKind::Removed, "test1", None, &[]),
+                change(ChangeKind::Removed, "test1", Some("node1"), &[]),
+            ]
+        );
+
+        assert_eq!(
+            diff_configs(&other, &current).unwrap()[..3],
+            [
+                change(ChangeKind::Modified, "test", None, &["area"]),
+                change(ChangeKind::Added, "test", Some("node2"), &[]),
+                change(ChangeKind::Removed, "test", Some("node3"), &[]),
+            ]
+        );
+    }
+
+    #[test]
+    fn overlapping_prefixes() {
+        assert!(prefixes_overlap(0xac100600, 24, 0xac100000, 16, 32));
+        assert!(!prefixes_overlap(0xac100600, 24, 0xac100700, 24, 32));
+        assert!(prefixes_overlap(0xac100600, 24, 0, 0, 32));
+        assert!(prefixes_overlap(1, 128, 0, 0, 128));
+        assert!(!prefixes_overlap(1, 128, 0, 128, 128));
+    }
+}
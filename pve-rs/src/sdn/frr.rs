@@ -0,0 +1,164 @@
+use similar::{Algorithm, DiffTag};
+
+/// Number of unchanged lines shown around every change in [`config_diff`].
+const DIFF_CONTEXT: usize = 3;
+
+/// Format the start and length of a hunk range as used in the unified diff format.
+fn hunk_range(start: usize, len: usize) -> String {
+    // empty ranges refer to the line before the change
+    if len == 0 {
+        format!("{start},0")
+    } else {
+        format!("{},{len}", start + 1)
+    }
+}
+
+/// Compute a line-based diff in the unified diff format between the `current` and the `desired`
+/// FRR configuration.
+///
+/// Returns an empty list if both configurations are identical. The output only depends on the
+/// input, so calling this with identical input always yields identical output.
+pub fn config_diff(current: &[String], desired: &[String]) -> Vec<String> {
+    // Myers' algorithm as implemented by `similar` only needs linear space
+    let ops = similar::capture_diff_slices(Algorithm::Myers, current, desired);
+    let hunks = similar::group_diff_ops(ops, DIFF_CONTEXT);
+
+    if hunks.is_empty() {
+        return Vec::new();
+    }
+
+    let mut diff = vec!["--- current".to_string(), "+++ desired".to_string()];
+
+    for ops in hunks {
+        let old_start = ops[0].old_range().start;
+        let new_start = ops[0].new_range().start;
+        let old_len: usize = ops.iter().map(|op| op.old_range().len()).sum();
+        let new_len: usize = ops.iter().map(|op| op.new_range().len()).sum();
+
+        diff.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_len),
+            hunk_range(new_start, new_len),
+        ));
+
+        for op in ops {
+            let (tag, old_range, new_range) = op.as_tag_tuple();
+
+            if tag == DiffTag::Equal {
+                diff.extend(current[old_range].iter().map(|line| format!(" {line}")));
+                continue;
+            }
+
+            // replacements are shown as deletions followed by insertions
+            diff.extend(current[old_range].iter().map(|line| format!("-{line}")));
+            diff.extend(desired[new_range].iter().map(|line| format!("+{line}")));
+        }
+    }
+
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn identical_config() {
+        let config = lines(&[
+            "router openfabric test",
+            " net 49.0001.1921.6800.2008.00",
+            "exit",
+        ]);
+
+        assert!(config_diff(&config, &config).is_empty());
+        assert!(config_diff(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn changed_config() {
+        let current = lines(&[
+            "frr defaults datacenter",
+            "!",
+            "router openfabric test",
+            " net 49.0001.1921.6800.2008.00",
+            "exit",
+            "!",
+            "interface ens19",
+            " ip router openfabric test",
+            "exit",
+            "!",
+            "interface ens20",
+            " ip router openfabric test",
+            "exit",
+        ]);
+        let desired = lines(&[
+            "frr defaults datacenter",
+            "!",
+            "router openfabric test",
+            " net 49.0001.1921.6800.2008.00",
+            "exit",
+            "!",
+            "interface ens19",
+            " ip router openfabric test",
+            " openfabric hello-interval 1",
+            "exit",
+            "!",
+            "interface ens21",
+            " ip router openfabric test",
+            "exit",
+        ]);
+
+        let expected = lines(&[
+            "--- current",
+            "+++ desired",
+            "@@ -6,8 +6,9 @@",
+            " !",
+            " interface ens19",
+            "  ip router openfabric test",
+            "+ openfabric hello-interval 1",
+            " exit",
+            " !",
+            "-interface ens20",
+            "+interface ens21",
+            "  ip router openfabric test",
+            " exit",
+        ]);
+
+        assert_eq!(config_diff(&current, &desired), expected);
+        assert_eq!(config_diff(&current, &desired), expected);
+    }
+
+    #[test]
+    fn separate_hunks() {
+        let current = lines(&["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"]);
+        let desired = lines(&["x", "b", "c", "d", "e", "f", "g", "h", "i"]);
+
+        assert_eq!(
+            config_diff(&current, &desired),
+            lines(&[
+                "--- current",
+                "+++ desired",
+                "@@ -1,4 +1,4 @@",
+                "-a",
+                "+x",
+                " b",
+                " c",
+                " d",
+                "@@ -7,4 +7,3 @@",
+                " g",
+                " h",
+                " i",
+                "-j",
+            ])
+        );
+
+        assert_eq!(
+            config_diff(&[], &lines(&["a"])),
+            lines(&["--- current", "+++ desired", "@@ -0,0 +1,1 @@", "+a"])
+        );
+    }
+}
@@ -1,3 +1,8 @@
+/// This module contains helpers operating on the fabric configuration, which are used by the
+/// `PVE::RS::SDN::Fabrics` bindings
+pub mod fabric;
+/// This module contains helpers for working with the generated FRR configuration
+pub mod frr;
 /// This module contains status-related structs that represent Routes, Neighbors and general Status
 /// for all Protocols and SDN Objects
 pub mod status;
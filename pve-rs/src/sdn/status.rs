@@ -1,18 +1,21 @@
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::net::IpAddr;
 
+use anyhow::Context;
 use proxmox_network_types::ip_address::Cidr;
 use proxmox_network_types::mac_address::MacAddress;
 use serde::{Deserialize, Serialize};
 
 use proxmox_frr::de::{self};
+use proxmox_http_error::HttpError;
+use proxmox_section_config::typed::SectionConfigData;
 use proxmox_ve_config::sdn::fabric::section_config::protocol::ospf::{
     OspfNodeProperties, OspfProperties,
 };
 use proxmox_ve_config::{
     common::valid::Valid,
     sdn::fabric::{
-        Entry, FabricConfig,
+        Entry, FabricConfig, FabricEntry,
         section_config::{Section, fabric::FabricId, node::Node as ConfigNode, node::NodeId},
     },
 };
@@ -27,9 +30,10 @@ pub enum InterfaceState {
     Down,
 }
 
-mod ospf {
-    use proxmox_frr::de;
-    use serde::Serialize;
+pub mod ospf {
+    use std::collections::BTreeMap;
+
+    use serde::{Deserialize, Serialize};
 
     /// The status of a neighbor.
     ///
@@ -39,6 +43,12 @@ mod ospf {
         pub neighbor: String,
         pub status: String,
         pub uptime: String,
+        /// The role of the neighbor on broadcast segments (`DR`, `Backup` or `DROther`).
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub role: Option<String>,
+        /// The time until the neighbor is considered down if no more hello packets arrive.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub dead_time: Option<String>,
     }
 
     /// The status of a fabric interface
@@ -50,7 +60,45 @@ mod ospf {
         pub name: String,
         pub state: super::InterfaceState,
         #[serde(rename = "type")]
-        pub ty: de::ospf::NetworkType,
+        pub ty: NetworkType,
+    }
+
+    /// The OSPF network type of an interface, as reported by FRR.
+    ///
+    /// Types unknown to us are passed through as they are.
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+    pub enum NetworkType {
+        #[serde(rename = "BROADCAST")]
+        Broadcast,
+        #[serde(rename = "POINTOPOINT")]
+        PointToPoint,
+        #[serde(rename = "POINTOMULTIPOINT")]
+        PointToMultipoint,
+        #[serde(rename = "NBMA")]
+        Nbma,
+        #[serde(rename = "VIRTUALLINK")]
+        VirtualLink,
+        #[serde(rename = "LOOPBACK")]
+        Loopback,
+        #[serde(untagged)]
+        Other(String),
+    }
+
+    /// The parsed output of `show ip ospf interface json`.
+    ///
+    /// This only contains the fields we need, so that e.g. network types we do not know do not
+    /// fail parsing the whole output.
+    #[derive(Clone, Debug, Default, Deserialize)]
+    pub struct Interfaces {
+        pub interfaces: BTreeMap<String, Interface>,
+    }
+
+    /// A single interface in the output of `show ip ospf interface json`.
+    #[derive(Clone, Debug, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Interface {
+        pub if_up: bool,
+        pub network_type: NetworkType,
     }
 }
 mod openfabric {
@@ -65,6 +113,9 @@ mod openfabric {
         pub neighbor: String,
         pub status: de::openfabric::AdjacencyState,
         pub uptime: String,
+        /// The time until the adjacency expires if no more hello packets arrive.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub dead_time: Option<String>,
     }
 
     /// The status of a fabric interface
@@ -187,6 +238,45 @@ pub struct FabricsRunningConfig {
     pub ids: BTreeMap<String, Section>,
 }
 
+/// Parse the fabrics section of the raw running-config `raw_config`.
+///
+/// This will return a valid FabricConfig. If the running-config does not contain any fabric,
+/// `Ok(None)` is returned.
+pub fn parse_fabrics_config(
+    raw_config: &str,
+) -> Result<Option<Valid<FabricConfig>>, anyhow::Error> {
+    let running_config: RunningConfig =
+        serde_json::from_str(raw_config).context("error parsing running-config")?;
+    let Some(fabrics) = running_config.fabrics else {
+        return Ok(None);
+    };
+    let section_config = SectionConfigData::from_iter(fabrics.ids);
+    Some(
+        FabricConfig::from_section_config(section_config)
+            .context("error converting section config to fabricconfig"),
+    )
+    .transpose()
+}
+
+/// The error returned by the fabric status functions if the fabric `fabric_id` does not exist.
+pub fn fabric_not_found(fabric_id: &FabricId) -> HttpError {
+    HttpError::new(
+        http::StatusCode::NOT_FOUND,
+        format!("fabric '{fabric_id}' does not exist"),
+    )
+}
+
+/// Get the fabric `fabric_id` from `config`, failing with a `NOT_FOUND` error if it does not
+/// exist.
+pub fn fabric_entry<'a>(
+    config: &'a FabricConfig,
+    fabric_id: &FabricId,
+) -> Result<&'a FabricEntry, HttpError> {
+    config
+        .get_fabric(fabric_id)
+        .map_err(|_| fabric_not_found(fabric_id))
+}
+
 /// Converts the parsed `show ip route x` frr route output into a list of common [`RouteStatus`]
 /// structs.
 ///
@@ -294,6 +384,7 @@ pub fn get_neighbors_openfabric(
                 neighbor: adj.clone(),
                 status: state,
                 uptime: interface.last_ago.clone(),
+                dead_time: circuit.expires_in.clone(),
             });
         }
     }
@@ -335,6 +426,8 @@ pub fn get_neighbors_ospf(
                     neighbor: neighbor.interface_address.clone(),
                     status: neighbor.neighbor_state.clone(),
                     uptime: neighbor.up_time.clone(),
+                    role: Some(neighbor.role.clone()),
+                    dead_time: Some(neighbor.dead_time.clone()),
                 });
             }
         }
@@ -375,7 +468,7 @@ pub fn get_interfaces_openfabric(
 pub fn get_interfaces_ospf(
     fabric_id: FabricId,
     fabric: &Entry<OspfProperties, OspfNodeProperties>,
-    neighbors: de::ospf::Interfaces,
+    neighbors: ospf::Interfaces,
     hostname: &str,
 ) -> Result<Vec<ospf::InterfaceStatus>, anyhow::Error> {
     let mut stats: Vec<ospf::InterfaceStatus> = Vec::new();
@@ -399,7 +492,7 @@ pub fn get_interfaces_ospf(
                     } else {
                         InterfaceState::Down
                     },
-                    ty: interface.network_type,
+                    ty: interface.network_type.clone(),
                 });
             }
         }
@@ -491,6 +584,8 @@ pub struct L3VPNRoute {
     protocol: String,
     metric: i32,
     nexthops: Vec<IpAddrOrInterfaceName>,
+    /// Whether the route is an unreachable/reject (blackhole) route
+    blackhole: bool,
 }
 
 /// All L3VPN routes of a zone
@@ -504,10 +599,16 @@ pub fn get_l3vpn_routes(vrf: &str, routes: de::Routes) -> Result<L3VPNRoutes, an
     for (prefix, routes) in routes.0 {
         for route in routes {
             if route.vrf_name == vrf && route.installed.unwrap_or_default() {
+                let blackhole = route
+                    .nexthops
+                    .iter()
+                    .any(|nh| nh.unreachable.unwrap_or_default() || nh.reject.unwrap_or_default());
+
                 result.push(L3VPNRoute {
                     ip: prefix,
                     metric: route.metric,
                     protocol: route.protocol,
+                    blackhole,
                     nexthops: route
                         .nexthops
                         .into_iter()
@@ -534,41 +635,102 @@ pub struct L2VPNRoute {
     mac: MacAddress,
     ip: IpAddr,
     nexthop: IpAddr,
+    /// The VNI the route was learned on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vni: Option<u32>,
+    /// Hostname of the router that originated the route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    router: Option<String>,
+    /// The vnet the route belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vnet: Option<String>,
 }
 
 /// All L2VPN routes of a specific vnet
 #[derive(Debug, Serialize, PartialEq, Eq)]
 pub struct L2VPNRoutes(Vec<L2VPNRoute>);
 
+impl L2VPNRoutes {
+    /// Set the vnet of every route with a VNI, using the `vnets` mapping VNIs to vnet names.
+    pub fn set_vnets(&mut self, vnets: &HashMap<u32, String>) {
+        for route in &mut self.0 {
+            route.vnet = route.vni.and_then(|vni| vnets.get(&vni).cloned());
+        }
+    }
+}
+
+/// A page of the L2VPN routes of a vnet
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct L2VPNRoutePage {
+    /// The total number of routes
+    pub total: usize,
+    /// The routes on this page
+    pub routes: L2VPNRoutes,
+}
+
 /// Convert the parsed frr evpn struct into an array of structured L2VPN routes
-pub fn get_l2vpn_routes(routes: de::evpn::Routes) -> Result<L2VPNRoutes, anyhow::Error> {
+///
+/// The type 2 route keys do not contain the VNI, so it has to be passed in by the caller (it is
+/// the same one that was used when querying FRR).
+pub fn get_l2vpn_routes(
+    routes: de::evpn::Routes,
+    vni: Option<u32>,
+) -> Result<L2VPNRoutes, anyhow::Error> {
+    get_l2vpn_routes_page(routes, vni, 0, None).map(|page| page.routes)
+}
+
+/// Convert the parsed frr evpn struct into a page of structured L2VPN routes
+///
+/// Only the `limit` routes starting at `offset` are converted, but all routes are counted. The
+/// routes are sorted by their prefix, so paging through them is consistent across calls as long
+/// as the routes do not change.
+pub fn get_l2vpn_routes_page(
+    routes: de::evpn::Routes,
+    vni: Option<u32>,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<L2VPNRoutePage, anyhow::Error> {
+    let end = limit.map_or(usize::MAX, |limit| offset.saturating_add(limit));
+
+    // sort explicitly, so the pages do not depend on the order FRR or the map returns them in
+    let mut entries: Vec<_> = routes.0.iter().collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let mut total = 0;
     let mut result = Vec::new();
-    for route in routes.0.values() {
+    for (_prefix, route) in entries {
         if let de::evpn::Entry::Route(r) = route {
             r.paths.iter().flatten().for_each(|path| {
                 if path.bestpath.unwrap_or_default() {
                     if let (Some(mac), Some(ip), Some(nh)) =
                         (path.mac, path.ip, path.nexthops.first())
                     {
-                        result.push(L2VPNRoute {
-                            mac,
-                            ip,
-                            nexthop: nh.ip,
-                        });
+                        if (offset..end).contains(&total) {
+                            result.push(L2VPNRoute {
+                                mac,
+                                ip,
+                                nexthop: nh.ip,
+                                vni,
+                                router: nh.hostname.clone(),
+                                vnet: None,
+                            });
+                        }
+                        total += 1;
                     }
                 }
             });
         }
     }
 
-    Ok(L2VPNRoutes(result))
+    Ok(L2VPNRoutePage {
+        total,
+        routes: L2VPNRoutes(result),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use proxmox_section_config::typed::SectionConfigData;
-    use proxmox_ve_config::sdn::fabric::FabricConfig;
 
     fn sample_two_fabric_config() -> Valid<FabricConfig> {
         let raw_config = r#"{
@@ -645,13 +807,9 @@ mod tests {
             }
             "#;
 
-        let running_config: RunningConfig =
-            serde_json::from_str(raw_config).expect("error parsing running-config");
-        let section_config = SectionConfigData::from_iter(
-            running_config.fabrics.expect("no fabrics configured").ids,
-        );
-        FabricConfig::from_section_config(section_config)
-            .expect("error converting section config to fabricconfig")
+        parse_fabrics_config(raw_config)
+            .expect("error parsing running-config")
+            .expect("no fabrics configured")
     }
 
     fn sample_one_fabric_config() -> Valid<FabricConfig> {
@@ -695,13 +853,38 @@ mod tests {
               }
             }
             "#;
-        let running_config: RunningConfig =
-            serde_json::from_str(raw_config).expect("error parsing running-config");
-        let section_config = SectionConfigData::from_iter(
-            running_config.fabrics.expect("no fabrics configured").ids,
+        parse_fabrics_config(raw_config)
+            .expect("error parsing running-config")
+            .expect("no fabrics configured")
+    }
+
+    #[test]
+    fn running_config_without_fabrics() {
+        assert!(parse_fabrics_config("{}").unwrap().is_none());
+        assert!(
+            parse_fabrics_config(r#"{"fabrics": {"ids": {}}}"#)
+                .unwrap()
+                .is_some_and(|config| config.values().next().is_none())
         );
-        FabricConfig::from_section_config(section_config)
-            .expect("error converting section config to fabricconfig")
+
+        let err = parse_fabrics_config("{").unwrap_err();
+        assert_eq!(err.to_string(), "error parsing running-config");
+    }
+
+    #[test]
+    fn unknown_fabric() {
+        let config = sample_two_fabric_config();
+
+        let fabric_id = FabricId::from_string("test1".to_owned()).expect("error parsing fabricId");
+        assert!(matches!(
+            fabric_entry(&config, &fabric_id),
+            Ok(FabricEntry::Ospf(_))
+        ));
+
+        let fabric_id = FabricId::from_string("gone".to_owned()).expect("error parsing fabricId");
+        let err = fabric_entry(&config, &fabric_id).unwrap_err();
+        assert_eq!(err.code, http::StatusCode::NOT_FOUND);
+        assert_eq!(err.message, "fabric 'gone' does not exist");
     }
 
     mod openfabric {
@@ -762,6 +945,7 @@ mod tests {
                 neighbor: "node2".to_owned(),
                 status: de::openfabric::AdjacencyState::Up,
                 uptime: "11m5s".to_owned(),
+                dead_time: Some("29s".to_owned()),
             }];
             assert_eq!(reference, output);
         }
@@ -844,11 +1028,13 @@ mod tests {
                     neighbor: "node1".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "25m26s".to_owned(),
+                    dead_time: Some("28s".to_owned()),
                 },
                 openfabric::NeighborStatus {
                     neighbor: "node3".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "25m21s".to_owned(),
+                    dead_time: Some("29s".to_owned()),
                 },
             ];
             assert_eq!(reference, output);
@@ -984,11 +1170,13 @@ mod tests {
                     neighbor: "node1".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "33m39s".to_owned(),
+                    dead_time: Some("29s".to_owned()),
                 },
                 openfabric::NeighborStatus {
                     neighbor: "node3".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "33m34s".to_owned(),
+                    dead_time: Some("29s".to_owned()),
                 },
             ];
             assert_eq!(reference_node1, output_node1);
@@ -1004,11 +1192,13 @@ mod tests {
                     neighbor: "node1".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "56s".to_owned(),
+                    dead_time: Some("28s".to_owned()),
                 },
                 openfabric::NeighborStatus {
                     neighbor: "node3".to_owned(),
                     status: de::openfabric::AdjacencyState::Up,
                     uptime: "1m2s".to_owned(),
+                    dead_time: Some("28s".to_owned()),
                 },
             ];
             assert_eq!(reference_node2, output_node2);
@@ -1090,6 +1280,100 @@ mod tests {
             assert_eq!(reference, output);
         }
 
+        #[test]
+        fn interfaces_network_types() {
+            let json_output = r#"
+            {
+              "interfaces":{
+                "dummy_test":{
+                  "ifUp":true,
+                  "ifIndex":10,
+                  "ospfEnabled":true,
+                  "area":"0.0.0.0",
+                  "networkType":"BROADCAST",
+                  "state":"DR"
+                },
+                "ens19":{
+                  "ifUp":true,
+                  "ifIndex":3,
+                  "ospfEnabled":true,
+                  "area":"0.0.0.0",
+                  "networkType":"POINTOMULTIPOINT",
+                  "state":"Point-To-Point",
+                  "nbrCount":2,
+                  "nbrAdjacentCount":2
+                },
+                "ens20":{
+                  "ifUp":false,
+                  "ifIndex":4,
+                  "ospfEnabled":true,
+                  "area":"0.0.0.0",
+                  "networkType":"SOMETHING-NEW",
+                  "state":"Down"
+                },
+                "ens21":{
+                  "ifUp":true,
+                  "ifIndex":5,
+                  "ospfEnabled":true,
+                  "area":"0.0.0.0",
+                  "networkType":"NBMA",
+                  "state":"DROther"
+                }
+              }
+            }
+            "#;
+
+            let interfaces: ospf::Interfaces =
+                serde_json::from_str(json_output).expect("error parsing json output");
+            assert_eq!(
+                interfaces.interfaces["ens21"].network_type,
+                ospf::NetworkType::Nbma
+            );
+
+            let fabric_config = sample_one_fabric_config();
+
+            let fabric_id =
+                FabricId::from_string("test".to_owned()).expect("error parsing fabricId");
+            let fabric = fabric_config
+                .get_fabric(&fabric_id)
+                .expect("can't find fabric in config");
+
+            let FabricEntry::Ospf(fabric) = fabric else {
+                panic!("not a ospf fabric");
+            };
+
+            let output = get_interfaces_ospf(fabric_id, fabric, interfaces, "node2")
+                .expect("error converting vtysh output");
+
+            let reference = vec![
+                ospf::InterfaceStatus {
+                    name: "dummy_test".to_owned(),
+                    state: InterfaceState::Up,
+                    ty: ospf::NetworkType::Broadcast,
+                },
+                ospf::InterfaceStatus {
+                    name: "ens19".to_owned(),
+                    state: InterfaceState::Up,
+                    ty: ospf::NetworkType::PointToMultipoint,
+                },
+                ospf::InterfaceStatus {
+                    name: "ens20".to_owned(),
+                    state: InterfaceState::Down,
+                    ty: ospf::NetworkType::Other("SOMETHING-NEW".to_owned()),
+                },
+            ];
+            assert_eq!(reference, output);
+
+            assert_eq!(
+                serde_json::to_value(&output).expect("error serializing status"),
+                serde_json::json!([
+                    { "name": "dummy_test", "state": "up", "type": "BROADCAST" },
+                    { "name": "ens19", "state": "up", "type": "POINTOMULTIPOINT" },
+                    { "name": "ens20", "state": "down", "type": "SOMETHING-NEW" },
+                ])
+            );
+        }
+
         #[test]
         fn interfaces_multiple_areas() {
             let json_output = r#"
@@ -1305,11 +1589,15 @@ mod tests {
                     neighbor: "172.16.6.1".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "1m04s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("37.331s".to_owned()),
                 },
                 ospf::NeighborStatus {
                     neighbor: "172.16.6.3".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "1m07s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("32.384s".to_owned()),
                 },
             ];
             assert_eq!(reference, output);
@@ -1414,11 +1702,15 @@ mod tests {
                     neighbor: "172.16.6.1".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "8m29s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("32.912s".to_owned()),
                 },
                 ospf::NeighborStatus {
                     neighbor: "172.16.6.3".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "8m32s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("37.968s".to_owned()),
                 },
             ];
             assert_eq!(reference_fabric1, output_fabric1);
@@ -1441,11 +1733,15 @@ mod tests {
                     neighbor: "172.16.7.1".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "1m28s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("31.531s".to_owned()),
                 },
                 ospf::NeighborStatus {
                     neighbor: "172.16.7.3".to_owned(),
                     status: "Full/-".to_owned(),
                     uptime: "1m32s".to_owned(),
+                    role: Some("DROther".to_owned()),
+                    dead_time: Some("37.384s".to_owned()),
                 },
             ];
             assert_eq!(reference_fabric2, output_fabric2);
@@ -1551,8 +1847,8 @@ mod tests {
             }
             "#;
 
-            let interfaces: de::ospf::Interfaces = if json_output.is_empty() {
-                de::ospf::Interfaces::default()
+            let interfaces: ospf::Interfaces = if json_output.is_empty() {
+                ospf::Interfaces::default()
             } else {
                 serde_json::from_str(json_output).expect("error parsing json output")
             };
@@ -1576,17 +1872,17 @@ mod tests {
                 ospf::InterfaceStatus {
                     name: "dummy_test".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::Broadcast,
+                    ty: ospf::NetworkType::Broadcast,
                 },
                 ospf::InterfaceStatus {
                     name: "ens19".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
                 ospf::InterfaceStatus {
                     name: "ens20".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
             ];
             assert_eq!(reference, output);
@@ -1783,8 +2079,8 @@ mod tests {
             }
             "#;
 
-            let interfaces: de::ospf::Interfaces = if json_output.is_empty() {
-                de::ospf::Interfaces::default()
+            let interfaces: ospf::Interfaces = if json_output.is_empty() {
+                ospf::Interfaces::default()
             } else {
                 serde_json::from_str(json_output).expect("error parsing json output")
             };
@@ -1809,17 +2105,17 @@ mod tests {
                 ospf::InterfaceStatus {
                     name: "dummy_test".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::Broadcast,
+                    ty: ospf::NetworkType::Broadcast,
                 },
                 ospf::InterfaceStatus {
                     name: "ens19".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
                 ospf::InterfaceStatus {
                     name: "ens20".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
             ];
             assert_eq!(reference_fabric1, output_fabric1);
@@ -1841,17 +2137,17 @@ mod tests {
                 ospf::InterfaceStatus {
                     name: "dummy_test1".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::Broadcast,
+                    ty: ospf::NetworkType::Broadcast,
                 },
                 ospf::InterfaceStatus {
                     name: "ens21".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
                 ospf::InterfaceStatus {
                     name: "ens22".to_owned(),
                     state: InterfaceState::Up,
-                    ty: de::ospf::NetworkType::PointToPoint,
+                    ty: ospf::NetworkType::PointToPoint,
                 },
             ];
             assert_eq!(reference_fabric2, output_fabric2);
@@ -2515,24 +2811,28 @@ mod tests {
                     protocol: "kernel".to_owned(),
                     metric: 8192,
                     nexthops: vec![],
+                    blackhole: true,
                 },
                 L3VPNRoute {
                     ip: Cidr::from_str("172.16.100.0/24").expect("valid cidr"),
                     protocol: "connected".to_owned(),
                     metric: 0,
                     nexthops: vec![IpAddrOrInterfaceName::InterfaceName("test".to_owned())],
+                    blackhole: false,
                 },
                 L3VPNRoute {
                     ip: Cidr::from_str("172.16.100.0/24").expect("valid cidr"),
                     protocol: "kernel".to_owned(),
                     metric: 0,
                     nexthops: vec![IpAddrOrInterfaceName::InterfaceName("test".to_owned())],
+                    blackhole: false,
                 },
                 L3VPNRoute {
                     ip: Cidr::from_str("172.16.100.1/32").expect("valid cidr"),
                     protocol: "local".to_owned(),
                     metric: 0,
                     nexthops: vec![IpAddrOrInterfaceName::InterfaceName("test".to_owned())],
+                    blackhole: false,
                 },
                 L3VPNRoute {
                     ip: Cidr::from_str("172.16.100.2/32").expect("valid cidr"),
@@ -2541,6 +2841,7 @@ mod tests {
                     nexthops: vec![IpAddrOrInterfaceName::IpAddr(IpAddr::V4(
                         Ipv4Addr::from_str("172.16.6.1").expect("valid ip addr"),
                     ))],
+                    blackhole: false,
                 },
             ]);
             assert_eq!(reference, output);
@@ -2634,7 +2935,7 @@ mod tests {
                 serde_json::from_str(json_output).expect("error parsing json output")
             };
 
-            let output = get_l2vpn_routes(routes).expect("error converting vtysh output");
+            let output = get_l2vpn_routes(routes, None).expect("error converting vtysh output");
 
             let reference = L2VPNRoutes(vec![
                 L2VPNRoute {
@@ -2645,6 +2946,9 @@ mod tests {
                     nexthop: IpAddr::V4(
                         Ipv4Addr::from_str("172.16.6.1").expect("valid ip address"),
                     ),
+                    vni: None,
+                    router: Some("node1".to_owned()),
+                    vnet: None,
                 },
                 L2VPNRoute {
                     mac: MacAddress::from_str("bc:24:11:02:45:ae").expect("valid mac address"),
@@ -2652,9 +2956,121 @@ mod tests {
                     nexthop: IpAddr::V4(
                         Ipv4Addr::from_str("172.16.6.1").expect("valid ip address"),
                     ),
+                    vni: None,
+                    router: Some("node1".to_owned()),
+                    vnet: None,
                 },
             ]);
             assert_eq!(reference, output);
+
+            let page = |offset, limit| {
+                let routes: de::evpn::Routes =
+                    serde_json::from_str(json_output).expect("error parsing json output");
+                get_l2vpn_routes_page(routes, None, offset, limit)
+                    .expect("error converting vtysh output")
+            };
+
+            let L2VPNRoutes(mut reference) = reference;
+            let second = reference.pop().expect("two routes");
+            let first = reference.pop().expect("two routes");
+
+            assert_eq!(
+                page(0, Some(1)),
+                L2VPNRoutePage {
+                    total: 2,
+                    routes: L2VPNRoutes(vec![first]),
+                }
+            );
+            assert_eq!(
+                page(1, None),
+                L2VPNRoutePage {
+                    total: 2,
+                    routes: L2VPNRoutes(vec![second]),
+                }
+            );
+            assert_eq!(
+                page(2, Some(10)),
+                L2VPNRoutePage {
+                    total: 2,
+                    routes: L2VPNRoutes(Vec::new()),
+                }
+            );
+            assert_eq!(page(1, Some(0)).routes, L2VPNRoutes(Vec::new()));
+        }
+
+        #[test]
+        fn routes_l2vpn_vni() {
+            let json_output = r#"
+                {
+                  "[2]:[0]:[48]:[00:00:00:00:00:00]:[32]:[172.16.100.2]":{
+                    "prefix":"[2]:[0]:[48]:[00:00:00:00:00:00]:[32]:[172.16.100.2]",
+                    "prefixLen":352,
+                    "paths":[
+                      [
+                        {
+                          "valid":true,
+                          "bestpath":true,
+                          "selectionReason":"First path received",
+                          "pathFrom":"internal",
+                          "routeType":2,
+                          "ethTag":0,
+                          "macLen":48,
+                          "mac":"bc:24:11:02:45:ae",
+                          "ipLen":32,
+                          "ip":"172.16.100.2",
+                          "locPrf":100,
+                          "weight":0,
+                          "peerId":"172.16.6.1",
+                          "path":"",
+                          "origin":"IGP",
+                          "extendedCommunity":{
+                            "string":"RT:65000:100 RT:65000:101 ET:8 Rmac:e2:44:0e:6f:78:72"
+                          },
+                          "nexthops":[
+                            {
+                              "ip":"172.16.6.1",
+                              "hostname":"node1",
+                              "afi":"ipv4",
+                              "used":true
+                            }
+                          ]
+                        }
+                      ]
+                    ]
+                  },
+                  "numPrefix":1,
+                  "numPaths":1
+                }
+
+            "#;
+
+            let routes: de::evpn::Routes =
+                serde_json::from_str(json_output).expect("error parsing json output");
+
+            let output =
+                get_l2vpn_routes(routes, Some(100)).expect("error converting vtysh output");
+
+            let reference = L2VPNRoutes(vec![L2VPNRoute {
+                mac: MacAddress::from_str("bc:24:11:02:45:ae").expect("valid mac address"),
+                ip: IpAddr::V4(Ipv4Addr::from_str("172.16.100.2").expect("valid ip address")),
+                nexthop: IpAddr::V4(Ipv4Addr::from_str("172.16.6.1").expect("valid ip address")),
+                vni: Some(100),
+                router: Some("node1".to_owned()),
+                vnet: None,
+            }]);
+            assert_eq!(reference, output);
+
+            let serialized = serde_json::to_value(&output).expect("error serializing routes");
+            assert_eq!(serialized[0]["vni"], 100);
+            assert_eq!(serialized[0]["router"], "node1");
+            assert!(serialized[0].get("vnet").is_none());
+
+            let mut output = output;
+            output.set_vnets(&HashMap::from([(100, "vnet100".to_owned())]));
+            assert_eq!(output.0[0].vnet.as_deref(), Some("vnet100"));
+
+            output.set_vnets(&HashMap::from([(200, "vnet200".to_owned())]));
+            assert_eq!(output.0[0].vnet, None);
         }
     }
 }
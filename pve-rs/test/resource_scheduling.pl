@@ -179,10 +179,80 @@ sub test_balance_small_memory_difference {
     }
 }
 
+sub test_rank_nodes {
+    my $static = PVE::RS::ResourceScheduling::Static->new();
+    $static->add_node("A", 10, 100_000_000_000);
+    $static->add_node("B", 20, 200_000_000_000);
+
+    my $service = {
+	maxcpu => 4,
+	maxmem => 20_000_000_000,
+    };
+
+    my $ranking = $static->rank_nodes($service);
+    is(scalar($ranking->@*), 2, 'all nodes ranked');
+    is($ranking->[0]->{node}, "B", 'first should be B');
+    is($ranking->[1]->{node}, "A", 'second should be A');
+    ok($ranking->[0]->{score} >= $ranking->[1]->{score}, 'ranking sorted by score');
+
+    my @nodes = score_nodes($static, $service);
+    is_deeply([map { $_->{node} } $ranking->@*], \@nodes, 'same order as scoring');
+
+    my $again = $static->rank_nodes($service);
+    is_deeply($again, $ranking, 'ranking does not change the usage');
+}
+
+sub test_rank_nodes_constraints {
+    my $static = PVE::RS::ResourceScheduling::Static->new();
+    $static->add_node("A", 10, 100_000_000_000);
+    $static->add_node("B", 20, 200_000_000_000);
+    $static->add_node("C", 10, 100_000_000_000);
+
+    $static->add_service("vm:100", {
+	stats => { maxcpu => 1, maxmem => 1_000_000_000 },
+	running => 1,
+	'current-node' => "B",
+    });
+
+    my $service = {
+	maxcpu => 4,
+	maxmem => 20_000_000_000,
+    };
+
+    my $ranking = $static->rank_nodes($service);
+    is($ranking->[0]->{node}, "B", 'B is the best node without constraints');
+
+    $ranking = $static->rank_nodes($service, { 'anti-affinity' => ["vm:100"] });
+    is_deeply([sort map { $_->{node} } $ranking->@*], ["A", "C"], 'B excluded by anti-affinity');
+
+    $ranking = $static->rank_nodes($service, {
+	'anti-affinity' => ["vm:100"],
+	'preferred-nodes' => ["C"],
+	'preference-bonus' => 1,
+    });
+    is($ranking->[0]->{node}, "C", 'preferred node ranked first');
+
+    $static->add_service("vm:101", {
+	stats => { maxcpu => 1, maxmem => 1_000_000_000 },
+	running => 1,
+	'current-node' => "A",
+    });
+    $static->add_service("vm:102", {
+	stats => { maxcpu => 1, maxmem => 1_000_000_000 },
+	running => 1,
+	'current-node' => "C",
+    });
+
+    eval { $static->rank_nodes($service, { 'anti-affinity' => ["vm:100", "vm:101", "vm:102"] }) };
+    like($@, qr/no node satisfies the placement constraints/, 'no feasible node');
+}
+
 test_basic();
 test_balance();
 test_balance_removal();
 test_overcommitted();
+test_rank_nodes();
+test_rank_nodes_constraints();
 test_balance_small_memory_difference(1);
 test_balance_small_memory_difference(0);
 